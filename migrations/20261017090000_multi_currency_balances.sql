@@ -0,0 +1,44 @@
+-- Add migration script here
+-- Balances are now kept per currency. Existing balances are all in the native currency of the chain the bot runs on
+-- (VRSC or VRSCTEST). The bot sets verusbot.native_currency_id to its id before it runs the migrations.
+DO $$
+BEGIN
+    IF coalesce(current_setting('verusbot.native_currency_id', true), '') = '' THEN
+        RAISE EXCEPTION 'verusbot.native_currency_id is not set, run the migrations by starting the bot';
+    END IF;
+END;
+$$;
+
+CREATE TABLE
+    public.balances (
+        discord_id bigint NOT NULL,
+        currency_id TEXT NOT NULL,
+        balance bigint NOT NULL DEFAULT 0,
+        created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+        updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+        PRIMARY KEY (discord_id, currency_id),
+        CONSTRAINT non_negative_balance CHECK (balance >= 0)
+    ) TABLESPACE pg_default;
+
+CREATE TRIGGER SET_UPDATED_TIMESTAMP 
+	BEFORE
+	UPDATE
+	    ON public.balances FOR EACH ROW
+	EXECUTE
+	    PROCEDURE trigger_set_timestamp();
+
+INSERT INTO balances (discord_id, currency_id, balance)
+SELECT discord_id, current_setting('verusbot.native_currency_id'), balance
+FROM balance_vrsc
+WHERE discord_id IS NOT NULL;
+
+DROP TABLE balance_vrsc;
+
+ALTER TABLE tips_vrsc ADD COLUMN currency_id TEXT NOT NULL DEFAULT current_setting('verusbot.native_currency_id');
+ALTER TABLE tips_vrsc ALTER COLUMN currency_id DROP DEFAULT;
+
+ALTER TABLE transactions_vrsc ADD COLUMN currency_id TEXT NOT NULL DEFAULT current_setting('verusbot.native_currency_id');
+ALTER TABLE transactions_vrsc ALTER COLUMN currency_id DROP DEFAULT;
+
+ALTER TABLE reactdrops ADD COLUMN currency_id TEXT NOT NULL DEFAULT current_setting('verusbot.native_currency_id');
+ALTER TABLE reactdrops ALTER COLUMN currency_id DROP DEFAULT;
@@ -0,0 +1,47 @@
+-- Add migration script here
+-- Append-only journal of every balance change. The entries of one transfer (same transfer_id) sum up to zero per currency.
+-- account is one of 'user', 'chain', 'fees', 'outbox', 'escrow' or 'adjustment'. discord_id is only set for 'user' accounts.
+-- A positive amount credits the account, a negative amount debits it.
+CREATE TABLE
+    public.ledger (
+        id BIGSERIAL PRIMARY KEY,
+        transfer_id TEXT NOT NULL,
+        account TEXT NOT NULL,
+        discord_id bigint,
+        currency_id TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        amount bigint NOT NULL,
+        reference TEXT,
+        created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+        CONSTRAINT user_account_has_discord_id CHECK ((account = 'user') = (discord_id IS NOT NULL))
+    ) TABLESPACE pg_default;
+
+CREATE INDEX ledger_transfer_id_idx ON public.ledger (transfer_id);
+CREATE INDEX ledger_discord_id_idx ON public.ledger (discord_id, currency_id);
+
+CREATE OR REPLACE FUNCTION ledger_append_only()
+RETURNS TRIGGER AS $$
+BEGIN
+    RAISE EXCEPTION 'the ledger is append-only';
+END;
+
+$$ language 'plpgsql';
+
+CREATE TRIGGER LEDGER_APPEND_ONLY
+	BEFORE
+	UPDATE OR DELETE
+	    ON public.ledger FOR EACH ROW
+	EXECUTE
+	    PROCEDURE ledger_append_only();
+
+-- the balances that exist at this point get an opening entry, with the adjustment account as counterparty.
+INSERT INTO ledger (transfer_id, account, discord_id, currency_id, kind, amount, reference)
+SELECT 'opening-balance', 'user', discord_id, currency_id, 'opening-balance', balance, NULL
+FROM balances
+WHERE balance <> 0;
+
+INSERT INTO ledger (transfer_id, account, discord_id, currency_id, kind, amount, reference)
+SELECT 'opening-balance', 'adjustment', NULL, currency_id, 'opening-balance', -SUM(balance), NULL
+FROM balances
+WHERE balance <> 0
+GROUP BY currency_id;
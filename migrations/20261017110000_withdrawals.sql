@@ -0,0 +1,30 @@
+-- Add migration script here
+-- Outbox of withdrawals. A withdrawal is stored (and its amount and fee reserved in the ledger) before sendcurrency is called,
+-- then advanced by the withdrawal worker: queued -> broadcast -> confirmed / failed.
+CREATE TABLE
+    public.withdrawals (
+        id TEXT PRIMARY KEY,
+        discord_id bigint NOT NULL,
+        currency_id TEXT NOT NULL,
+        amount bigint NOT NULL,
+        fee_currency_id TEXT NOT NULL,
+        fee bigint NOT NULL,
+        destination TEXT NOT NULL,
+        status TEXT NOT NULL,
+        opid TEXT,
+        txid TEXT,
+        error TEXT,
+        created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+        updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+        CONSTRAINT positive_amount CHECK (amount > 0),
+        CONSTRAINT non_negative_fee CHECK (fee >= 0)
+    ) TABLESPACE pg_default;
+
+CREATE INDEX withdrawals_status_idx ON public.withdrawals (status);
+
+CREATE TRIGGER SET_UPDATED_TIMESTAMP
+	BEFORE
+	UPDATE
+	    ON public.withdrawals FOR EACH ROW
+	EXECUTE
+	    PROCEDURE trigger_set_timestamp();
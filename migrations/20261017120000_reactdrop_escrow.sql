@@ -0,0 +1,4 @@
+-- Add migration script here
+-- The amount of a reactdrop is moved to escrow when it starts. Reactdrops that were started before this migration
+-- have nothing in escrow, their author is still debited when they finish.
+ALTER TABLE reactdrops ADD COLUMN escrowed BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,7 @@
+-- Add migration script here
+-- Optional eligibility rules of a reactdrop. NULL means the rule does not apply.
+ALTER TABLE reactdrops ADD COLUMN guild_id bigint;
+ALTER TABLE reactdrops ADD COLUMN required_role_id bigint;
+ALTER TABLE reactdrops ADD COLUMN min_account_age_days integer;
+ALTER TABLE reactdrops ADD COLUMN min_member_age_days integer;
+ALTER TABLE reactdrops ADD COLUMN max_participants integer;
@@ -0,0 +1,18 @@
+-- Add migration script here
+-- How the amount of a reactdrop is divided: split, random-winners, first-reactors or weighted-by-role.
+-- For random winners, the height and hash of the block used as seed are stored so the draw can be verified.
+ALTER TABLE reactdrops ADD COLUMN mode TEXT NOT NULL DEFAULT 'split';
+ALTER TABLE reactdrops ADD COLUMN winners integer;
+ALTER TABLE reactdrops ADD COLUMN weighted_role_id bigint;
+ALTER TABLE reactdrops ADD COLUMN role_weight integer;
+ALTER TABLE reactdrops ADD COLUMN seed_height bigint;
+ALTER TABLE reactdrops ADD COLUMN seed_hash TEXT;
+
+-- Discord does not tell in which order users reacted, so the bot records it while a reactdrop runs.
+CREATE TABLE
+    public.reactdrop_reactions (
+        message_id bigint NOT NULL,
+        discord_id bigint NOT NULL,
+        reacted_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+        PRIMARY KEY (message_id, discord_id)
+    ) TABLESPACE pg_default;
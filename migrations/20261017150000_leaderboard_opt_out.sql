@@ -0,0 +1,5 @@
+-- Add migration script here
+-- Users that opted out are left out of the public leaderboards.
+ALTER TABLE public.discord_users ADD COLUMN leaderboard_opt_out BOOLEAN NOT NULL DEFAULT false;
+
+CREATE INDEX tips_vrsc_currency_created_at_idx ON public.tips_vrsc (currency_id, created_at);
@@ -0,0 +1,3 @@
+-- Add migration script here
+-- Tips above this amount (in sats of the tipped currency) need to be confirmed by the user. NULL never asks.
+ALTER TABLE public.discord_users ADD COLUMN confirm_threshold bigint;
@@ -0,0 +1,3 @@
+-- Add migration script here
+-- An optional message the tipper added to the tip.
+ALTER TABLE public.tips_vrsc ADD COLUMN memo TEXT;
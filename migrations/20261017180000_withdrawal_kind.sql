@@ -0,0 +1,3 @@
+-- Add migration script here
+-- 'withdrawal' when users withdraw to their own wallet, 'send' when they pay someone else on-chain with /send.
+ALTER TABLE public.withdrawals ADD COLUMN kind TEXT NOT NULL DEFAULT 'withdrawal';
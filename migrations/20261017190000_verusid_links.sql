@@ -0,0 +1,19 @@
+-- Add migration script here
+-- A VerusID a user proved to control by signing a challenge. An identity is linked to at most one user.
+CREATE TABLE
+    public.verusid_links (
+        discord_id bigint PRIMARY KEY,
+        identity TEXT NOT NULL,
+        identity_address TEXT NOT NULL UNIQUE,
+        created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+    ) TABLESPACE pg_default;
+
+-- The last challenge a user got to sign, until it is verified.
+CREATE TABLE
+    public.verusid_challenges (
+        discord_id bigint PRIMARY KEY,
+        identity TEXT NOT NULL,
+        identity_address TEXT NOT NULL,
+        challenge TEXT NOT NULL,
+        created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+    ) TABLESPACE pg_default;
@@ -0,0 +1,20 @@
+-- Add migration script here
+-- Users that get a DM when an identity changes. state is the identity as the user last saw it (JSON),
+-- it is compared with the identity after every block.
+CREATE TABLE
+    public.identity_watches (
+        discord_id bigint NOT NULL,
+        identity_address TEXT NOT NULL,
+        identity TEXT NOT NULL,
+        state TEXT NOT NULL,
+        created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+        updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+        PRIMARY KEY (discord_id, identity_address)
+    ) TABLESPACE pg_default;
+
+CREATE TRIGGER SET_UPDATED_TIMESTAMP
+	BEFORE
+	UPDATE
+	    ON public.identity_watches FOR EACH ROW
+	EXECUTE
+	    PROCEDURE trigger_set_timestamp();
@@ -0,0 +1,23 @@
+-- Add migration script here
+-- Users that get a DM when a transaction pays to or spends from an address.
+CREATE TABLE
+    public.address_watches (
+        discord_id bigint NOT NULL,
+        address TEXT NOT NULL,
+        created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+        PRIMARY KEY (discord_id, address)
+    ) TABLESPACE pg_default;
+
+CREATE INDEX address_watches_address_idx ON public.address_watches (address);
+
+-- The transactions a user was notified of. The daemon announces a transaction when it enters the mempool and again
+-- when it is mined, this makes sure a user only gets one DM per transaction.
+CREATE TABLE
+    public.address_watch_notifications (
+        discord_id bigint NOT NULL,
+        address TEXT NOT NULL,
+        txid TEXT NOT NULL,
+        created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+        PRIMARY KEY (discord_id, address, txid),
+        FOREIGN KEY (discord_id, address) REFERENCES public.address_watches (discord_id, address) ON DELETE CASCADE
+    ) TABLESPACE pg_default;
@@ -0,0 +1,20 @@
+-- Add migration script here
+-- Users that get a DM when a new offer for an identity appears. offers is a JSON list of the txids of the
+-- open offers the user already knows about.
+CREATE TABLE
+    public.offer_watches (
+        discord_id bigint NOT NULL,
+        identity_address TEXT NOT NULL,
+        identity TEXT NOT NULL,
+        offers TEXT NOT NULL,
+        created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+        updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+        PRIMARY KEY (discord_id, identity_address)
+    ) TABLESPACE pg_default;
+
+CREATE TRIGGER SET_UPDATED_TIMESTAMP
+	BEFORE
+	UPDATE
+	    ON public.offer_watches FOR EACH ROW
+	EXECUTE
+	    PROCEDURE trigger_set_timestamp();
@@ -0,0 +1,14 @@
+-- Add migration script here
+-- Reactdrops can require participants to solve a captcha before they count.
+ALTER TABLE reactdrops ADD COLUMN captcha boolean NOT NULL DEFAULT false;
+
+-- The last captcha a user got for a reactdrop. answer is the sum they are asked for, attempts counts their answers.
+CREATE TABLE
+    public.reactdrop_captchas (
+        message_id bigint NOT NULL,
+        discord_id bigint NOT NULL,
+        answer integer NOT NULL,
+        attempts integer NOT NULL DEFAULT 0,
+        solved boolean NOT NULL DEFAULT false,
+        PRIMARY KEY (message_id, discord_id)
+    ) TABLESPACE pg_default;
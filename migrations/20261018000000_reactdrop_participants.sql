@@ -0,0 +1,10 @@
+-- Add migration script here
+-- The participants of a random winners reactdrop, stored when it ends and before the block that draws the winners
+-- is known. position is the order in which they reacted.
+CREATE TABLE
+    public.reactdrop_participants (
+        message_id bigint NOT NULL,
+        discord_id bigint NOT NULL,
+        position integer NOT NULL,
+        PRIMARY KEY (message_id, discord_id)
+    ) TABLESPACE pg_default;
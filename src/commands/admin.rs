@@ -1,14 +1,20 @@
 use poise::serenity_prelude::UserId;
 use sqlx::PgPool;
-use std::{sync::Arc, time::Duration};
+use std::{collections::HashMap, str::FromStr, sync::Arc, time::Duration};
 use tracing::{debug, error, instrument, trace};
 use uuid::Uuid;
-use vrsc::Amount;
-use vrsc_rpc::{bitcoin::Txid, client::RpcApi};
+use vrsc::{Address, Amount, Denomination};
+use vrsc_rpc::{
+    bitcoin::Txid,
+    client::{Client, RpcApi},
+};
 
 use crate::{
-    util::database,
-    wallet_listener::{process_txid, TransactionProcessor},
+    util::{
+        currency::{cached_currency, lookup_currency, Currency, CurrencyNames},
+        database,
+    },
+    wallet_listener::{process_txid, reserve_amount, TransactionProcessor},
     Context, Error,
 };
 
@@ -27,6 +33,8 @@ pub async fn adminhelp(ctx: Context<'_>) -> Result<(), Error> {
 !depositenabled <true/false>    - enable / disable deposits
 !setwithdrawfee <sats>          - sets the fee a user is charged when withdrawing funds
 !maintenance <true/false>       - set maintenance mode (commands are not executed) 
+!resolvewithdrawal <id> <txid/failed> - set the txid of a stuck withdrawal, or fail and refund it
+!adjustbalance <user_id> <currency> <sats> <reason> - add (or subtract, when negative) sats to a user's balance
 
 ```
     "#,
@@ -45,22 +53,88 @@ pub async fn status(ctx: Context<'_>) -> Result<(), Error> {
     let maintenance = *ctx.data().tx_processor.maintenance.read().await;
     let deposits_enabled = *ctx.data().tx_processor.deposits_enabled.read().await;
     let withdrawals_enabled = *ctx.data().withdrawals_enabled.read().await;
-    let total_balance = Amount::from_sat(database::get_total_balance(pool).await?);
-    let total_tipped = Amount::from_sat(database::get_total_tipped(pool).await?);
-    let largest_tip = Amount::from_sat(database::get_largest_tip(pool).await?);
-    let total_deposited = totaldeposited(ctx).await?;
-    let total_withdrawn = totalwithdrawn(ctx).await?;
+    let native_currency = &ctx.data().native_currency.id;
+    let total_balance = Amount::from_sat(database::get_total_balance(pool, native_currency).await?);
+    let total_tipped = Amount::from_sat(database::get_total_tipped(pool, native_currency).await?);
+    let largest_tip = Amount::from_sat(database::get_largest_tip(pool, native_currency).await?);
+
+    let totals = database::get_ledger_totals(pool, native_currency).await?;
+    let sum = |account: &str, kind: Option<&str>| -> i64 {
+        totals
+            .iter()
+            .filter(|(a, k, _)| a == account && kind.map_or(true, |kind| k == kind))
+            .map(|(_, _, total)| total)
+            .sum()
+    };
+    let to_amount = |sats: i64| Amount::from_sat(sats.max(0) as u64);
+
+    let total_deposited = sum("user", Some("deposit"));
+    // only what reached the chain: refunded and pending withdrawals never get there.
+    let total_withdrawn = sum("chain", Some("withdrawal")) + sum("chain", Some("send"));
+    let fees = sum("fees", None);
+    let adjustments = -sum("adjustment", None);
+    let user_balances = sum("user", None);
+    let pending_withdrawals = sum("outbox", None);
+    let escrow = sum("escrow", None);
+    // what was deposited minus what was withdrawn, according to the ledger.
+    let held = -sum("chain", None);
+
+    let unbalanced_transfers = database::get_unbalanced_transfers(pool).await?;
+    let balance_mismatches = database::get_balance_mismatches(pool).await?;
+
+    for transfer_id in &unbalanced_transfers {
+        error!("transfer {transfer_id} does not balance");
+    }
 
-    let client = ctx.data().verus()?;
+    for (user_id, currency_id, balance, total) in &balance_mismatches {
+        error!("{currency_id} balance of {user_id} is {balance}, the ledger says {total}");
+    }
 
-    let daemon_balance = client.get_balance(None, None)?;
+    let balanced = unbalanced_transfers.is_empty()
+        && balance_mismatches.is_empty()
+        && total_balance.as_sat() as i64 == user_balances;
+
+    // the wallet must hold at least what the bot owes, in every currency.
+    let wallet = wallet_balances(
+        &ctx.data().verus()?,
+        &ctx.data().native_currency,
+        &ctx.data().currency_names,
+    )?;
+    let mut solvent = true;
+    let mut solvency = vec![];
+    for (currency_id, owed) in database::get_owed_totals(pool).await? {
+        let currency = cached_currency(
+            &ctx.data().currency_names,
+            &Address::from_str(&currency_id)?,
+        );
+        let in_wallet = wallet.get(&currency.id).copied().unwrap_or(Amount::ZERO);
+        let covered = in_wallet.as_sat() as i64 >= owed;
+
+        if !covered {
+            error!(
+                "the wallet holds {} of {}, but {owed} sats are owed",
+                in_wallet.as_sat(),
+                currency.name
+            );
+        }
+        solvent &= covered;
+        solvency.push(format!(
+            "{}: {} in the wallet, {} owed{}",
+            currency.name,
+            currency.display(&in_wallet),
+            currency.display(&to_amount(owed)),
+            if covered { "" } else { " (NOT covered)" }
+        ));
+    }
 
     debug!("total balance: {total_balance}");
     debug!("total_tipped: {total_tipped}");
     debug!("largest_tip: {largest_tip}");
     debug!("total_deposited: {total_deposited}");
     debug!("total_withdrawn: {total_withdrawn}");
-    debug!("daemon_balance: {daemon_balance}");
+    debug!(
+        "held: {held}, fees: {fees}, adjustments: {adjustments}, user balances: {user_balances}"
+    );
 
     ctx.send(|reply| {
         reply.embed(|embed| {
@@ -69,27 +143,35 @@ pub async fn status(ctx: Context<'_>) -> Result<(), Error> {
                 .field("bot in maintenance", maintenance, false)
                 .field("deposits enabled", deposits_enabled, false)
                 .field("withdrawals enabled", withdrawals_enabled, false)
-                .field("VRSC daemon balance", daemon_balance, false)
                 .field("Tipbot balance", total_balance, false)
-                .field("Total deposited", total_deposited, false)
-                .field("Total withdrawn", total_withdrawn, false)
+                .field("Total deposited", to_amount(total_deposited), false)
+                .field("Total withdrawn", to_amount(total_withdrawn), false)
+                .field("Database deposits - withdraws", to_amount(held), false)
+                .field("Adjustments", format!("{adjustments} sats"), false)
+                .field("Total tipped", total_tipped, false)
+                .field("Largest tip", largest_tip, false)
+                .field("Bot fees", to_amount(fees), false)
+                .field("Pending withdrawals", to_amount(pending_withdrawals), false)
+                .field("Reactdrops in escrow", to_amount(escrow), false)
                 .field(
-                    "Database deposits - withdraws",
-                    total_deposited
-                        .checked_sub(total_withdrawn)
-                        .unwrap_or(Amount::ZERO),
+                    "Wallet",
+                    format!(
+                        "{}\n{}",
+                        if solvent { "solvent" } else { "NOT solvent" },
+                        solvency.join("\n")
+                    ),
                     false,
                 )
-                .field("Total tipped", total_tipped, false)
-                .field("Largest tip", largest_tip, false)
                 .field(
-                    "Bot fees _(minus network fees)_",
-                    {
-                        if let Some(pos_amount) = daemon_balance.checked_sub(total_balance) {
-                            pos_amount
-                        } else {
-                            Amount::ZERO
-                        }
+                    "Ledger",
+                    if balanced {
+                        "balanced".to_string()
+                    } else {
+                        format!(
+                            "NOT balanced: {} unbalanced transfers, {} balance mismatches",
+                            unbalanced_transfers.len(),
+                            balance_mismatches.len()
+                        )
                     },
                     false,
                 )
@@ -100,54 +182,41 @@ pub async fn status(ctx: Context<'_>) -> Result<(), Error> {
     Ok(())
 }
 
-async fn totaldeposited(ctx: Context<'_>) -> Result<Amount, Error> {
-    ctx.defer().await?;
-
-    let pool = &ctx.data().database;
-    let client = ctx.data().verus()?;
-
-    let deposit_transactions = database::get_all_txids(&pool, "deposit").await?;
-
-    let mut sum = Amount::ZERO;
-
-    for txid in deposit_transactions {
-        let raw_tx = client.get_raw_transaction_verbose(&txid)?;
-
-        for vout in raw_tx.vout.iter() {
-            if let Some(addresses) = &vout.script_pubkey.addresses {
-                for address in addresses {
-                    if let Some(user_id) = database::get_user_from_address(&pool, address).await? {
-                        trace!("there is a user for this address: {user_id}",);
-                        sum = sum.checked_add(vout.value_sat).unwrap();
-                    }
-                }
-            } else {
-                trace!("no addresses found in scriptpubkey");
+// What the wallet of the bot holds per currency. The native currency includes private balances.
+fn wallet_balances(
+    client: &Client,
+    native_currency: &Currency,
+    currency_names: &CurrencyNames,
+) -> Result<HashMap<Address, Amount>, Error> {
+    let mut balances = HashMap::new();
+
+    let total: serde_json::Value = client.call("z_gettotalbalance", &[])?;
+    let native = total["total"]
+        .as_str()
+        .ok_or("z_gettotalbalance returned no total")?;
+    balances.insert(
+        native_currency.id.clone(),
+        Amount::from_str_in(native, Denomination::Verus)?,
+    );
+
+    // reserve balances are listed by currency name.
+    let info: serde_json::Value = client.call("getwalletinfo", &[])?;
+    let reserves: HashMap<String, serde_json::Number> =
+        serde_json::from_value(info["reserve_balance"].clone()).unwrap_or_default();
+    for (name, amount) in &reserves {
+        match (
+            lookup_currency(client, currency_names, name),
+            reserve_amount(amount),
+        ) {
+            (Some(currency), Some(amount)) if currency.id != native_currency.id => {
+                balances.insert(currency.id, amount);
             }
+            (Some(_), Some(_)) => {}
+            _ => error!("could not read the wallet balance of {name}: {amount}"),
         }
     }
 
-    Ok(sum)
-}
-
-pub async fn totalwithdrawn(ctx: Context<'_>) -> Result<Amount, Error> {
-    ctx.defer().await?;
-
-    let pool = &ctx.data().database;
-    let client = ctx.data().verus()?;
-
-    let withdraw_transactions = database::get_all_txids(&pool, "withdraw").await?;
-
-    let mut sum = Amount::ZERO;
-
-    for txid in withdraw_transactions {
-        let raw_tx = client.get_raw_transaction_verbose(&txid)?;
-        let vout = raw_tx.vout.first().unwrap();
-
-        sum = sum.checked_add(vout.value_sat).unwrap();
-    }
-
-    Ok(sum)
+    Ok(balances)
 }
 
 #[instrument(skip(ctx))]
@@ -271,46 +340,130 @@ pub async fn checktxid(ctx: Context<'_>, txid: Txid) -> Result<(), Error> {
 
     let client = &ctx.data().verus()?;
 
-    if let Ok(raw_tx) = client.get_raw_transaction_verbose(&txid) {
-        process_txid(http, &pool, &raw_tx).await?;
+    if client.get_raw_transaction_verbose(&txid).is_ok() {
+        process_txid(
+            http,
+            &pool,
+            &client,
+            &ctx.data().native_currency,
+            &ctx.data().currency_names,
+            &txid,
+        )
+        .await?;
     }
 
     Ok(())
 }
 
-/// Manually add withdraw tx when one didn't register
+/// Manually resolve a withdrawal that the withdrawal processor cannot finish on its own
 ///
-/// Needs discord_user_id, txid, tx_fee (in sats)
+/// Needs the withdrawal ID and either the txid of the withdrawal, or `failed` to refund it to the user.
 #[instrument(skip(ctx))]
 #[poise::command(dm_only, owners_only, prefix_command, hide_in_help)]
-pub async fn manuallyaddwithdraw(
+pub async fn resolvewithdrawal(
     ctx: Context<'_>,
-    user_id: UserId,
-    txid: Txid,
-    tx_fee: u64,
+    withdrawal_id: Uuid,
+    result: String,
 ) -> Result<(), Error> {
-    trace!("manually add withdraw: {txid}");
+    trace!("manually resolve withdrawal {withdrawal_id}: {result}");
     let pool = &ctx.data().database;
-    let uuid = Uuid::new_v4();
-
-    debug!("manually storing withdraw transaction: {uuid}: {user_id} - {txid} ({tx_fee})");
-
-    database::store_withdraw_transaction(
-        pool,
-        &uuid,
-        &user_id,
-        Some(&txid),
-        &format!("opid-{uuid}"),
-        &Amount::from_sat(tx_fee),
-    )
-    .await?;
 
-    ctx.send(|reply| reply.ephemeral(true).content(format!("{txid} stored.")))
+    let Some(withdrawal) = database::get_withdrawal(pool, &withdrawal_id).await? else {
+        ctx.send(|reply| reply.content(format!("withdrawal {withdrawal_id} not found")))
+            .await?;
+
+        return Ok(());
+    };
+
+    let resolved = if result == "failed" {
+        database::fail_withdrawal(pool, &withdrawal, "resolved manually").await?
+    } else {
+        let txid = Txid::from_str(&result)?;
+        // claim it first in case it is still queued, so it can't be sent anymore.
+        database::claim_withdrawal(pool, &withdrawal_id).await?;
+
+        let mut tx = pool.begin().await?;
+        let resolved = database::set_withdrawal_txid(&mut tx, &withdrawal_id, &txid).await?;
+        if resolved {
+            database::store_withdraw_transaction(
+                &mut tx,
+                &withdrawal_id,
+                &withdrawal.user_id,
+                &withdrawal.currency_id,
+                Some(&txid),
+                withdrawal
+                    .opid
+                    .as_deref()
+                    .unwrap_or(&format!("opid-{withdrawal_id}")),
+                &withdrawal.fee,
+            )
+            .await?;
+        }
+        tx.commit().await?;
+
+        resolved
+    };
+
+    let content = if resolved {
+        format!("withdrawal {withdrawal_id} resolved")
+    } else {
+        format!(
+            "withdrawal {withdrawal_id} could not be resolved, its status is {}",
+            withdrawal.status
+        )
+    };
+
+    ctx.send(|reply| reply.ephemeral(true).content(content))
         .await?;
 
     Ok(())
 }
 
+/// Manually correct the balance of a user
+///
+/// Needs discord_user_id, currency name or id, amount (in sats, negative to subtract) and a reason
+#[instrument(skip(ctx))]
+#[poise::command(dm_only, owners_only, prefix_command, hide_in_help)]
+pub async fn adjustbalance(
+    ctx: Context<'_>,
+    user_id: UserId,
+    currency: String,
+    amount: i64,
+    #[rest] reason: String,
+) -> Result<(), Error> {
+    trace!("adjust balance of {user_id} with {amount} {currency}: {reason}");
+    let pool = &ctx.data().database;
+
+    let Some(currency) = ctx.data().currency(&currency)? else {
+        ctx.send(|reply| reply.content(format!("{currency} is not a known currency")))
+            .await?;
+
+        return Ok(());
+    };
+
+    if let Err(e) = database::adjust_balance(pool, &user_id, &currency.id, amount, &reason).await {
+        error!("could not adjust balance: {:?}", e);
+        ctx.send(|reply| reply.content(format!("Could not adjust balance: {e}")))
+            .await?;
+
+        return Ok(());
+    }
+
+    let new_balance = database::get_balance_for_user(pool, &user_id, &currency.id)
+        .await?
+        .unwrap_or(0);
+
+    ctx.send(|reply| {
+        reply.content(format!(
+            "Balance of {user_id} adjusted, new balance: {}",
+            currency.display(&Amount::from_sat(new_balance))
+        ))
+    })
+    .await?;
+
+    Ok(())
+}
+
 /// Set maintenance mode on or off
 #[instrument(skip(ctx))]
 #[poise::command(dm_only, owners_only, prefix_command, hide_in_help)]
@@ -8,10 +8,19 @@ use poise::serenity_prelude::Colour;
 use serde::Deserialize;
 use tracing::{debug, instrument};
 use uuid::Uuid;
-use vrsc::Amount;
+use vrsc::{Address, Amount};
 use vrsc_rpc::{client::RpcApi, json::GetCurrencyStateResult};
 
-use crate::{Context, Error};
+use crate::{
+    util::{
+        currency::{cached_currency, lookup_currency},
+        identity::{get_identity, identity_name, identity_state},
+    },
+    Context, Error,
+};
+
+// Keys and values are 64 characters at most, so this many entries stay below the field limit of 1024 characters.
+const MAX_CONTENT_MAP_ENTRIES: usize = 6;
 
 /// Show information about Verus blockchain.
 #[instrument(skip(ctx), fields(request_id = %Uuid::new_v4() ))]
@@ -84,11 +93,7 @@ pub async fn peerinfo(ctx: Context<'_>) -> Result<(), Error> {
 pub async fn price(ctx: Context<'_>) -> Result<(), Error> {
     ctx.defer().await?;
 
-    let resp: CoinPaprika =
-        reqwest::get("https://api.coinpaprika.com/v1/tickers/vrsc-verus-coin?quotes=USD,BTC")
-            .await?
-            .json()
-            .await?;
+    let resp = fetch_coinpaprika().await?;
 
     // TODO: get current circulating supply
     // The below does not work because it is very slow (5+ secs)
@@ -509,6 +514,204 @@ pub async fn pure(ctx: Context<'_>) -> Result<(), Error> {
     Ok(())
 }
 
+/// Estimate a conversion through a basket
+///
+/// Shows what you would get for converting an amount of one currency into another, using the current state of \
+/// the basket: the estimated output, the conversion fees, and the slippage compared to the price of the reserves.
+///
+/// Between two reserves of a basket, enter the basket in `via`. To or from a basket itself, `via` is not needed.
+#[instrument(skip(ctx), fields(request_id = %Uuid::new_v4() ))]
+#[poise::command(slash_command, category = "Miscellaneous")]
+pub async fn convert(
+    ctx: Context<'_>,
+    #[description = "The amount to convert"] amount: f64,
+    #[description = "The currency to convert from"] from: String,
+    #[description = "The currency to convert to"] to: String,
+    #[description = "The basket to convert through"] via: Option<String>,
+) -> Result<(), Error> {
+    let client = ctx.data().verus()?;
+    let currency_names = &ctx.data().currency_names;
+
+    if !amount.is_finite() || amount <= 0.0 {
+        ctx.send(|reply| reply.content("Enter an amount above 0").ephemeral(true))
+            .await?;
+
+        return Ok(());
+    }
+
+    let lookup = |name: &str| lookup_currency(&client, currency_names, name.trim());
+    let (Some(from), Some(to)) = (lookup(&from), lookup(&to)) else {
+        ctx.send(|reply| {
+            reply
+                .content(format!("Currency `{from}` or `{to}` not found"))
+                .ephemeral(true)
+        })
+        .await?;
+
+        return Ok(());
+    };
+    let via = match via.as_deref().map(lookup) {
+        Some(None) => {
+            ctx.send(|reply| reply.content("Basket not found").ephemeral(true))
+                .await?;
+
+            return Ok(());
+        }
+        Some(Some(via)) => Some(via),
+        None => None,
+    };
+
+    let mut params = serde_json::json!({
+        "currency": from.id.to_string(),
+        "convertto": to.id.to_string(),
+        "amount": amount,
+    });
+    if let Some(via) = &via {
+        params["via"] = serde_json::json!(via.id.to_string());
+    }
+
+    let estimate = match client.call::<EstimateConversionResult>("estimateconversion", &[params]) {
+        Ok(estimate) => estimate,
+        Err(e) => {
+            debug!("estimateconversion failed: {e:?}");
+            ctx.send(|reply| {
+                reply
+                    .content(format!(
+                        "`{}` can't be converted to `{}`{}. Between two reserves, enter the basket in `via`.",
+                        from.name,
+                        to.name,
+                        via.as_ref()
+                            .map(|via| format!(" via `{}`", via.name))
+                            .unwrap_or_default()
+                    ))
+                    .ephemeral(true)
+            })
+            .await?;
+
+            return Ok(());
+        }
+    };
+    debug!("estimate: {estimate:?}");
+
+    // without via, one of the currencies is the basket, the one that has the other as a reserve.
+    let basket = match &via {
+        Some(via) => client.get_currency(&via.id.to_string())?,
+        None => {
+            let to_currency = client.get_currency(&to.id.to_string())?;
+            let to_is_basket = to_currency
+                .bestcurrencystate
+                .reservecurrencies
+                .iter()
+                .flatten()
+                .any(|rc| rc.currencyid == from.id);
+
+            match to_is_basket {
+                true => to_currency,
+                false => client.get_currency(&from.id.to_string())?,
+            }
+        }
+    };
+
+    // the price of the basket in a reserve, a currency that is not a reserve is the basket itself.
+    let supply = basket.bestcurrencystate.supply.as_vrsc();
+    let price_in = |id: &Address| {
+        basket
+            .bestcurrencystate
+            .reservecurrencies
+            .iter()
+            .flatten()
+            .find(|rc| &rc.currencyid == id)
+            .map(|rc| (rc.reserves.as_vrsc() / rc.weight) / supply)
+    };
+
+    let fee = (amount - estimate.netinputamount).max(0.0);
+    let mut fields = vec![
+        (
+            "Estimated output".to_string(),
+            to.display(&Amount::from_vrsc(estimate.estimatedcurrencyout)?),
+            false,
+        ),
+        (
+            "Basket".to_string(),
+            basket.fullyqualifiedname.clone(),
+            true,
+        ),
+        (
+            "Fees".to_string(),
+            format!(
+                "{} ({:.3}%)",
+                from.display(&Amount::from_vrsc(fee)?),
+                fee / amount * 100.0
+            ),
+            true,
+        ),
+    ];
+
+    if let Some(expected) = spot_output(amount, price_in(&from.id), price_in(&to.id)) {
+        fields.push((
+            "Reserve-ratio price".to_string(),
+            format!("1 {} = {:.8} {}", from.name, expected / amount, to.name),
+            false,
+        ));
+        fields.push((
+            "Slippage".to_string(),
+            format!(
+                "{:.3}% (fees included)",
+                slippage(estimate.estimatedcurrencyout, expected) * 100.0
+            ),
+            true,
+        ));
+    }
+
+    ctx.send(|reply| {
+        reply.embed(|embed| {
+            embed
+                .title(format!(
+                    "Converting {}",
+                    from.display(&Amount::from_vrsc(amount).unwrap_or(Amount::ZERO))
+                ))
+                .fields(fields)
+                .footer(|footer| {
+                    footer.text(
+                        "This is an estimate, the actual output depends on all the conversions in the block.",
+                    )
+                })
+                .color(deterministic_color(&basket.fullyqualifiedname))
+        })
+    })
+    .await?;
+
+    Ok(())
+}
+
+// The parts of `estimateconversion` that are shown. `netinputamount` is the input after conversion fees.
+#[derive(Debug, Deserialize)]
+struct EstimateConversionResult {
+    netinputamount: f64,
+    estimatedcurrencyout: f64,
+}
+
+/// Gets the VRSC price in USD and BTC from CoinPaprika.
+pub async fn fetch_coinpaprika() -> Result<CoinPaprika, Error> {
+    let resp: CoinPaprika =
+        reqwest::get("https://api.coinpaprika.com/v1/tickers/vrsc-verus-coin?quotes=USD,BTC")
+            .await?
+            .json()
+            .await?;
+
+    Ok(resp)
+}
+
+/// Gets the VRSC price in USD, from the same source as `price`.
+pub async fn fetch_usd_price() -> Result<f64, Error> {
+    fetch_coinpaprika()
+        .await?
+        .quotes
+        .get("USD")
+        .map(|quote| quote.price)
+        .ok_or_else(|| "CoinPaprika has no USD price".into())
+}
+
 #[derive(Deserialize, Debug)]
 pub struct CoinPaprika {
     #[serde(rename = "id")]
@@ -600,8 +803,142 @@ pub async fn time_of_block(ctx: Context<'_>, block: u64) -> Result<(), Error> {
     Ok(())
 }
 
+/// Show a VerusID profile
+///
+/// Enter a VerusID (`name@`, or `sub.parent@` for a subID) or its i-address.
+#[instrument(skip(ctx), fields(request_id = %Uuid::new_v4() ))]
+#[poise::command(slash_command, category = "Miscellaneous")]
+pub async fn identity(
+    ctx: Context<'_>,
+    #[description = "The VerusID (name@) or its i-address"] name: String,
+) -> Result<(), Error> {
+    let client = ctx.data().verus()?;
+    let name = identity_name(&name);
+
+    let Some(identity) = get_identity(&client, &name) else {
+        ctx.send(|reply| {
+            reply
+                .content(format!("VerusID `{name}` not found"))
+                .ephemeral(true)
+        })
+        .await?;
+
+        return Ok(());
+    };
+    debug!("identity: {identity:?}");
+
+    let definition = &identity.identity;
+    let parent = cached_currency(&ctx.data().currency_names, &definition.parent);
+
+    // authorities are shown by name, or as `self` when the identity is its own authority.
+    let authority = |address: &Address| {
+        if address == &definition.identityaddress {
+            String::from("self")
+        } else {
+            get_identity(&client, &address.to_string())
+                .map(|authority| authority.fullyqualifiedname)
+                .unwrap_or_else(|| address.to_string())
+        }
+    };
+
+    let mut fields = vec![
+        (
+            "Identity address",
+            format!("`{}`", definition.identityaddress),
+            false,
+        ),
+        ("Parent", parent.name, true),
+        (
+            "Status",
+            identity_state(&identity.status, definition.flags, definition.timelock),
+            true,
+        ),
+        (
+            "Primary addresses",
+            format!(
+                "{}\n({} signature(s) required)",
+                definition
+                    .primaryaddresses
+                    .iter()
+                    .map(|address| format!("`{address}`"))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+                definition.minimumsignatures
+            ),
+            false,
+        ),
+        (
+            "Revocation authority",
+            authority(&definition.revocationauthority),
+            true,
+        ),
+        (
+            "Recovery authority",
+            authority(&definition.recoveryauthority),
+            true,
+        ),
+    ];
+
+    if !definition.contentmap.is_empty() {
+        fields.push((
+            "Content map",
+            content_map_str(&definition.contentmap),
+            false,
+        ));
+    }
+
+    if let Some(blockheight) = identity.blockheight {
+        fields.push(("Last updated in block", blockheight.to_string(), false));
+    }
+
+    ctx.send(|reply| {
+        reply.embed(|embed| {
+            embed
+                .title(format!("VerusID: **{}**", identity.fullyqualifiedname))
+                .fields(fields)
+                .color(deterministic_color(&identity.fullyqualifiedname))
+        })
+    })
+    .await?;
+
+    Ok(())
+}
+
+// What an amount converts to at the reserve ratios of a basket, without fees and slippage.
+// The prices are the price of the basket in the currencies, None means the currency is the basket itself.
+fn spot_output(amount: f64, from_price: Option<f64>, to_price: Option<f64>) -> Option<f64> {
+    let output = amount * to_price.unwrap_or(1.0) / from_price.unwrap_or(1.0);
+
+    output.is_finite().then_some(output)
+}
+
+// How much less the estimate is than the reserve-ratio output, as a fraction.
+fn slippage(estimated: f64, expected: f64) -> f64 {
+    1.0 - estimated / expected
+}
+
+// A content map has hex keys and values. Only the first entries fit in an embed field.
+fn content_map_str(content_map: &HashMap<String, String>) -> String {
+    let mut entries = content_map.iter().collect::<Vec<_>>();
+    entries.sort();
+
+    let mut lines = entries
+        .iter()
+        .take(MAX_CONTENT_MAP_ENTRIES)
+        .map(|(key, value)| format!("`{key}`: `{value}`"))
+        .collect::<Vec<_>>();
+    if entries.len() > MAX_CONTENT_MAP_ENTRIES {
+        lines.push(format!(
+            "and {} more",
+            entries.len() - MAX_CONTENT_MAP_ENTRIES
+        ));
+    }
+
+    lines.join("\n")
+}
+
 /// Returns the DateTime in the future if current_height is not yet at future_height
-fn time_until_block(current_height: u64, future_height: u64) -> Option<DateTime<Utc>> {
+pub(crate) fn time_until_block(current_height: u64, future_height: u64) -> Option<DateTime<Utc>> {
     // actual block time is 61.95s, so we multiply with 1.0325
     // https://discord.com/channels/444621794964537354/449633463394500629/1121389199451500625
     let diff = future_height
@@ -645,3 +982,18 @@ fn time_until_block(current_height: u64, future_height: u64) -> Option<DateTime<
 
 //     None
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spot_output_through_basket() {
+        // basket costs 2 of reserve a and 6 of reserve b.
+        assert_eq!(spot_output(10.0, Some(2.0), Some(6.0)), Some(30.0));
+        assert_eq!(spot_output(10.0, Some(2.0), None), Some(5.0));
+        assert_eq!(spot_output(10.0, None, Some(6.0)), Some(60.0));
+        assert_eq!(spot_output(10.0, Some(0.0), Some(6.0)), None);
+        assert!((slippage(29.7, 30.0) - 0.01).abs() < 1e-9);
+    }
+}
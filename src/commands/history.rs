@@ -0,0 +1,366 @@
+use std::{borrow::Cow, time::Duration};
+
+use poise::serenity_prelude::{self as serenity, CreateComponents, CreateEmbed};
+use sqlx::types::chrono::{DateTime, Days, NaiveDate, Utc};
+use tracing::*;
+use uuid::Uuid;
+use vrsc::Amount;
+
+use crate::{
+    commands::user_blacklisted,
+    util::{
+        currency::{cached_currency, CurrencyNames},
+        database,
+        ledger::{HistoryEntry, HistoryFilter, LedgerKind},
+    },
+    Context, Error,
+};
+
+const PAGE_SIZE: i64 = 10;
+
+#[derive(Debug, poise::ChoiceParameter)]
+pub enum HistoryKind {
+    #[name = "Tips sent"]
+    TipsSent,
+    #[name = "Tips received"]
+    TipsReceived,
+    Deposits,
+    Withdrawals,
+}
+
+impl HistoryKind {
+    fn kinds(&self) -> Vec<LedgerKind> {
+        match self {
+            Self::TipsSent => vec![LedgerKind::TipSend, LedgerKind::ReactdropEscrow],
+            Self::TipsReceived => vec![LedgerKind::TipReceive],
+            Self::Deposits => vec![LedgerKind::Deposit],
+            Self::Withdrawals => vec![LedgerKind::Withdrawal, LedgerKind::Send, LedgerKind::Fee],
+        }
+    }
+}
+
+/// Show the history of your balance
+///
+/// -------- :robot: **History** --------
+/// Shows every change to your balance, newest first. Use the buttons to go to older or newer entries.
+///
+/// Optionally, only show tips sent, tips received, deposits or withdrawals, \
+/// and only the entries between two dates (`YYYY-MM-DD`, both included).
+///
+/// -------- :robot: **Export** --------
+/// Set `csv` to get all the matching entries as a CSV file instead, for example for your tax administration.
+#[instrument(skip(ctx), fields(request_id = %Uuid::new_v4() ))]
+#[poise::command(slash_command, category = "Wallet")]
+pub async fn history(
+    ctx: Context<'_>,
+    #[description = "Only show these entries"] kind: Option<HistoryKind>,
+    #[description = "Only show entries from this date on (YYYY-MM-DD)"] from: Option<String>,
+    #[description = "Only show entries up to and including this date (YYYY-MM-DD)"] until: Option<
+        String,
+    >,
+    #[description = "Export all matching entries as a CSV file"] csv: Option<bool>,
+) -> Result<(), Error> {
+    if user_blacklisted(ctx, ctx.author().id).await? {
+        return Ok(());
+    }
+
+    let (from, until) = match (parse_date(from.as_deref()), parse_date(until.as_deref())) {
+        (Ok(from), Ok(until)) => (from, until),
+        _ => {
+            ctx.send(|reply| {
+                reply
+                    .ephemeral(true)
+                    .content("Please enter dates as YYYY-MM-DD, for example 2024-01-31")
+            })
+            .await?;
+
+            return Ok(());
+        }
+    };
+
+    let filter = HistoryFilter {
+        kinds: kind.map(|kind| kind.kinds()).unwrap_or_default(),
+        from: from.map(start_of_day),
+        // the until date is included, so the filter ends at the start of the next day.
+        until: until
+            .and_then(|until| until.checked_add_days(Days::new(1)))
+            .map(start_of_day),
+    };
+    debug!("history filter: {filter:?}");
+
+    let pool = &ctx.data().database;
+    let currency_names = &ctx.data().currency_names;
+
+    if csv.unwrap_or(false) {
+        let entries = database::get_history(pool, &ctx.author().id, &filter, i64::MAX, 0).await?;
+        let csv = history_csv(&entries, currency_names);
+
+        ctx.send(|reply| {
+            reply
+                .ephemeral(true)
+                .content(format!("Your history has {} entries.", entries.len()))
+                .attachment(serenity::AttachmentType::Bytes {
+                    data: Cow::from(csv.into_bytes()),
+                    filename: String::from("history.csv"),
+                })
+        })
+        .await?;
+
+        return Ok(());
+    }
+
+    let count = database::count_history(pool, &ctx.author().id, &filter).await?;
+    let pages = ((count + PAGE_SIZE - 1) / PAGE_SIZE).max(1);
+    let mut page = 0;
+
+    let entries = database::get_history(pool, &ctx.author().id, &filter, PAGE_SIZE, 0).await?;
+
+    let ctx_id = ctx.id();
+    ctx.send(|reply| {
+        reply
+            .ephemeral(true)
+            .embed(|embed| history_embed(embed, &entries, currency_names, page, pages))
+            .components(|components| history_buttons(components, ctx_id, page, pages))
+    })
+    .await?;
+
+    while let Some(press) = serenity::CollectComponentInteraction::new(ctx.serenity_context())
+        .filter(move |press| press.data.custom_id.starts_with(&ctx_id.to_string()))
+        .timeout(Duration::from_secs(10 * 60))
+        .await
+    {
+        if press.data.custom_id == format!("{ctx_id}next") {
+            page = (page + 1).min(pages - 1);
+        } else if press.data.custom_id == format!("{ctx_id}prev") {
+            page = (page - 1).max(0);
+        } else {
+            continue;
+        }
+
+        let entries =
+            database::get_history(pool, &ctx.author().id, &filter, PAGE_SIZE, page * PAGE_SIZE)
+                .await?;
+
+        press
+            .create_interaction_response(ctx.http(), |response| {
+                response
+                    .kind(serenity::InteractionResponseType::UpdateMessage)
+                    .interaction_response_data(|data| {
+                        data.embed(|embed| {
+                            history_embed(embed, &entries, currency_names, page, pages)
+                        })
+                        .components(|components| history_buttons(components, ctx_id, page, pages))
+                    })
+            })
+            .await?;
+    }
+
+    Ok(())
+}
+
+fn parse_date(date: Option<&str>) -> Result<Option<NaiveDate>, Error> {
+    date.map(|date| NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d"))
+        .transpose()
+        .map_err(Into::into)
+}
+
+fn start_of_day(date: NaiveDate) -> DateTime<Utc> {
+    DateTime::<Utc>::from_utc(date.and_hms_opt(0, 0, 0).unwrap(), Utc)
+}
+
+fn history_embed<'a>(
+    embed: &'a mut CreateEmbed,
+    entries: &[HistoryEntry],
+    currency_names: &CurrencyNames,
+    page: i64,
+    pages: i64,
+) -> &'a mut CreateEmbed {
+    let description = if entries.is_empty() {
+        String::from("Nothing to show")
+    } else {
+        entries
+            .iter()
+            .map(|entry| {
+                format!(
+                    "<t:{}:d> **{}** {}{}{}",
+                    entry.created_at.timestamp(),
+                    kind_label(&entry.kind),
+                    signed_amount(entry, currency_names),
+                    counterparties_label(entry),
+                    entry
+                        .memo
+                        .as_ref()
+                        .map(|memo| format!(": _{memo}_"))
+                        .unwrap_or_default()
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+
+    embed
+        .title("History")
+        .description(description)
+        .footer(|footer| footer.text(format!("Page {} of {pages}", page + 1)))
+}
+
+fn history_buttons(
+    components: &mut CreateComponents,
+    ctx_id: u64,
+    page: i64,
+    pages: i64,
+) -> &mut CreateComponents {
+    components.create_action_row(|row| {
+        row.create_button(|button| {
+            button
+                .custom_id(format!("{ctx_id}prev"))
+                .label("Newer")
+                .disabled(page == 0)
+        })
+        .create_button(|button| {
+            button
+                .custom_id(format!("{ctx_id}next"))
+                .label("Older")
+                .disabled(page + 1 >= pages)
+        })
+    })
+}
+
+fn kind_label(kind: &str) -> &str {
+    match kind {
+        "deposit" => "Deposit",
+        "withdrawal" => "Withdrawal",
+        "send" => "Sent on-chain",
+        "fee" => "Withdrawal fee",
+        "tip-send" => "Tip sent",
+        "tip-receive" => "Tip received",
+        "refund" => "Refund",
+        "reactdrop-escrow" => "Reactdrop",
+        "adjustment" => "Adjustment",
+        "opening-balance" => "Opening balance",
+        other => other,
+    }
+}
+
+fn signed_amount(entry: &HistoryEntry, currency_names: &CurrencyNames) -> String {
+    let currency = cached_currency(currency_names, &entry.currency_id);
+    let sign = if entry.amount < 0 { "-" } else { "+" };
+
+    format!(
+        "{sign}{}",
+        currency.display(&Amount::from_sat(entry.amount.unsigned_abs()))
+    )
+}
+
+fn counterparties_label(entry: &HistoryEntry) -> String {
+    let direction = match entry.kind.as_str() {
+        "tip-send" => "to",
+        "tip-receive" => "from",
+        _ => return String::new(),
+    };
+
+    match entry.counterparties.as_slice() {
+        [] => String::new(),
+        [user_id] => format!(" {direction} <@{user_id}>"),
+        users => format!(" {direction} {} users", users.len()),
+    }
+}
+
+// One line per entry: date, kind, currency, signed amount, counterparties, memo, reference and transfer id.
+fn history_csv(entries: &[HistoryEntry], currency_names: &CurrencyNames) -> String {
+    let mut csv =
+        String::from("date,kind,currency,amount,counterparties,memo,reference,transfer_id\n");
+
+    for entry in entries {
+        let currency = cached_currency(currency_names, &entry.currency_id);
+
+        csv.push_str(&format!(
+            "{},{},{},{},{},{},{},{}\n",
+            entry.created_at.to_rfc3339(),
+            entry.kind,
+            csv_field(&currency.name),
+            csv_amount(entry.amount),
+            entry
+                .counterparties
+                .iter()
+                .map(|user_id| user_id.to_string())
+                .collect::<Vec<_>>()
+                .join(" "),
+            csv_field(entry.memo.as_deref().unwrap_or_default()),
+            csv_field(entry.reference.as_deref().unwrap_or_default()),
+            entry.transfer_id
+        ));
+    }
+
+    csv
+}
+
+// Formats an amount in sats as coins with 8 decimals. Integer math keeps large amounts exact, a float would not.
+fn csv_amount(sats: i64) -> String {
+    let abs = sats.unsigned_abs();
+
+    format!(
+        "{}{}.{:08}",
+        if sats < 0 { "-" } else { "" },
+        abs / 100_000_000,
+        abs % 100_000_000
+    )
+}
+
+// Quotes a field if it contains a separator, a quote or a line break. A field that starts like a formula gets a `'`
+// in front, so a spreadsheet shows it as text instead of running it.
+fn csv_field(value: &str) -> Cow<str> {
+    let value = if value.starts_with(['=', '+', '-', '@']) {
+        Cow::from(format!("'{value}"))
+    } else {
+        Cow::from(value)
+    };
+
+    if value.contains([',', '"', '\n', '\r']) {
+        Cow::from(format!("\"{}\"", value.replace('"', "\"\"")))
+    } else {
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_fields_are_quoted_when_needed() {
+        assert_eq!(csv_field("VRSC"), "VRSC");
+        assert_eq!(
+            csv_field("fix, see \"ticket\""),
+            "\"fix, see \"\"ticket\"\"\""
+        );
+    }
+
+    #[test]
+    fn csv_formulas_are_escaped() {
+        assert_eq!(csv_field("=HYPERLINK(\"x\")"), "\"'=HYPERLINK(\"\"x\"\")\"");
+        assert_eq!(csv_field("+1"), "'+1");
+        assert_eq!(csv_field("-1"), "'-1");
+        assert_eq!(csv_field("@SUM(A1)"), "'@SUM(A1)");
+        assert_eq!(csv_field("line\rbreak"), "\"line\rbreak\"");
+    }
+
+    #[test]
+    fn csv_amounts_are_exact() {
+        assert_eq!(csv_amount(0), "0.00000000");
+        assert_eq!(csv_amount(-150_000_000), "-1.50000000");
+        assert_eq!(csv_amount(1), "0.00000001");
+        assert_eq!(csv_amount(9_007_199_254_740_993), "90071992.54740993");
+    }
+
+    #[test]
+    fn until_date_is_included() {
+        let until = parse_date(Some("2024-01-31")).unwrap().unwrap();
+
+        assert_eq!(
+            start_of_day(until.checked_add_days(Days::new(1)).unwrap()).to_rfc3339(),
+            "2024-02-01T00:00:00+00:00"
+        );
+        assert!(parse_date(Some("31-01-2024")).is_err());
+    }
+}
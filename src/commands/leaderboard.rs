@@ -0,0 +1,161 @@
+use poise::ChoiceParameter;
+use sqlx::types::chrono::{self, Duration};
+use tracing::*;
+use uuid::Uuid;
+
+use crate::{
+    commands::{autocomplete_currency, resolve_currency},
+    util::database,
+    Context, Error,
+};
+
+const LEADERBOARD_SIZE: i64 = 10;
+
+#[derive(Debug, poise::ChoiceParameter)]
+pub enum Board {
+    #[name = "Top tippers"]
+    Tippers,
+    #[name = "Top receivers"]
+    Receivers,
+    #[name = "Biggest tips"]
+    BiggestTips,
+    #[name = "Most generous reactdrops"]
+    Reactdrops,
+}
+
+#[derive(Debug, poise::ChoiceParameter)]
+pub enum Window {
+    #[name = "This week"]
+    Week,
+    #[name = "This month"]
+    Month,
+    #[name = "All time"]
+    AllTime,
+}
+
+impl Window {
+    fn since(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        let now = chrono::Utc::now();
+
+        match self {
+            Self::Week => Some(now - Duration::days(7)),
+            Self::Month => Some(now - Duration::days(30)),
+            Self::AllTime => None,
+        }
+    }
+}
+
+/// Show who tipped and received the most
+///
+/// -------- :robot: **Leaderboards** --------
+/// `show` lists the top tippers, the top receivers, the biggest single tips or the most generous reactdrop hosts \
+/// of the last 7 days, the last 30 days or all time.
+/// Leaderboards are in VRSC, unless you select another currency in the `currency` parameter.
+///
+/// -------- :robot: **Opting out** --------
+/// Use `optout` if you don't want to show up in the leaderboards. You can opt in again at any time.
+#[instrument(skip(_ctx), fields(request_id = %Uuid::new_v4() ))]
+#[poise::command(
+    slash_command,
+    category = "Miscellaneous",
+    subcommands("leaderboard_show", "leaderboard_optout")
+)]
+pub async fn leaderboard(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Show a leaderboard
+#[instrument(skip(ctx), fields(request_id = %Uuid::new_v4() ))]
+#[poise::command(slash_command, category = "Miscellaneous", rename = "show")]
+async fn leaderboard_show(
+    ctx: Context<'_>,
+    #[description = "The leaderboard to show"] board: Board,
+    #[description = "The period to show (all time if empty)"] window: Option<Window>,
+    #[description = "The currency to show (VRSC if empty)"]
+    #[autocomplete = "autocomplete_currency"]
+    currency: Option<String>,
+) -> Result<(), Error> {
+    let Some(currency) = resolve_currency(ctx, currency).await? else {
+        return Ok(());
+    };
+
+    let pool = &ctx.data().database;
+    let window = window.unwrap_or(Window::AllTime);
+    let since = window.since();
+
+    let lines = match board {
+        Board::Tippers | Board::Reactdrops => {
+            let kind = matches!(board, Board::Reactdrops).then_some("reactdrop");
+
+            database::get_top_tippers(pool, &currency.id, since, kind, LEADERBOARD_SIZE)
+                .await?
+                .into_iter()
+                .map(|(user_id, total)| format!("<@{user_id}>: {}", currency.display(&total)))
+                .collect::<Vec<_>>()
+        }
+        Board::Receivers => {
+            database::get_top_receivers(pool, &currency.id, since, LEADERBOARD_SIZE)
+                .await?
+                .into_iter()
+                .map(|(user_id, total)| format!("<@{user_id}>: {}", currency.display(&total)))
+                .collect::<Vec<_>>()
+        }
+        Board::BiggestTips => {
+            database::get_biggest_tips(pool, &currency.id, since, LEADERBOARD_SIZE)
+                .await?
+                .into_iter()
+                .map(|(user_id, kind, total, receivers)| {
+                    format!(
+                        "<@{user_id}>: {} ({kind}, {receivers} receiver(s))",
+                        currency.display(&total)
+                    )
+                })
+                .collect::<Vec<_>>()
+        }
+    };
+    debug!("{} leaderboard lines", lines.len());
+
+    let description = if lines.is_empty() {
+        String::from("Nothing to show yet")
+    } else {
+        lines
+            .iter()
+            .enumerate()
+            .map(|(i, line)| format!("**{}.** {line}", i + 1))
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+
+    // mentions in embeds don't ping anyone.
+    ctx.send(|reply| {
+        reply.embed(|embed| {
+            embed
+                .title(format!("{} - {}", board.name(), window.name()))
+                .description(description)
+                .footer(|footer| footer.text("Use /leaderboard optout to leave the leaderboards"))
+        })
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Leave or rejoin the leaderboards
+#[instrument(skip(ctx), fields(request_id = %Uuid::new_v4() ))]
+#[poise::command(slash_command, category = "Miscellaneous", rename = "optout")]
+async fn leaderboard_optout(
+    ctx: Context<'_>,
+    #[description = "True to leave the leaderboards, false to show up again"] opt_out: bool,
+) -> Result<(), Error> {
+    database::set_leaderboard_opt_out(&ctx.data().database, &ctx.author().id, opt_out).await?;
+
+    ctx.send(|reply| {
+        reply.ephemeral(true).content(match opt_out {
+            true => "You will not show up in the leaderboards anymore.",
+            false => "You will show up in the leaderboards again.",
+        })
+    })
+    .await?;
+
+    Ok(())
+}
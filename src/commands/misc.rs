@@ -3,6 +3,7 @@ use std::time::Instant;
 use poise::ChoiceParameter;
 use tracing::{instrument, trace};
 use uuid::Uuid;
+use vrsc::Amount;
 
 use crate::{util::database, Context, Error};
 
@@ -104,6 +105,47 @@ pub async fn notifications(ctx: Context<'_>, notifications: Notification) -> Res
     Ok(())
 }
 
+/// Ask for confirmation before big tips
+///
+/// -------- :robot: **Confirmations** --------
+/// Tips above the threshold (in VRSC) have to be confirmed with a button before they are sent. \
+/// Once a threshold is set, tips in other currencies are always confirmed. Leave the threshold empty to never be asked. \
+/// Withdrawals, role tips and amounts entered as a percentage or in USD are always confirmed.
+#[instrument(skip(ctx), fields(request_id = %Uuid::new_v4() ))]
+#[poise::command(track_edits, slash_command, category = "Miscellaneous")]
+pub async fn confirmations(
+    ctx: Context<'_>,
+    #[description = "Confirm tips above this amount of VRSC (never if empty)"]
+    #[min = 0]
+    threshold: Option<f64>,
+) -> Result<(), Error> {
+    let threshold = match threshold.map(Amount::from_vrsc).transpose() {
+        Ok(threshold) => threshold,
+        Err(_) => {
+            ctx.send(|reply| reply.ephemeral(true).content("Invalid threshold"))
+                .await?;
+
+            return Ok(());
+        }
+    };
+
+    let pool = &ctx.data().database;
+    database::set_confirm_threshold(pool, &ctx.author().id, threshold).await?;
+
+    ctx.send(|reply| {
+        reply.ephemeral(true).content(match threshold {
+            Some(threshold) => format!(
+                "You will be asked to confirm tips above {} and all tips in other currencies",
+                ctx.data().native_currency.display(&threshold)
+            ),
+            None => String::from("You will not be asked to confirm tips anymore"),
+        })
+    })
+    .await?;
+
+    Ok(())
+}
+
 #[derive(Debug, ChoiceParameter)]
 pub enum Notification {
     #[name = "All"]
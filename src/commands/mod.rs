@@ -1,13 +1,29 @@
-use poise::serenity_prelude::UserId;
-use tracing::trace;
+use std::time::Duration;
 
-use crate::{Context, Error};
+use poise::serenity_prelude::{self as serenity, UserId};
+use tracing::{trace, warn};
+use vrsc::Amount;
+
+use crate::{
+    util::{
+        amount::{AmountInput, InvalidAmount},
+        currency::Currency,
+        database,
+        ledger::InsufficientBalance,
+    },
+    Context, Error,
+};
 
 pub mod admin;
 pub mod chain;
+pub mod history;
+pub mod leaderboard;
 pub mod misc;
+pub mod offers;
 pub mod tipping;
+pub mod verusid;
 pub mod wallet;
+pub mod watch;
 
 async fn user_blacklisted(ctx: Context<'_>, user_id: UserId) -> Result<bool, Error> {
     let blacklist = &ctx.data().blacklist;
@@ -26,3 +42,259 @@ async fn user_blacklisted(ctx: Context<'_>, user_id: UserId) -> Result<bool, Err
 
     Ok(false)
 }
+
+/// Autocompletes a currency argument with the currencies the bot knows about.
+pub async fn autocomplete_currency<'a>(
+    ctx: Context<'_>,
+    partial: &'a str,
+) -> impl Iterator<Item = String> + 'a {
+    let mut currency_names = ctx
+        .data()
+        .currency_names
+        .read()
+        .unwrap()
+        .values()
+        .cloned()
+        .collect::<Vec<_>>();
+    currency_names.sort_by_key(|name| name.to_lowercase());
+
+    currency_names
+        .into_iter()
+        .filter(move |name| name.to_lowercase().starts_with(&partial.to_lowercase()))
+}
+
+/// Resolves the (optional) currency argument of a command. If no currency was given, the native currency is used.
+///
+/// Lets the user know and returns None if the currency does not exist.
+async fn resolve_currency(
+    ctx: Context<'_>,
+    currency: Option<String>,
+) -> Result<Option<Currency>, Error> {
+    let Some(currency) = currency else {
+        return Ok(Some(ctx.data().native_currency.clone()));
+    };
+
+    if let Some(currency) = ctx.data().currency(&currency)? {
+        trace!("resolved currency {currency:?}");
+
+        return Ok(Some(currency));
+    }
+
+    ctx.send(|reply| {
+        reply
+            .ephemeral(true)
+            .content(format!("Error: `{currency}` is not a known currency."))
+    })
+    .await?;
+
+    Ok(None)
+}
+
+/// An amount a user entered, resolved against their balance and the price.
+struct ResolvedAmount {
+    amount: Amount,
+    /// What the input was converted to, if it was not an exact amount.
+    conversion: Option<String>,
+}
+
+/// Parses an amount argument, see `AmountInput`. Percentages are of the balance in the currency, minus the fee if the fee is
+/// paid in the same currency. USD values are converted with the price from `chain::price` and only work for the native currency.
+///
+/// Lets the user know and returns None if the amount can't be used.
+async fn resolve_amount(
+    ctx: Context<'_>,
+    input: &str,
+    currency: &Currency,
+    fee: Amount,
+) -> Result<Option<ResolvedAmount>, Error> {
+    let native_currency = &ctx.data().native_currency;
+
+    let resolved = match AmountInput::parse(input) {
+        Ok(AmountInput::Usd(_)) if currency != native_currency => Err(InvalidAmount(format!(
+            "USD amounts only work for {}",
+            native_currency.name
+        ))),
+        Ok(parsed) => {
+            let balance = database::get_balance_for_user(
+                &ctx.data().database,
+                &ctx.author().id,
+                &currency.id,
+            )
+            .await?
+            .unwrap_or(0);
+            let spendable = match currency == native_currency {
+                true => Amount::from_sat(balance.saturating_sub(fee.as_sat())),
+                false => Amount::from_sat(balance),
+            };
+
+            let usd_price = match parsed {
+                AmountInput::Usd(_) => chain::fetch_usd_price()
+                    .await
+                    .map_err(|e| warn!("could not get the USD price: {e:?}"))
+                    .ok(),
+                _ => None,
+            };
+
+            parsed
+                .resolve(spendable, usd_price)
+                .map(|amount| ResolvedAmount {
+                    amount,
+                    conversion: parsed.is_converted().then(|| match usd_price {
+                        Some(price) => format!(
+                            "`{input}` is {} (1 {} = ${price:.4})",
+                            currency.display(&amount),
+                            currency.name
+                        ),
+                        None => format!("`{input}` is {}", currency.display(&amount)),
+                    }),
+                })
+        }
+        Err(e) => Err(e),
+    };
+
+    match resolved {
+        Ok(resolved) => {
+            trace!("resolved amount {input} to {}", resolved.amount);
+
+            Ok(Some(resolved))
+        }
+        Err(InvalidAmount(message)) => {
+            ctx.send(|reply| reply.ephemeral(true).content(message))
+                .await?;
+
+            Ok(None)
+        }
+    }
+}
+
+/// Puts what an amount was converted to above the text of a confirmation, so the user sees the amount they confirm.
+fn with_conversion(conversion: Option<&str>, content: &str) -> String {
+    match conversion {
+        Some(conversion) => format!("{conversion}\n{content}"),
+        None => content.to_owned(),
+    }
+}
+
+// How long the Confirm and Cancel buttons can be pressed.
+const CONFIRM_TIMEOUT_SECS: u64 = 60;
+
+/// Asks the user to confirm an action with Confirm and Cancel buttons. Returns true only if the user pressed Confirm in time:
+/// after the timeout, nothing happens.
+async fn confirm(ctx: Context<'_>, content: &str) -> Result<bool, Error> {
+    let ctx_id = ctx.id();
+    let confirm_id = format!("{ctx_id}confirm");
+    let cancel_id = format!("{ctx_id}cancel");
+
+    let reply_handle = ctx
+        .send(|reply| {
+            reply
+                .ephemeral(true)
+                .content(content)
+                .components(|components| {
+                    components.create_action_row(|row| {
+                        row.create_button(|button| {
+                            button
+                                .custom_id(&confirm_id)
+                                .label("Confirm")
+                                .style(serenity::ButtonStyle::Success)
+                        })
+                        .create_button(|button| {
+                            button
+                                .custom_id(&cancel_id)
+                                .label("Cancel")
+                                .style(serenity::ButtonStyle::Secondary)
+                        })
+                    })
+                })
+        })
+        .await?;
+
+    let press = serenity::CollectComponentInteraction::new(ctx.serenity_context())
+        .author_id(ctx.author().id)
+        .filter(move |press| press.data.custom_id.starts_with(&ctx_id.to_string()))
+        .timeout(Duration::from_secs(CONFIRM_TIMEOUT_SECS))
+        .await;
+
+    let confirmed = press
+        .as_ref()
+        .map_or(false, |press| press.data.custom_id == confirm_id);
+    trace!("confirmed: {confirmed}");
+
+    // the buttons are removed, so they can't be pressed again.
+    match press {
+        Some(press) => {
+            press
+                .create_interaction_response(ctx.http(), |response| {
+                    response
+                        .kind(serenity::InteractionResponseType::UpdateMessage)
+                        .interaction_response_data(|data| {
+                            data.content(format!(
+                                "{content}\n\n{}",
+                                match confirmed {
+                                    true => "Confirmed.",
+                                    false => "Cancelled, nothing was done.",
+                                }
+                            ))
+                            .components(|components| components)
+                        })
+                })
+                .await?;
+        }
+        None => {
+            reply_handle
+                .edit(ctx, |reply| {
+                    reply
+                        .content(format!("{content}\n\nTimed out, nothing was done."))
+                        .components(|components| components)
+                })
+                .await?;
+        }
+    }
+
+    Ok(confirmed)
+}
+
+/// Asks for confirmation if the amount is above the threshold the user set with `/confirmations`, or if the amount was
+/// converted from a percentage or a USD value: the user has not seen the amount yet in that case.
+/// The threshold is in the native currency, so amounts of other currencies are always confirmed once a threshold is set.
+/// Otherwise, the action goes ahead without asking.
+async fn confirm_above_threshold(
+    ctx: Context<'_>,
+    currency: &Currency,
+    amount: Amount,
+    conversion: Option<&str>,
+    content: &str,
+) -> Result<bool, Error> {
+    if conversion.is_some() {
+        return confirm(ctx, &with_conversion(conversion, content)).await;
+    }
+
+    let threshold = database::get_confirm_threshold(&ctx.data().database, &ctx.author().id).await?;
+
+    match threshold {
+        Some(threshold) if currency.id != ctx.data().native_currency.id || amount > threshold => {
+            confirm(ctx, content).await
+        }
+        _ => Ok(true),
+    }
+}
+
+/// Balances are checked before a command starts, but another command of the same user can spend the balance in the meantime.
+/// The debit then fails with `InsufficientBalance`: let the user know instead of sending the error to the admins.
+///
+/// Any other error is returned.
+async fn reply_if_insufficient_balance(ctx: Context<'_>, error: Error) -> Result<(), Error> {
+    if !error.is::<InsufficientBalance>() {
+        return Err(error);
+    }
+
+    trace!("{error}");
+    ctx.send(|reply| {
+        reply.ephemeral(true).content(
+            "Your balance is insufficient, it was used by another command in the meantime.",
+        )
+    })
+    .await?;
+
+    Ok(())
+}
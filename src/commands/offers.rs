@@ -0,0 +1,312 @@
+use std::{str::FromStr, time::Duration};
+
+use poise::serenity_prelude::{self as serenity, CreateComponents, CreateEmbed};
+use tracing::*;
+use uuid::Uuid;
+use vrsc::Address;
+use vrsc_rpc::client::RpcApi;
+
+use crate::{
+    commands::{chain::time_until_block, user_blacklisted},
+    util::{
+        currency::{lookup_currency, CurrencyNames},
+        database,
+        identity::{get_identity, identity_name},
+        offers::{get_offers, offer_side, Offer},
+    },
+    Context, Error,
+};
+
+const PAGE_SIZE: usize = 5;
+// The most identities a user can watch the offers of.
+const MAX_OFFER_WATCHES: i64 = 10;
+
+/// See the open offers on the marketplace
+///
+/// -------- :robot: **Open offers** --------
+/// `show` lists the open offers for a currency or a VerusID: what is offered, what is asked in return, \
+/// and when the offer expires. Use the buttons to go through the offers.
+///
+/// -------- :robot: **Watching offers** --------
+/// `watch` sends you a DM when a new offer for a VerusID appears, `unwatch` stops that. \
+/// You can watch the offers of up to 10 VerusIDs.
+#[instrument(skip(_ctx), fields(request_id = %Uuid::new_v4() ))]
+#[poise::command(
+    slash_command,
+    category = "Marketplace",
+    subcommands("offers_show", "offers_watch", "offers_unwatch")
+)]
+pub async fn offers(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Show the open offers for a currency or a VerusID
+#[instrument(skip(ctx), fields(request_id = %Uuid::new_v4() ))]
+#[poise::command(slash_command, category = "Marketplace", rename = "show")]
+async fn offers_show(
+    ctx: Context<'_>,
+    #[description = "A currency, or a VerusID (name@)"] query: String,
+) -> Result<(), Error> {
+    let client = ctx.data().verus()?;
+    let currency_names = &ctx.data().currency_names;
+    let query = query.trim();
+
+    // names ending in @ are identities, anything else is looked up as a currency first.
+    let currency = match query.ends_with('@') {
+        true => None,
+        false => lookup_currency(&client, currency_names, query),
+    };
+    let (title, offers) = match currency {
+        Some(currency) => (
+            currency.name.clone(),
+            get_offers(&client, &currency.id.to_string(), true)?,
+        ),
+        None => {
+            let Some(identity) = get_identity(&client, &identity_name(query)) else {
+                ctx.send(|reply| {
+                    reply
+                        .ephemeral(true)
+                        .content(format!("No currency or VerusID `{query}` found"))
+                })
+                .await?;
+
+                return Ok(());
+            };
+
+            (
+                identity.fullyqualifiedname.clone(),
+                get_offers(
+                    &client,
+                    &identity.identity.identityaddress.to_string(),
+                    false,
+                )?,
+            )
+        }
+    };
+    debug!("{} open offers for {title}", offers.len());
+
+    // currencies that are not cached yet are looked up, so the offers show their names instead of their ids.
+    for side in offers
+        .iter()
+        .flat_map(|offer| [&offer.offer, &offer.accept])
+    {
+        for id in side.as_object().into_iter().flat_map(|side| side.keys()) {
+            if Address::from_str(id).is_ok() {
+                lookup_currency(&client, currency_names, id);
+            }
+        }
+    }
+
+    let height = client.get_blockchain_info()?.blocks;
+    let pages = ((offers.len() + PAGE_SIZE - 1) / PAGE_SIZE).max(1);
+    let mut page = 0;
+
+    let ctx_id = ctx.id();
+    ctx.send(|reply| {
+        reply
+            .embed(|embed| {
+                offers_embed(embed, &title, &offers, currency_names, height, page, pages)
+            })
+            .components(|components| offers_buttons(components, ctx_id, page, pages))
+    })
+    .await?;
+
+    while let Some(press) = serenity::CollectComponentInteraction::new(ctx.serenity_context())
+        .filter(move |press| press.data.custom_id.starts_with(&ctx_id.to_string()))
+        .timeout(Duration::from_secs(10 * 60))
+        .await
+    {
+        if press.data.custom_id == format!("{ctx_id}next") {
+            page = (page + 1).min(pages - 1);
+        } else if press.data.custom_id == format!("{ctx_id}prev") {
+            page = page.saturating_sub(1);
+        } else {
+            continue;
+        }
+
+        press
+            .create_interaction_response(ctx.http(), |response| {
+                response
+                    .kind(serenity::InteractionResponseType::UpdateMessage)
+                    .interaction_response_data(|data| {
+                        data.embed(|embed| {
+                            offers_embed(
+                                embed,
+                                &title,
+                                &offers,
+                                currency_names,
+                                height,
+                                page,
+                                pages,
+                            )
+                        })
+                        .components(|components| offers_buttons(components, ctx_id, page, pages))
+                    })
+            })
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Get a DM when a new offer for a VerusID appears
+#[instrument(skip(ctx), fields(request_id = %Uuid::new_v4() ))]
+#[poise::command(slash_command, category = "Marketplace", rename = "watch")]
+async fn offers_watch(
+    ctx: Context<'_>,
+    #[description = "The VerusID (name@) or its i-address"] identity: String,
+) -> Result<(), Error> {
+    if user_blacklisted(ctx, ctx.author().id).await? {
+        return Ok(());
+    }
+
+    let pool = &ctx.data().database;
+    let client = ctx.data().verus()?;
+    let identity = identity_name(&identity);
+
+    let Some(result) = get_identity(&client, &identity) else {
+        ctx.send(|reply| {
+            reply
+                .ephemeral(true)
+                .content(format!("VerusID `{identity}` not found"))
+        })
+        .await?;
+
+        return Ok(());
+    };
+
+    if database::count_offer_watches_for_user(pool, &ctx.author().id).await? >= MAX_OFFER_WATCHES {
+        ctx.send(|reply| {
+            reply.ephemeral(true).content(format!(
+                "You can watch the offers of at most {MAX_OFFER_WATCHES} VerusIDs. Use `/offers unwatch` to stop watching one."
+            ))
+        })
+        .await?;
+
+        return Ok(());
+    }
+
+    let identity_address = &result.identity.identityaddress;
+    // the offers that are open now are known, only offers after this are sent.
+    let offers = get_offers(&client, &identity_address.to_string(), false)?
+        .into_iter()
+        .map(|offer| offer.txid)
+        .collect::<Vec<_>>();
+    database::insert_offer_watch(
+        pool,
+        &ctx.author().id,
+        identity_address,
+        &result.fullyqualifiedname,
+        &serde_json::to_string(&offers)?,
+    )
+    .await?;
+    debug!(
+        "{} watches the offers for {identity_address}",
+        ctx.author().id
+    );
+
+    ctx.send(|reply| {
+        reply.ephemeral(true).content(format!(
+            "You are watching the offers for `{}`. You will get a DM when a new offer appears, so make sure you accept DMs from this server.",
+            result.fullyqualifiedname
+        ))
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Stop watching the offers for a VerusID
+#[instrument(skip(ctx), fields(request_id = %Uuid::new_v4() ))]
+#[poise::command(slash_command, category = "Marketplace", rename = "unwatch")]
+async fn offers_unwatch(
+    ctx: Context<'_>,
+    #[description = "The VerusID (name@) or its i-address"] identity: String,
+) -> Result<(), Error> {
+    let identity = identity_name(&identity);
+    // watches are stored with the fully qualified name, so a name is looked up to find its address.
+    let query = get_identity(&ctx.data().verus()?, &identity)
+        .map(|result| result.identity.identityaddress.to_string())
+        .unwrap_or_else(|| identity.clone());
+
+    let stopped =
+        database::delete_offer_watch(&ctx.data().database, &ctx.author().id, &query).await?;
+
+    ctx.send(|reply| {
+        reply.ephemeral(true).content(match stopped {
+            true => format!("You stopped watching the offers for `{identity}`"),
+            false => format!("You don't watch the offers for `{identity}`"),
+        })
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// One line per side of the offer, and when it expires.
+fn offer_description(offer: &Offer, currency_names: &CurrencyNames, height: u64) -> String {
+    let expiry = match time_until_block(height, offer.blockexpiry) {
+        Some(time) => format!("block {} (<t:{}:R>)", offer.blockexpiry, time.timestamp()),
+        None => format!("block {}", offer.blockexpiry),
+    };
+
+    format!(
+        "Offered: {}\nAsked: {}\nExpires: {expiry}\nTransaction: `{}`",
+        offer_side(&offer.offer, currency_names),
+        offer_side(&offer.accept, currency_names),
+        offer.txid
+    )
+}
+
+fn offers_embed<'a>(
+    embed: &'a mut CreateEmbed,
+    title: &str,
+    offers: &[Offer],
+    currency_names: &CurrencyNames,
+    height: u64,
+    page: usize,
+    pages: usize,
+) -> &'a mut CreateEmbed {
+    embed.title(format!("Open offers for {title}"));
+
+    if offers.is_empty() {
+        embed.description("There are no open offers");
+    }
+
+    for (i, offer) in offers
+        .iter()
+        .enumerate()
+        .skip(page * PAGE_SIZE)
+        .take(PAGE_SIZE)
+    {
+        embed.field(
+            format!("Offer {}", i + 1),
+            offer_description(offer, currency_names, height),
+            false,
+        );
+    }
+
+    embed.footer(|footer| footer.text(format!("Page {} of {pages}", page + 1)))
+}
+
+fn offers_buttons(
+    components: &mut CreateComponents,
+    ctx_id: u64,
+    page: usize,
+    pages: usize,
+) -> &mut CreateComponents {
+    components.create_action_row(|row| {
+        row.create_button(|button| {
+            button
+                .custom_id(format!("{ctx_id}prev"))
+                .label("Previous")
+                .disabled(page == 0)
+        })
+        .create_button(|button| {
+            button
+                .custom_id(format!("{ctx_id}next"))
+                .label("Next")
+                .disabled(page + 1 >= pages)
+        })
+    })
+}
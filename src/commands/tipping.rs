@@ -1,5 +1,9 @@
 use ::chrono::Duration;
-use poise::serenity_prelude::{self, CacheHttp, ChannelId, ReactionType, RoleId, UserId};
+use std::str::FromStr;
+
+use poise::serenity_prelude::{
+    self, CacheHttp, ChannelId, MessageId, ReactionType, RoleId, UserId,
+};
 
 use sqlx::{types::chrono, PgPool};
 use tracing::*;
@@ -7,12 +11,28 @@ use uuid::Uuid;
 use vrsc::Amount;
 
 use crate::{
-    commands::{misc::Notification, user_blacklisted},
-    util::database::{self},
+    commands::{
+        autocomplete_currency, confirm, confirm_above_threshold, misc::Notification,
+        reply_if_insufficient_balance, resolve_amount, resolve_currency, user_blacklisted,
+        with_conversion,
+    },
+    reactdrop::{DropMode, Reactdrop, ReactdropRules, ReactdropState, CAPTCHA_BUTTON_ID},
+    util::{
+        amount::{InvalidAmount, Split},
+        currency::{cached_currency, Currency},
+        database::{self},
+    },
     wallet::get_and_check_balance,
     Context, Error,
 };
 
+// The longest memo that can be added to a tip.
+const MAX_MEMO_LENGTH: usize = 200;
+// The maximum number of members Discord returns at once.
+const MEMBERS_PAGE_SIZE: u64 = 1000;
+// The longest a reactdrop can last, in minutes (7 days).
+const MAX_REACTDROP_MINUTES: i64 = 7 * 24 * 60;
+
 /// Tip a user or a role
 ///
 /// -------- :robot: **Tipping a user** --------
@@ -20,7 +40,22 @@ use crate::{
 ///
 /// -------- :robot: **Tipping a role** --------
 /// Tip a role by entering and selecting the role name. The role name can be any role, even the @everyone role. \
-/// The amount entered in the second parameter will be split evenly among the members of the role.
+/// The amount entered in the second parameter will be split evenly among the members of the role. \
+/// Set `online_only` to only tip the members that are online, like @here. \
+/// Bots, suspended users and you yourself don't get a share. Role tips always need to be confirmed.
+///
+/// Tips above the threshold you set with `/confirmations` need to be confirmed too.
+///
+/// -------- :robot: **Memo** --------
+/// Add a `memo` to say what the tip is for. It is shown with the tip, in the DM to the receivers and in `/history`.
+///
+/// -------- :robot: **Amount** --------
+/// Enter an amount in coins (`1.5`, `10k`) or sats (`1000 sats`), a part of your balance (`50%`, `half`, `all`) \
+/// or a value in USD (`$5`), which is converted with the current VRSC price. \
+/// You confirm the converted amount before it is tipped.
+///
+/// -------- :robot: **Currency** --------
+/// Tips are in VRSC, unless you select another currency in the `currency` parameter.
 #[instrument(skip(_ctx), fields(request_id = %Uuid::new_v4() ))]
 #[poise::command(slash_command, category = "Tipping", subcommands("role", "user"))]
 pub async fn tip(_ctx: Context<'_>) -> Result<(), Error> {
@@ -33,43 +68,96 @@ pub async fn tip(_ctx: Context<'_>) -> Result<(), Error> {
 async fn role(
     ctx: Context<'_>,
     #[description = "Enter and select the role you want to tip"] role: serenity_prelude::Role,
-    #[description = "The amount you want to tip"]
-    #[min = 0.5]
-    tip_amount: f64,
+    #[description = "The amount you want to tip, for example 1.5, 1000 sats, 50% or $5"]
+    tip_amount: String,
+    #[description = "The currency you want to tip (VRSC if empty)"]
+    #[autocomplete = "autocomplete_currency"]
+    currency: Option<String>,
+    #[description = "Only tip members that are online, like @here"] online_only: Option<bool>,
+    #[description = "A message to add to the tip"] memo: Option<String>,
 ) -> Result<(), Error> {
     if user_blacklisted(ctx, ctx.author().id).await? {
         return Ok(());
     }
 
+    let memo = match check_memo(memo) {
+        Ok(memo) => memo,
+        Err(message) => {
+            ctx.send(|reply| reply.ephemeral(true).content(message))
+                .await?;
+
+            return Ok(());
+        }
+    };
+
+    let Some(currency) = resolve_currency(ctx, currency).await? else {
+        return Ok(());
+    };
+
     debug!("role: {:?}", role.id);
-    let tip_amount = Amount::from_vrsc(tip_amount)?;
+    let Some(resolved) = resolve_amount(ctx, &tip_amount, &currency, Amount::ZERO).await? else {
+        return Ok(());
+    };
+    let tip_amount = resolved.amount;
 
-    if get_and_check_balance(&ctx, tip_amount, Amount::ZERO)
-        .await?
-        .is_some()
-    {
+    if let Some(balance) = get_and_check_balance(&ctx, &currency, tip_amount, Amount::ZERO).await? {
         trace!("tipper has enough balance");
 
         if let Some(guild) = ctx.guild() {
             debug!("guildid: {:?}", guild.id);
-            let guild_members = guild.members.values();
-            let role_members = guild_members
-                .filter(
-                    |m| m.roles.contains(&role.id) || &role.id == &RoleId(guild.id.0), // @everyone role_id (same as guild_id) does never get tips
-                )
+            // the @everyone role has the same id as the guild: every member has it.
+            let everyone = role.id == RoleId(guild.id.0);
+            let blacklisted = database::get_blacklisted_users(&ctx.data().database).await?;
+
+            let role_members = guild_members(ctx, &guild)
+                .await?
+                .into_iter()
+                .filter(|m| everyone || m.roles.contains(&role.id))
+                .filter(|m| !m.user.bot && m.user.id != ctx.author().id)
+                .filter(|m| !blacklisted.contains(&m.user.id))
+                .filter(|m| !online_only.unwrap_or(false) || is_online(&guild, m.user.id))
                 .map(|m| m.user.id)
                 .collect::<Vec<_>>();
+            debug!("{} members to tip", role_members.len());
+
+            let Some(split) = split_or_reply(ctx, tip_amount, role_members.len()).await? else {
+                return Ok(());
+            };
 
-            tip_multiple_users(
+            if !confirm(
+                ctx,
+                &with_conversion(
+                    resolved.conversion.as_deref(),
+                    &format!(
+                        "You are about to tip {} to {} members of <@&{}> ({} each).\nBalance after the tip: {}",
+                        currency.display(&split.total()),
+                        role_members.len(),
+                        role.id,
+                        currency.display(&split.share),
+                        currency.display(&balance.checked_sub(split.total()).unwrap_or(Amount::ZERO))
+                    ),
+                ),
+            )
+            .await?
+            {
+                return Ok(());
+            }
+
+            if let Err(e) = tip_multiple_users(
                 &ctx.data().database,
                 ctx.author().id,
                 ctx.http(),
                 &ctx.channel_id(),
                 &role_members,
-                &tip_amount,
+                &currency,
+                &split,
                 "role",
+                memo.as_deref(),
             )
-            .await?;
+            .await
+            {
+                return reply_if_insufficient_balance(ctx, e).await;
+            }
 
             return Ok(());
         } else {
@@ -89,25 +177,92 @@ async fn role(
     Ok(())
 }
 
+// Returns the members of a guild. The cache is used if it has every member, otherwise the members are fetched
+// page by page, because on large guilds the cache only has the members the bot has seen.
+async fn guild_members(
+    ctx: Context<'_>,
+    guild: &serenity_prelude::Guild,
+) -> Result<Vec<serenity_prelude::Member>, Error> {
+    if guild.members.len() as u64 >= guild.member_count {
+        return Ok(guild.members.values().cloned().collect());
+    }
+
+    debug!(
+        "{} of {} members cached, fetching members",
+        guild.members.len(),
+        guild.member_count
+    );
+
+    let mut members = vec![];
+    let mut after = None;
+
+    loop {
+        let page = guild
+            .id
+            .members(ctx.http(), Some(MEMBERS_PAGE_SIZE), after)
+            .await?;
+        let last_page = (page.len() as u64) < MEMBERS_PAGE_SIZE;
+        after = page.last().map(|member| member.user.id);
+        members.extend(page);
+
+        if last_page {
+            return Ok(members);
+        }
+    }
+}
+
+// Members without a presence, or with an offline or invisible status, are not online.
+fn is_online(guild: &serenity_prelude::Guild, user_id: UserId) -> bool {
+    guild.presences.get(&user_id).map_or(false, |presence| {
+        ![
+            serenity_prelude::OnlineStatus::Offline,
+            serenity_prelude::OnlineStatus::Invisible,
+        ]
+        .contains(&presence.status)
+    })
+}
+
 /// Tip a user by entering and selecting the user's name.
 #[instrument(skip(ctx), fields(request_id = %Uuid::new_v4() ))]
 #[poise::command(slash_command, category = "Tipping")]
 async fn user(
     ctx: Context<'_>,
     #[description = "Enter and select the user you want to tip"] user: serenity_prelude::User,
-    #[description = "The amount you want to tip"] tip_amount: f64,
+    #[description = "The amount you want to tip, for example 1.5, 1000 sats, 50% or $5"] tip_amount: String,
+    #[description = "The currency you want to tip (VRSC if empty)"]
+    #[autocomplete = "autocomplete_currency"]
+    currency: Option<String>,
+    #[description = "A message to add to the tip"] memo: Option<String>,
 ) -> Result<(), Error> {
     if user_blacklisted(ctx, ctx.author().id).await? {
         return Ok(());
     }
 
-    let tip_amount = Amount::from_vrsc(tip_amount)?;
+    let memo = match check_memo(memo) {
+        Ok(memo) => memo,
+        Err(message) => {
+            ctx.send(|reply| reply.ephemeral(true).content(message))
+                .await?;
+
+            return Ok(());
+        }
+    };
+
+    let Some(currency) = resolve_currency(ctx, currency).await? else {
+        return Ok(());
+    };
+
+    let Some(resolved) = resolve_amount(ctx, &tip_amount, &currency, Amount::ZERO).await? else {
+        return Ok(());
+    };
+    let tip_amount = resolved.amount;
 
     debug!(
-        "user {} ({}) wants to tip {} with {tip_amount}",
+        "user {} ({}) wants to tip {} with {tip_amount} of {}",
         ctx.author().name,
         ctx.author().id,
-        user.id
+        user.id,
+        currency.name
     );
 
     // check if the tipper has enough balance
@@ -115,26 +270,62 @@ async fn user(
 
     let pool = &ctx.data().database;
 
-    if get_and_check_balance(&ctx, tip_amount, Amount::ZERO)
-        .await?
-        .is_some()
-    {
+    if let Some(balance) = get_and_check_balance(&ctx, &currency, tip_amount, Amount::ZERO).await? {
         trace!("tipper has enough balance");
 
-        database::process_a_tip(pool, &ctx.author().id, &vec![user.id], &tip_amount).await?;
+        if !confirm_above_threshold(
+            ctx,
+            &currency,
+            tip_amount,
+            resolved.conversion.as_deref(),
+            &format!(
+                "You are about to tip {} to <@{}>.\nBalance after the tip: {}",
+                currency.display(&tip_amount),
+                user.id,
+                currency.display(&balance.checked_sub(tip_amount).unwrap_or(Amount::ZERO))
+            ),
+        )
+        .await?
+        {
+            return Ok(());
+        }
 
-        // tips are only stored one way: counterparty is the sender of the tip.
         let tip_event_id = Uuid::new_v4();
+
+        if let Err(e) = database::process_a_tip(
+            pool,
+            &tip_event_id,
+            &ctx.author().id,
+            &vec![user.id],
+            &currency.id,
+            &tip_amount,
+        )
+        .await
+        {
+            return reply_if_insufficient_balance(ctx, e).await;
+        }
+
+        // tips are only stored one way: counterparty is the sender of the tip.
         database::store_tip_transactions(
             pool,
             &tip_event_id,
             &vec![user.id],
             "direct",
+            &currency.id,
             &tip_amount,
             ctx.author().id,
+            memo.as_deref(),
         )
         .await?;
 
+        let tip_amount = currency.display(&tip_amount);
+        let memo_line = memo_line(memo.as_deref());
+        // a linked VerusID is shown next to the receiver.
+        let receiver_id = database::get_linked_verusid(pool, &user.id)
+            .await?
+            .map(|(identity, _)| format!(" (`{identity}`)"))
+            .unwrap_or_default();
+
         match database::get_notification_settings(&pool, &vec![user.id])
             .await?
             .first()
@@ -145,7 +336,7 @@ async fn user(
                         // send a message in the same channel:
                         ctx.send(|reply| {
                             reply.ephemeral(false).content(format!(
-                                "<@{}> just tipped <@{}> {tip_amount}!",
+                                "<@{}> just tipped <@{}>{receiver_id} {tip_amount}!{memo_line}",
                                 &ctx.author().id,
                                 user.id
                             ))
@@ -156,7 +347,7 @@ async fn user(
                         // send a non-pinging message in the channel:
                         ctx.send(|reply| {
                             reply.ephemeral(false).content(format!(
-                                "<@{}> just tipped `{}` {tip_amount}!",
+                                "<@{}> just tipped `{}`{receiver_id} {tip_amount}!{memo_line}",
                                 &ctx.author().id,
                                 user.tag()
                             ))
@@ -165,7 +356,7 @@ async fn user(
                         // send a notification in dm:
                         user.dm(&ctx.http(), |message| {
                             message.content(format!(
-                                "You just got tipped {tip_amount} from <@{}>!",
+                                "You just got tipped {tip_amount} from <@{}>!{memo_line}",
                                 &ctx.author().id,
                             ))
                         })
@@ -175,7 +366,7 @@ async fn user(
                         // send a non-pinging message in the channel:
                         ctx.send(|reply| {
                             reply.ephemeral(false).content(format!(
-                                "<@{}> just tipped `{}` {tip_amount}!",
+                                "<@{}> just tipped `{}`{receiver_id} {tip_amount}!{memo_line}",
                                 &ctx.author().id,
                                 user.tag()
                             ))
@@ -189,7 +380,7 @@ async fn user(
 
                 ctx.send(|reply| {
                     reply.ephemeral(false).content(format!(
-                        "<@{}> just tipped <@{}> {tip_amount}!",
+                        "<@{}> just tipped <@{}>{receiver_id} {tip_amount}!{memo_line}",
                         &ctx.author().id,
                         user.id
                     ))
@@ -197,10 +388,110 @@ async fn user(
                 .await?;
             }
         }
+    }
+
+    Ok(())
+}
+
+/// Split an amount among the users that recently posted in this channel
+///
+/// -------- :robot: **Rain** --------
+/// The amount is split evenly among everyone who posted a message in this channel in the last `minutes` minutes, \
+/// except you. Only messages posted since the bot last started are counted.
+///
+/// Rain is in VRSC, unless you select another currency in the `currency` parameter.
+#[instrument(skip(ctx), fields(request_id = %Uuid::new_v4() ))]
+#[poise::command(slash_command, category = "Tipping", guild_only)]
+pub async fn rain(
+    ctx: Context<'_>,
+    #[description = "The amount you want to split, for example 1.5, 1000 sats, 50% or $5"]
+    amount: String,
+    #[description = "Users that posted in this channel in the last number of minutes get a share"]
+    #[min = 1]
+    #[max = 1440]
+    minutes: u32,
+    #[description = "The currency you want to rain (VRSC if empty)"]
+    #[autocomplete = "autocomplete_currency"]
+    currency: Option<String>,
+) -> Result<(), Error> {
+    if user_blacklisted(ctx, ctx.author().id).await? {
+        return Ok(());
+    }
+
+    let Some(currency) = resolve_currency(ctx, currency).await? else {
+        return Ok(());
+    };
+
+    let since = chrono::Utc::now() - Duration::minutes(minutes as i64);
+    let blacklisted = database::get_blacklisted_users(&ctx.data().database).await?;
+    let users = ctx
+        .data()
+        .channel_activity
+        .active_users(ctx.channel_id(), since)
+        .into_iter()
+        .filter(|user_id| user_id != &ctx.author().id)
+        .filter(|user_id| !blacklisted.contains(user_id))
+        .collect::<Vec<_>>();
+    debug!("active users in the last {minutes} minutes: {users:?}");
+
+    if users.is_empty() {
+        ctx.send(|reply| {
+            reply.ephemeral(true).content(format!(
+                "Nobody else posted in this channel in the last {minutes} minute(s)."
+            ))
+        })
+        .await?;
 
         return Ok(());
     }
 
+    let Some(resolved) = resolve_amount(ctx, &amount, &currency, Amount::ZERO).await? else {
+        return Ok(());
+    };
+    let amount = resolved.amount;
+
+    let Some(split) = split_or_reply(ctx, amount, users.len()).await? else {
+        return Ok(());
+    };
+
+    if let Some(balance) =
+        get_and_check_balance(&ctx, &currency, split.total(), Amount::ZERO).await?
+    {
+        if !confirm_above_threshold(
+            ctx,
+            &currency,
+            split.total(),
+            resolved.conversion.as_deref(),
+            &format!(
+                "You are about to rain {} on {} users ({} each).\nBalance after the rain: {}",
+                currency.display(&split.total()),
+                users.len(),
+                currency.display(&split.share),
+                currency.display(&balance.checked_sub(split.total()).unwrap_or(Amount::ZERO))
+            ),
+        )
+        .await?
+        {
+            return Ok(());
+        }
+
+        if let Err(e) = tip_multiple_users(
+            &ctx.data().database,
+            ctx.author().id,
+            ctx.http(),
+            &ctx.channel_id(),
+            &users,
+            &currency,
+            &split,
+            "rain",
+            None,
+        )
+        .await
+        {
+            return reply_if_insufficient_balance(ctx, e).await;
+        }
+    }
+
     Ok(())
 }
 
@@ -210,6 +501,18 @@ pub enum Hms {
     Minutes,
 }
 
+#[derive(Debug, poise::ChoiceParameter)]
+pub enum Mode {
+    #[name = "Split evenly"]
+    Split,
+    #[name = "Random winners"]
+    RandomWinners,
+    #[name = "First reactors"]
+    FirstReactors,
+    #[name = "Weighted by role"]
+    WeightedByRole,
+}
+
 /// Start a giveaway where users need to react to a message to participate
 ///
 /// -------- :robot: **Reactdrop** --------
@@ -217,27 +520,163 @@ pub enum Hms {
 /// It can be any Emoji, as long as the emoji is in the current server.
 ///
 /// The amount is entered in the second parameter. This amount will be split among the participants of the reactdrop when it ends.
+/// It is reserved from your balance when the reactdrop starts, and returned to you if nobody reacts.
+/// Reactdrops are in VRSC, unless you select another currency in the `currency` parameter.
+/// A reactdrop lasts at most 7 days.
+///
+/// Optionally, participants can be limited to members with a role, to accounts and members of a minimum age (in days) \
+/// and to a maximum number of participants. With `captcha`, participants must also press Verify under the reactdrop \
+/// and solve a captcha.
+///
+/// -------- :robot: **Modes** --------
+/// By default the amount is split evenly. Other modes are: \
+/// `Random winners`: the amount is split among `winners` participants, drawn using the hash of the first block after the reactdrop ends. \
+/// `First reactors`: the amount is split among the first `winners` participants to react. \
+/// `Weighted by role`: members with `weighted role` get `role weight` shares (2 if empty), everyone else 1 share.
+///
+/// -------- :robot: **Managing reactdrops** --------
+/// `list` shows your running reactdrops. \
+/// `cancel` stops a running reactdrop and returns the amount to your balance. \
+/// `extend` adds time to a running reactdrop, as long as it still ends within 7 days. \
+/// Use the message ID or the message link of the reactdrop to cancel or extend it.
+#[instrument(skip(_ctx), fields(request_id = %Uuid::new_v4() ))]
+#[poise::command(
+    slash_command,
+    category = "Tipping",
+    subcommands(
+        "reactdrop_start",
+        "reactdrop_cancel",
+        "reactdrop_extend",
+        "reactdrop_list"
+    )
+)]
+pub async fn reactdrop(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Start a reactdrop
 #[instrument(skip(ctx), fields(request_id = %Uuid::new_v4() ))]
-#[poise::command(slash_command, category = "Tipping")]
-pub async fn reactdrop(
+#[poise::command(slash_command, category = "Tipping", rename = "start")]
+async fn reactdrop_start(
     ctx: Context<'_>,
     #[description = "The emoji users need to react with"] emoji: String,
-    #[min = 0.1]
-    #[description = "The amount you want to give away"]
-    amount: f64,
-    #[min = 1] time: i64,
+    #[description = "The amount you want to give away, for example 1.5, 1000 sats, 50% or $5"]
+    amount: String,
+    #[min = 1]
+    #[max = 10080]
+    time: i64,
     #[description = "The time in hours, minutes or seconds"] hms: Hms,
+    #[description = "The currency you want to give away (VRSC if empty)"]
+    #[autocomplete = "autocomplete_currency"]
+    currency: Option<String>,
+    #[description = "Only members with this role can participate"] required_role: Option<
+        serenity_prelude::Role,
+    >,
+    #[min = 1]
+    #[description = "The minimum age in days of the Discord account of participants"]
+    min_account_age: Option<u32>,
+    #[min = 1]
+    #[description = "The minimum number of days participants have been a member of this server"]
+    min_member_age: Option<u32>,
+    #[min = 1]
+    #[description = "The maximum number of participants"]
+    max_participants: Option<u32>,
+    #[description = "Participants must solve a captcha (no captcha if empty)"] captcha: Option<
+        bool,
+    >,
+    #[description = "How the amount is divided among participants (split evenly if empty)"]
+    mode: Option<Mode>,
+    #[min = 1]
+    #[description = "The number of winners, for the random winners and first reactors modes"]
+    winners: Option<u32>,
+    #[description = "The role that gets a bigger share, for the weighted by role mode"]
+    weighted_role: Option<serenity_prelude::Role>,
+    #[min = 2]
+    #[description = "The number of shares members with the weighted role get (2 if empty)"]
+    role_weight: Option<u32>,
 ) -> Result<(), Error> {
     if user_blacklisted(ctx, ctx.author().id).await? {
         return Ok(());
     }
 
-    let tip_amount = Amount::from_vrsc(amount)?;
+    let mode = match (mode.unwrap_or(Mode::Split), winners, weighted_role) {
+        (Mode::Split, _, _) => DropMode::Split,
+        (Mode::RandomWinners, Some(winners), _) => DropMode::RandomWinners {
+            winners: winners as i32,
+        },
+        (Mode::FirstReactors, Some(winners), _) => DropMode::FirstReactors {
+            winners: winners as i32,
+        },
+        (Mode::WeightedByRole, _, Some(role)) => DropMode::WeightedByRole {
+            role_id: role.id,
+            weight: role_weight.unwrap_or(2) as i32,
+        },
+        (Mode::RandomWinners | Mode::FirstReactors, None, _) => {
+            ctx.send(|reply| {
+                reply
+                    .ephemeral(true)
+                    .content("Please enter the number of winners for this mode")
+            })
+            .await?;
 
-    if get_and_check_balance(&ctx, tip_amount, Amount::ZERO)
-        .await?
-        .is_some()
-    {
+            return Ok(());
+        }
+        (Mode::WeightedByRole, _, None) => {
+            ctx.send(|reply| {
+                reply
+                    .ephemeral(true)
+                    .content("Please select the role that gets a bigger share for this mode")
+            })
+            .await?;
+
+            return Ok(());
+        }
+    };
+
+    let Some(time_in_seconds) = reactdrop_duration(time, &hms) else {
+        ctx.send(|reply| reply.ephemeral(true).content(too_long_message()))
+            .await?;
+
+        return Ok(());
+    };
+
+    let Some(currency) = resolve_currency(ctx, currency).await? else {
+        return Ok(());
+    };
+
+    let Some(resolved) = resolve_amount(ctx, &amount, &currency, Amount::ZERO).await? else {
+        return Ok(());
+    };
+    let tip_amount = resolved.amount;
+
+    let min_share = ctx.data().settings.application.min_tip_per_recipient;
+    if tip_amount < min_share {
+        ctx.send(|reply| {
+            reply.ephemeral(true).content(format!(
+                "A reactdrop must be at least {}",
+                currency.display(&min_share)
+            ))
+        })
+        .await?;
+
+        return Ok(());
+    }
+
+    let rules = ReactdropRules {
+        required_role: required_role.map(|role| role.id),
+        min_account_age_days: min_account_age.map(|days| days as i32),
+        min_member_age_days: min_member_age.map(|days| days as i32),
+        max_participants: max_participants.map(|max| max as i32),
+        captcha: captcha.unwrap_or(false),
+    };
+    let rules_description = mode
+        .describe()
+        .into_iter()
+        .chain(rules.describe())
+        .map(|line| format!("{line}\n"))
+        .collect::<String>();
+
+    if let Some(balance) = get_and_check_balance(&ctx, &currency, tip_amount, Amount::ZERO).await? {
         debug!("emoji picked for reactdrop: {}", emoji);
 
         if let Ok(reaction_type) = ReactionType::try_from(emoji) {
@@ -278,24 +717,56 @@ pub async fn reactdrop(
 
             trace!("valid emoji");
 
-            let time_in_seconds: Duration = match hms {
-                Hms::Hours => Duration::seconds(time * 60 * 60),
-                Hms::Minutes => Duration::seconds(time * 60),
-            };
+            if !confirm_above_threshold(
+                ctx,
+                &currency,
+                tip_amount,
+                resolved.conversion.as_deref(),
+                &format!(
+                    "You are about to start a reactdrop of {}.\nBalance after the reactdrop: {}",
+                    currency.display(&tip_amount),
+                    currency.display(&balance.checked_sub(tip_amount).unwrap_or(Amount::ZERO))
+                ),
+            )
+            .await?
+            {
+                return Ok(());
+            }
+
+            let Some(finish_time) = chrono::Utc::now().checked_add_signed(time_in_seconds) else {
+                ctx.send(|reply| reply.ephemeral(true).content(too_long_message()))
+                    .await?;
 
-            let now = chrono::Utc::now();
-            let finish_time = now.checked_add_signed(time_in_seconds).unwrap(); // sane values are guaranteed by command argument limits
+                return Ok(());
+            };
             debug!("finish_time: {finish_time:?}");
 
-            let reply_handle = ctx
-                .say(format!(
-                    ">>> **A reactdrop of {tip_amount} was started!**\n\n\
-React with the {} emoji to participate\n\n
+            let content = format!(
+                ">>> **A reactdrop of {} was started!**\n\n\
+React with the {} emoji to participate\n\n\
+{}
 Time remaining: {} hour(s) and {} minute(s)",
-                    reaction_type.clone(),
-                    time_in_seconds.num_seconds() / (60 * 60),
-                    (time_in_seconds.num_seconds() / 60) % 60
-                ))
+                currency.display(&tip_amount),
+                reaction_type.clone(),
+                rules_description,
+                time_in_seconds.num_seconds() / (60 * 60),
+                (time_in_seconds.num_seconds() / 60) % 60
+            );
+            let reply_handle = ctx
+                .send(|reply| {
+                    reply.content(content);
+                    if rules.captcha {
+                        reply.components(|components| {
+                            components.create_action_row(|row| {
+                                row.create_button(|button| {
+                                    button.custom_id(CAPTCHA_BUTTON_ID).label("Verify")
+                                })
+                            })
+                        });
+                    }
+
+                    reply
+                })
                 .await?;
             let msg = reply_handle.into_message().await?;
             msg.react(ctx.http(), reaction_type.clone()).await?;
@@ -305,91 +776,485 @@ Time remaining: {} hour(s) and {} minute(s)",
             let channel_id = ctx.channel_id();
             let message_id = msg.id;
 
-            database::insert_reactdrop(
+            // the amount is moved to escrow right away, so the author can't spend it while the reactdrop runs.
+            if let Err(e) = database::insert_reactdrop(
                 &ctx.data().database,
                 ctx.author().id.try_into()?,
                 reaction_type.to_string(),
-                Amount::from_vrsc(amount).unwrap().as_sat() as i64,
+                &currency.id,
+                tip_amount.as_sat() as i64,
+                ctx.guild_id().map(|guild_id| guild_id.0 as i64),
                 channel_id.try_into()?,
                 message_id.try_into()?,
                 finish_time,
+                &rules,
+                &mode,
             )
+            .await
+            {
+                msg.delete(ctx.http()).await?;
+
+                return reply_if_insufficient_balance(ctx, e).await;
+            }
+
+            ctx.data().reactdrop_scheduler.reschedule(message_id);
+        }
+    }
+
+    Ok(())
+}
+
+/// Cancel a running reactdrop and get the amount back
+#[instrument(skip(ctx), fields(request_id = %Uuid::new_v4() ))]
+#[poise::command(slash_command, category = "Tipping", rename = "cancel")]
+async fn reactdrop_cancel(
+    ctx: Context<'_>,
+    #[description = "The message ID or message link of the reactdrop"] message: String,
+) -> Result<(), Error> {
+    let Some(reactdrop) = find_managed_reactdrop(ctx, &message).await? else {
+        return Ok(());
+    };
+
+    let pool = &ctx.data().database;
+
+    if !database::refund_reactdrop(pool, &reactdrop, ReactdropState::Cancelled).await? {
+        ctx.send(|reply| {
+            reply
+                .ephemeral(true)
+                .content("This reactdrop has already finished.")
+        })
+        .await?;
+
+        return Ok(());
+    }
+
+    info!("reactdrop cancelled by {}: {reactdrop:?}", ctx.author().id);
+
+    let currency = cached_currency(&ctx.data().currency_names, &reactdrop.currency_id);
+
+    if let Ok(mut message) = reactdrop
+        .channel_id
+        .message(ctx.http(), reactdrop.message_id)
+        .await
+    {
+        message
+            .edit(ctx.http(), |edit| {
+                edit.content(format!(
+                    ">>> **The reactdrop of {} was cancelled.**",
+                    currency.display(&reactdrop.tip_amount)
+                ))
+            })
             .await?;
+
+        if let Ok(reaction_type) = ReactionType::from_str(&reactdrop.emoji) {
+            reactdrop
+                .channel_id
+                .delete_reaction_emoji(ctx.http(), message.id, reaction_type)
+                .await?;
         }
     }
 
+    ctx.send(|reply| {
+        reply.ephemeral(true).content(format!(
+            "The reactdrop was cancelled, {} has been returned to <@{}>.",
+            currency.display(&reactdrop.tip_amount),
+            reactdrop.author
+        ))
+    })
+    .await?;
+
     Ok(())
 }
 
+/// Add time to a running reactdrop
+#[instrument(skip(ctx), fields(request_id = %Uuid::new_v4() ))]
+#[poise::command(slash_command, category = "Tipping", rename = "extend")]
+async fn reactdrop_extend(
+    ctx: Context<'_>,
+    #[description = "The message ID or message link of the reactdrop"] message: String,
+    #[min = 1]
+    #[max = 10080]
+    time: i64,
+    #[description = "The time in hours, minutes or seconds"] hms: Hms,
+) -> Result<(), Error> {
+    let Some(reactdrop) = find_managed_reactdrop(ctx, &message).await? else {
+        return Ok(());
+    };
+
+    let Some(finish_time) = reactdrop_duration(time, &hms)
+        .and_then(|extension| reactdrop.finish_time.checked_add_signed(extension))
+        .filter(|finish_time| {
+            *finish_time - chrono::Utc::now() <= Duration::minutes(MAX_REACTDROP_MINUTES)
+        })
+    else {
+        ctx.send(|reply| reply.ephemeral(true).content(too_long_message()))
+            .await?;
+
+        return Ok(());
+    };
+
+    if !database::extend_reactdrop(&ctx.data().database, &reactdrop, finish_time).await? {
+        ctx.send(|reply| {
+            reply
+                .ephemeral(true)
+                .content("This reactdrop has already finished.")
+        })
+        .await?;
+
+        return Ok(());
+    }
+
+    debug!(
+        "reactdrop {} extended to {finish_time}",
+        reactdrop.message_id
+    );
+
+    // the remaining time in the message is updated by the reactdrop scheduler.
+    ctx.data()
+        .reactdrop_scheduler
+        .reschedule(reactdrop.message_id);
+
+    ctx.send(|reply| {
+        reply.ephemeral(true).content(format!(
+            "The reactdrop now ends <t:{}:R>.",
+            finish_time.timestamp()
+        ))
+    })
+    .await?;
+
+    Ok(())
+}
+
+// Returns None if a reactdrop can't last that long.
+fn reactdrop_duration(time: i64, hms: &Hms) -> Option<Duration> {
+    let minutes = match hms {
+        Hms::Hours => time.checked_mul(60)?,
+        Hms::Minutes => time,
+    };
+
+    (minutes <= MAX_REACTDROP_MINUTES).then(|| Duration::minutes(minutes))
+}
+
+fn too_long_message() -> String {
+    format!(
+        "A reactdrop can last at most {} days",
+        MAX_REACTDROP_MINUTES / (24 * 60)
+    )
+}
+
+/// List the running reactdrops you started
+#[instrument(skip(ctx), fields(request_id = %Uuid::new_v4() ))]
+#[poise::command(slash_command, category = "Tipping", rename = "list")]
+async fn reactdrop_list(ctx: Context<'_>) -> Result<(), Error> {
+    let is_owner = ctx.data().owners.contains(&ctx.author().id);
+
+    // admins see all the running reactdrops.
+    let reactdrops = database::get_pending_reactdrops(&ctx.data().database)
+        .await?
+        .into_iter()
+        .filter(|reactdrop| is_owner || reactdrop.author == ctx.author().id)
+        .collect::<Vec<_>>();
+
+    if reactdrops.is_empty() {
+        ctx.send(|reply| {
+            reply
+                .ephemeral(true)
+                .content("There are no running reactdrops.")
+        })
+        .await?;
+
+        return Ok(());
+    }
+
+    let lines = reactdrops
+        .iter()
+        .map(|reactdrop| {
+            let currency = cached_currency(&ctx.data().currency_names, &reactdrop.currency_id);
+
+            format!(
+                "{} {} by <@{}> in <#{}> (message ID: {}), ends <t:{}:R>",
+                reactdrop.emoji,
+                currency.display(&reactdrop.tip_amount),
+                reactdrop.author,
+                reactdrop.channel_id,
+                reactdrop.message_id,
+                reactdrop.finish_time.timestamp()
+            )
+        })
+        .collect::<Vec<_>>();
+
+    ctx.send(|reply| {
+        reply.ephemeral(true).embed(|embed| {
+            embed
+                .title("Running reactdrops")
+                .description(lines.join("\n"))
+        })
+    })
+    .await?;
+
+    Ok(())
+}
+
+// Finds the pending reactdrop a message ID or link points to, if the user is allowed to manage it: its author or an admin.
+// Lets the user know and returns None otherwise.
+async fn find_managed_reactdrop(
+    ctx: Context<'_>,
+    message: &str,
+) -> Result<Option<Reactdrop>, Error> {
+    // a message link ends with the message ID.
+    let message_id = message
+        .trim()
+        .rsplit('/')
+        .next()
+        .and_then(|id| id.parse::<u64>().ok())
+        .map(MessageId);
+
+    let reactdrop = match message_id {
+        Some(message_id) => database::get_pending_reactdrops(&ctx.data().database)
+            .await?
+            .into_iter()
+            .find(|reactdrop| reactdrop.message_id == message_id),
+        None => None,
+    };
+
+    match reactdrop {
+        Some(reactdrop)
+            if reactdrop.author == ctx.author().id
+                || ctx.data().owners.contains(&ctx.author().id) =>
+        {
+            Ok(Some(reactdrop))
+        }
+        Some(_) => {
+            ctx.send(|reply| {
+                reply
+                    .ephemeral(true)
+                    .content("Only the author of a reactdrop can do that.")
+            })
+            .await?;
+
+            Ok(None)
+        }
+        None => {
+            ctx.send(|reply| {
+                reply.ephemeral(true).content(format!(
+                    "Error: no running reactdrop found for `{message}`."
+                ))
+            })
+            .await?;
+
+            Ok(None)
+        }
+    }
+}
+
 // Divides the amount over the `users` vec, increases the balance for all `users` and stores the tip transaction
-// This function gets called in `tip role` and `reactdrop`
+// This function gets called in `tip role`, `rain` and `reactdrop`
 // We need the ChannelId here because ReactDrops tend to last longer than 15 minutes, which is the time Discord drops the context, giving
 // us an invalid webhook token when trying to send a message using that context.
+//
+// Every user gets the share of the split, the remainder is not sent and stays with the author.
 pub async fn tip_multiple_users(
     pool: &PgPool,
     author: UserId,
     http: impl CacheHttp + std::convert::AsRef<poise::serenity_prelude::Http>,
     channel_id: &ChannelId,
     users: &Vec<UserId>,
-    amount: &Amount,
+    currency: &Currency,
+    split: &Split,
     kind: &str,
+    memo: Option<&str>,
 ) -> Result<(), Error> {
-    // TODO optimize this query (select all that don't exist, insert them in 1 go)
-    // check if all the tippees have an entry in the db
-    // let pool = &ctx.data().database;
-    // let author = ctx.author().id;
-    // let http = ctx.http();
-
     debug!("users in tip_users: {:?}", users);
+    debug!(
+        "every member gets {}, {} stays with the author",
+        split.share, split.remainder
+    );
 
-    // need to divide tipping amount over number of users
-    if let Some(div_tip_amount) = amount.checked_div(users.len() as u64) {
-        let amount = div_tip_amount
-            .checked_mul(users.len() as u64)
-            .unwrap_or(*amount);
-        debug!("after division every member gets {div_tip_amount}");
-        debug!("members: {:#?}", &users);
+    let tip_event_id = Uuid::new_v4();
 
-        let tip_event_id = Uuid::new_v4();
+    database::process_a_tip(
+        pool,
+        &tip_event_id,
+        &author,
+        &users,
+        &currency.id,
+        &split.share,
+    )
+    .await?;
 
-        database::process_a_tip(pool, &author, &users, &div_tip_amount).await?;
+    database::store_tip_transactions(
+        pool,
+        &tip_event_id,
+        users,
+        kind,
+        &currency.id,
+        &split.share,
+        author,
+        memo,
+    )
+    .await?;
 
-        database::store_tip_transactions(pool, &tip_event_id, users, kind, &div_tip_amount, author)
-            .await?;
+    notify_tipped_users(pool, author, http, channel_id, users, currency, split, memo).await?;
 
-        let notification_settings = database::get_notification_settings(pool, &users).await?;
+    Ok(())
+}
 
-        for (user_id, notification) in notification_settings {
-            match (user_id, notification) {
-                (_, Notification::All) | (_, Notification::DMOnly) => {
+// Lets the users know they got tipped, depending on their notification settings, and announces the tip in the channel.
+pub async fn notify_tipped_users(
+    pool: &PgPool,
+    author: UserId,
+    http: impl CacheHttp + std::convert::AsRef<poise::serenity_prelude::Http>,
+    channel_id: &ChannelId,
+    users: &Vec<UserId>,
+    currency: &Currency,
+    split: &Split,
+    memo: Option<&str>,
+) -> Result<(), Error> {
+    dm_tipped_users(pool, author, &http, users, currency, &split.share, memo).await?;
+
+    let mut content = format!(
+        "<@{}> just tipped {} to {} users ({} each)!{}",
+        &author,
+        currency.display(&split.total()),
+        &users.len(),
+        currency.display(&split.share),
+        memo_line(memo)
+    );
+    if split.remainder > Amount::ZERO {
+        content.push_str(&format!(
+            "\n{} could not be split evenly and was not sent.",
+            currency.display(&split.remainder)
+        ));
+    }
+
+    channel_id
+        .send_message(http, |message| message.content(content))
+        .await?;
+
+    Ok(())
+}
+
+// Splits an amount over the recipients of a tip. Lets the user know and returns None if there are no recipients,
+// or if the share of every recipient would be below the configured minimum.
+async fn split_or_reply(
+    ctx: Context<'_>,
+    amount: Amount,
+    recipients: usize,
+) -> Result<Option<Split>, Error> {
+    let min_share = ctx.data().settings.application.min_tip_per_recipient;
+
+    match Split::new(amount, recipients, min_share) {
+        Ok(split) => Ok(Some(split)),
+        Err(InvalidAmount(message)) => {
+            ctx.send(|reply| reply.ephemeral(true).content(message))
+                .await?;
+
+            Ok(None)
+        }
+    }
+}
+
+// Trims a memo and checks its length. An empty memo is no memo.
+fn check_memo(memo: Option<String>) -> Result<Option<String>, String> {
+    let Some(memo) = memo
+        .map(|memo| memo.trim().to_owned())
+        .filter(|memo| !memo.is_empty())
+    else {
+        return Ok(None);
+    };
+
+    if memo.chars().count() > MAX_MEMO_LENGTH {
+        return Err(format!(
+            "A memo can be at most {MAX_MEMO_LENGTH} characters long"
+        ));
+    }
+
+    Ok(Some(memo))
+}
+
+// The memo of a tip as a quote on its own line, or nothing without a memo.
+// Mentions in the memo are broken up with a zero-width space, so a memo can't ping anyone.
+fn memo_line(memo: Option<&str>) -> String {
+    memo.map(|memo| format!("\n> {}", memo.replace('@', "@\u{200B}")))
+        .unwrap_or_default()
+}
+
+// DMs the users that got tipped, if their notification settings allow it. A failed DM is logged and skipped.
+pub async fn dm_tipped_users(
+    pool: &PgPool,
+    author: UserId,
+    http: impl CacheHttp + std::convert::AsRef<poise::serenity_prelude::Http>,
+    users: &Vec<UserId>,
+    currency: &Currency,
+    tip_amount: &Amount,
+    memo: Option<&str>,
+) -> Result<(), Error> {
+    let notification_settings = database::get_notification_settings(pool, &users).await?;
+
+    for (user_id, notification) in notification_settings {
+        match (user_id, notification) {
+            (_, Notification::All) | (_, Notification::DMOnly) => {
+                // the tip is already stored, a user that can't be DMed should not keep the others from their DM.
+                let result = async {
                     let user = UserId(user_id as u64).to_user(&http).await?;
                     user.dm(&http, |message| {
                         message.content(format!(
-                            "You just got tipped {div_tip_amount} from <@{}>!",
+                            "You just got tipped {} from <@{}>!{}",
+                            currency.display(&tip_amount),
                             &author,
+                            memo_line(memo)
                         ))
                     })
                     .await?;
+
+                    Ok::<_, Error>(())
+                };
+
+                if let Err(e) = result.await {
+                    warn!("could not DM {user_id} about their tip: {e:?}");
                 }
-                _ => {
-                    // don't ping when ChannelOnly or Off
-                }
+            }
+            _ => {
+                // don't ping when ChannelOnly or Off
             }
         }
-
-        channel_id
-            .send_message(http, |message| {
-                message.content(format!(
-                    "<@{}> just tipped {} to {} users!",
-                    &author,
-                    amount,
-                    &users.len()
-                ))
-            })
-            .await?;
-    } else {
-        error!("could not send tip to role");
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memos_are_trimmed_and_limited() {
+        assert_eq!(check_memo(None), Ok(None));
+        assert_eq!(check_memo(Some(String::from("  "))), Ok(None));
+        assert_eq!(
+            check_memo(Some(String::from(" for the pizza "))),
+            Ok(Some(String::from("for the pizza")))
+        );
+        assert!(check_memo(Some("a".repeat(MAX_MEMO_LENGTH + 1))).is_err());
+    }
+
+    #[test]
+    fn memos_do_not_mention() {
+        assert_eq!(memo_line(None), "");
+        assert_eq!(memo_line(Some("hi @everyone")), "\n> hi @\u{200B}everyone");
+    }
+
+    #[test]
+    fn reactdrops_last_at_most_a_week() {
+        assert_eq!(
+            reactdrop_duration(168, &Hms::Hours),
+            Some(Duration::days(7))
+        );
+        assert_eq!(reactdrop_duration(169, &Hms::Hours), None);
+        assert_eq!(reactdrop_duration(i64::MAX, &Hms::Hours), None);
+        assert_eq!(
+            reactdrop_duration(90, &Hms::Minutes),
+            Some(Duration::minutes(90))
+        );
+    }
+}
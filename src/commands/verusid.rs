@@ -0,0 +1,423 @@
+use poise::serenity_prelude::{Attachment, Colour, CreateEmbed};
+use sqlx::types::chrono::{Duration, Utc};
+use tracing::*;
+use uuid::Uuid;
+use vrsc::Address;
+use vrsc_rpc::{
+    bitcoin::hashes::{sha256, Hash},
+    client::{Client, RpcApi},
+};
+
+use crate::{
+    commands::user_blacklisted,
+    util::{database, identity::identity_name},
+    Context, Error,
+};
+
+// How long a challenge can be signed, in minutes.
+const CHALLENGE_VALIDITY: i64 = 30;
+// The largest file that can be verified, in bytes.
+const MAX_VERIFY_FILE_SIZE: u64 = 8 * 1024 * 1024;
+// The reply when the daemon could not check a signature.
+const VERIFY_FAILED: &str = "The signature could not be checked right now, please try again later.";
+
+/// Link a VerusID to your Discord account
+///
+/// -------- :robot: **Linking a VerusID** --------
+/// `verusid` gives you a message to sign with the VerusID you want to link. \
+/// Sign it in Verus Desktop (Sign & Verify) or with `signmessage` in the CLI, \
+/// then send the signature with `confirm` within 30 minutes.
+///
+/// A linked VerusID is used as the destination of your withdrawals when you don't enter one, \
+/// and is shown next to your name when you get tipped.
+///
+/// -------- :robot: **Unlinking** --------
+/// `remove` unlinks your VerusID. A VerusID can only be linked to one Discord account at a time.
+#[instrument(skip(_ctx), fields(request_id = %Uuid::new_v4() ))]
+#[poise::command(
+    slash_command,
+    category = "VerusID",
+    subcommands("link_verusid", "link_confirm", "link_remove")
+)]
+pub async fn link(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Get a message to sign with your VerusID
+#[instrument(skip(ctx), fields(request_id = %Uuid::new_v4() ))]
+#[poise::command(slash_command, category = "VerusID", rename = "verusid")]
+async fn link_verusid(
+    ctx: Context<'_>,
+    #[description = "The VerusID you want to link, for example name@"] identity: String,
+) -> Result<(), Error> {
+    if user_blacklisted(ctx, ctx.author().id).await? {
+        return Ok(());
+    }
+
+    let identity = identity_name(&identity);
+    let client = ctx.data().verus()?;
+
+    let Some(identity_address) = identity_address(&client, &identity) else {
+        ctx.send(|reply| {
+            reply
+                .ephemeral(true)
+                .content(format!("Error: `{identity}` is not an existing VerusID"))
+        })
+        .await?;
+
+        return Ok(());
+    };
+
+    let challenge = format!(
+        "Link {identity} to Discord user {}: {}",
+        ctx.author().id,
+        Uuid::new_v4()
+    );
+    database::insert_verusid_challenge(
+        &ctx.data().database,
+        &ctx.author().id,
+        &identity,
+        &identity_address,
+        &challenge,
+    )
+    .await?;
+    debug!("challenge for {identity} ({identity_address}): {challenge}");
+
+    ctx.send(|reply| {
+        reply.ephemeral(true).content(format!(
+            "Sign this message with `{identity}`:\n```\n{challenge}\n```\n\
+            In the CLI: `signmessage \"{identity}\" \"{challenge}\"`\n\n\
+            Then use `/link confirm` with the signature within {CHALLENGE_VALIDITY} minutes."
+        ))
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Link your VerusID with the signature of the message
+#[instrument(skip(ctx), fields(request_id = %Uuid::new_v4() ))]
+#[poise::command(slash_command, category = "VerusID", rename = "confirm")]
+async fn link_confirm(
+    ctx: Context<'_>,
+    #[description = "The signature of the message you got with /link verusid"] signature: String,
+) -> Result<(), Error> {
+    if user_blacklisted(ctx, ctx.author().id).await? {
+        return Ok(());
+    }
+
+    let pool = &ctx.data().database;
+
+    let Some((identity, identity_address, challenge, created_at)) =
+        database::get_verusid_challenge(pool, &ctx.author().id).await?
+    else {
+        ctx.send(|reply| {
+            reply
+                .ephemeral(true)
+                .content("There is nothing to confirm. Use `/link verusid` first.")
+        })
+        .await?;
+
+        return Ok(());
+    };
+
+    if created_at < Utc::now() - Duration::minutes(CHALLENGE_VALIDITY) {
+        ctx.send(|reply| {
+            reply
+                .ephemeral(true)
+                .content("The message has expired. Use `/link verusid` to get a new one.")
+        })
+        .await?;
+
+        return Ok(());
+    }
+
+    let client = ctx.data().verus()?;
+    let valid = match daemon_verifies(
+        &client,
+        "verifymessage",
+        &[
+            serde_json::json!(identity_address.to_string()),
+            serde_json::json!(signature.trim()),
+            serde_json::json!(challenge),
+        ],
+    ) {
+        Ok(valid) => valid,
+        Err(e) => {
+            error!("could not verify the signature of {identity}: {e:?}");
+            ctx.send(|reply| reply.ephemeral(true).content(VERIFY_FAILED))
+                .await?;
+
+            return Ok(());
+        }
+    };
+
+    if !valid {
+        ctx.send(|reply| {
+            reply.ephemeral(true).content(format!(
+                "The signature is not valid for `{identity}`. Make sure you signed the exact message with this VerusID."
+            ))
+        })
+        .await?;
+
+        return Ok(());
+    }
+
+    database::link_verusid(pool, &ctx.author().id, &identity, &identity_address).await?;
+    info!("{} linked {identity} ({identity_address})", ctx.author().id);
+
+    ctx.send(|reply| {
+        reply
+            .ephemeral(true)
+            .content(format!("`{identity}` is now linked to your account."))
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Unlink your VerusID
+#[instrument(skip(ctx), fields(request_id = %Uuid::new_v4() ))]
+#[poise::command(slash_command, category = "VerusID", rename = "remove")]
+async fn link_remove(ctx: Context<'_>) -> Result<(), Error> {
+    let unlinked = database::unlink_verusid(&ctx.data().database, &ctx.author().id).await?;
+
+    ctx.send(|reply| {
+        reply.ephemeral(true).content(match unlinked {
+            true => "Your VerusID is not linked anymore.",
+            false => "You don't have a linked VerusID.",
+        })
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Check a signature of a VerusID or an address
+///
+/// -------- :robot: **Verifying a signature** --------
+/// `message` checks the signature of a message, `hash` the signature of a SHA256 hash (in hex) \
+/// and `file` the signature of a file you upload, as made with `signmessage`, `signhash` or `signfile`.
+///
+/// The signer can be a VerusID (ends with an `@`) or an address. \
+/// For a VerusID, the answer shows its current status and primary addresses: \
+/// a signature is checked against the primary addresses the VerusID has now.
+#[instrument(skip(_ctx), fields(request_id = %Uuid::new_v4() ))]
+#[poise::command(
+    slash_command,
+    category = "VerusID",
+    subcommands("verify_message", "verify_hash", "verify_file")
+)]
+pub async fn verify(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Check the signature of a message
+#[instrument(skip(ctx), fields(request_id = %Uuid::new_v4() ))]
+#[poise::command(slash_command, category = "VerusID", rename = "message")]
+async fn verify_message(
+    ctx: Context<'_>,
+    #[description = "The VerusID (ends with @) or address that signed"] signer: String,
+    #[description = "The message that was signed"] message: String,
+    #[description = "The signature"] signature: String,
+) -> Result<(), Error> {
+    let client = ctx.data().verus()?;
+    let valid = daemon_verifies(
+        &client,
+        "verifymessage",
+        &[
+            serde_json::json!(signer.trim()),
+            serde_json::json!(signature.trim()),
+            serde_json::json!(message),
+        ],
+    );
+
+    reply_verification(
+        ctx,
+        &client,
+        signer.trim(),
+        &format!("```\n{message}\n```"),
+        valid,
+    )
+    .await
+}
+
+/// Check the signature of a SHA256 hash
+#[instrument(skip(ctx), fields(request_id = %Uuid::new_v4() ))]
+#[poise::command(slash_command, category = "VerusID", rename = "hash")]
+async fn verify_hash(
+    ctx: Context<'_>,
+    #[description = "The VerusID (ends with @) or address that signed"] signer: String,
+    #[description = "The SHA256 hash that was signed, in hex"] hash: String,
+    #[description = "The signature"] signature: String,
+) -> Result<(), Error> {
+    let hash = hash.trim().to_lowercase();
+    if !is_sha256_hex(&hash) {
+        ctx.send(|reply| {
+            reply
+                .ephemeral(true)
+                .content("A hash must be 64 hexadecimal characters")
+        })
+        .await?;
+
+        return Ok(());
+    }
+
+    let client = ctx.data().verus()?;
+    let valid = daemon_verifies(
+        &client,
+        "verifyhash",
+        &[
+            serde_json::json!(signer.trim()),
+            serde_json::json!(signature.trim()),
+            serde_json::json!(hash),
+        ],
+    );
+
+    reply_verification(ctx, &client, signer.trim(), &format!("`{hash}`"), valid).await
+}
+
+/// Check the signature of a file
+#[instrument(skip(ctx, file), fields(request_id = %Uuid::new_v4() ))]
+#[poise::command(slash_command, category = "VerusID", rename = "file")]
+async fn verify_file(
+    ctx: Context<'_>,
+    #[description = "The VerusID (ends with @) or address that signed"] signer: String,
+    #[description = "The file that was signed"] file: Attachment,
+    #[description = "The signature"] signature: String,
+) -> Result<(), Error> {
+    if file.size > MAX_VERIFY_FILE_SIZE {
+        ctx.send(|reply| {
+            reply.ephemeral(true).content(format!(
+                "Files of at most {} MB can be verified",
+                MAX_VERIFY_FILE_SIZE / (1024 * 1024)
+            ))
+        })
+        .await?;
+
+        return Ok(());
+    }
+
+    ctx.defer().await?;
+
+    // the daemon doesn't necessarily share a disk with the bot, so the bot hashes the file and the daemon checks
+    // the signature of the hash. A file signature is a signature of its SHA256 hash.
+    let hash = sha256::Hash::hash(&file.download().await?);
+
+    let client = ctx.data().verus()?;
+    let valid = daemon_verifies(
+        &client,
+        "verifyhash",
+        &[
+            serde_json::json!(signer.trim()),
+            serde_json::json!(signature.trim()),
+            serde_json::json!(hash.to_string()),
+        ],
+    );
+
+    reply_verification(
+        ctx,
+        &client,
+        signer.trim(),
+        &format!("`{}`", file.filename),
+        valid,
+    )
+    .await
+}
+
+// Answers with a valid or invalid embed. For a VerusID, its status and primary addresses are added.
+async fn reply_verification(
+    ctx: Context<'_>,
+    client: &Client,
+    signer: &str,
+    signed: &str,
+    valid: Result<bool, Error>,
+) -> Result<(), Error> {
+    let valid = match valid {
+        Ok(valid) => valid,
+        Err(e) => {
+            error!("could not verify the signature of {signer}: {e:?}");
+            ctx.send(|reply| reply.ephemeral(true).content(VERIFY_FAILED))
+                .await?;
+
+            return Ok(());
+        }
+    };
+    let identity = client.get_identity(signer).ok();
+    debug!("{signer} signature valid: {valid}");
+
+    ctx.send(|reply| {
+        reply.embed(|embed| {
+            let embed: &mut CreateEmbed = embed
+                .title(match valid {
+                    true => "Valid signature",
+                    false => "Invalid signature",
+                })
+                .color(match valid {
+                    true => Colour::DARK_GREEN,
+                    false => Colour::RED,
+                })
+                .field("Signer", format!("`{signer}`"), false)
+                .field("Signed", signed, false);
+
+            if let Some(identity) = identity {
+                embed
+                    .field(
+                        "Identity address",
+                        format!("`{}`", identity.identity.identityaddress),
+                        false,
+                    )
+                    .field("Status", &identity.status, true)
+                    .field(
+                        "Primary addresses",
+                        identity
+                            .identity
+                            .primaryaddresses
+                            .iter()
+                            .map(|address| format!("`{address}`"))
+                            .collect::<Vec<_>>()
+                            .join("\n"),
+                        false,
+                    );
+            }
+
+            embed
+        })
+    })
+    .await?;
+
+    Ok(())
+}
+
+fn is_sha256_hex(hash: &str) -> bool {
+    hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+// The i-address of an identity, if it exists.
+fn identity_address(client: &Client, identity: &str) -> Option<Address> {
+    client
+        .get_identity(identity)
+        .ok()
+        .map(|identity| identity.identity.identityaddress)
+}
+
+// Checks a signature with one of the verify RPCs of the daemon. Fails if the daemon could not check it.
+fn daemon_verifies(
+    client: &Client,
+    method: &str,
+    params: &[serde_json::Value],
+) -> Result<bool, Error> {
+    Ok(client.call::<bool>(method, params)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashes_are_64_hex_characters() {
+        assert!(is_sha256_hex(&"ab".repeat(32)));
+        assert!(!is_sha256_hex(&"ab".repeat(31)));
+        assert!(!is_sha256_hex(&"zz".repeat(32)));
+    }
+}
@@ -1,19 +1,22 @@
 use std::path::PathBuf;
-use std::{cmp::Ordering, fmt, ops::Sub, str::FromStr, time::Duration};
+use std::{fmt, str::FromStr, time::Duration};
 
 use fast_qr::convert::{image::ImageBuilder, Builder, Shape};
 use fast_qr::qr::QRBuilder;
-use sqlx::PgPool;
 use tracing::*;
 use uuid::Uuid;
 use vrsc::{Address, Amount};
-use vrsc_rpc::{
-    bitcoin::Txid,
-    client::{Client, RpcApi, SendCurrencyOutput},
-};
+use vrsc_rpc::client::{Client, RpcApi};
 
-use crate::commands::user_blacklisted;
-use crate::{util::database, Context, Error};
+use crate::commands::{
+    autocomplete_currency, confirm, reply_if_insufficient_balance, resolve_amount,
+    resolve_currency, user_blacklisted, with_conversion,
+};
+use crate::{
+    util::{currency::Currency, database},
+    withdrawal::{WithdrawalKind, WithdrawalState},
+    Context, Error,
+};
 
 /// Withdraw funds from the tipbot wallet.
 ///
@@ -22,6 +25,8 @@ use crate::{util::database, Context, Error};
 /// - an address that starts with R* or i*
 /// - an existing VerusID (ends with an `@`)
 ///
+/// Without a destination, the VerusID you linked with `/link verusid` is used.
+///
 /// A withdrawal fee will be subtracted from your remaining balance.
 /// You will encounter an error when the amount you want to withdraw is more than (your balance - withdrawal fee).
 ///
@@ -31,6 +36,14 @@ use crate::{util::database, Context, Error};
 /// - an existing VerusID (ends with an `@`)
 ///
 /// A withdrawal fee will be subtracted from the total balance before withdrawal.
+///
+/// -------- :robot: **Currency** --------
+/// Withdrawals are in VRSC, unless you select another currency in the `currency` parameter.
+/// The withdrawal fee is always paid from your VRSC balance.
+///
+/// -------- :robot: **Confirmation** --------
+/// Before a withdrawal is sent, you see the amount, the destination, the fee and your balance afterwards. \
+/// Press Confirm to send it. If you press Cancel or don't respond within a minute, nothing is withdrawn.
 #[instrument(skip(_ctx), fields(request_id = %Uuid::new_v4() ))]
 #[poise::command(slash_command, category = "Wallet", subcommands("amount", "all"))]
 pub async fn withdraw(
@@ -47,8 +60,11 @@ pub async fn withdraw(
 #[poise::command(slash_command, category = "Wallet")]
 pub async fn all(
     ctx: Context<'_>,
-    #[description = "You can use any address starting with R* or i*, or use an existing VerusID (ends with @)."]
-    destination: String,
+    #[description = "Any address starting with R* or i*, or an existing VerusID (your linked VerusID if empty)"]
+    destination: Option<String>,
+    #[description = "The currency you want to withdraw (VRSC if empty)"]
+    #[autocomplete = "autocomplete_currency"]
+    currency: Option<String>,
 ) -> Result<(), Error> {
     if *ctx.data().withdrawals_enabled.read().await == false {
         ctx.send(|reply| {
@@ -65,6 +81,15 @@ pub async fn all(
         return Ok(());
     }
 
+    let Some(currency) = resolve_currency(ctx, currency).await? else {
+        return Ok(());
+    };
+    let native_currency = &ctx.data().native_currency;
+
+    let Some(destination) = destination_or_linked_verusid(ctx, destination).await? else {
+        return Ok(());
+    };
+
     debug!(
         "user {} ({}) demands a withdrawal of his whole balance",
         ctx.author().name,
@@ -84,88 +109,51 @@ pub async fn all(
     }
 
     let pool = &ctx.data().database;
-    let uuid = Uuid::new_v4();
     let tx_fee = &ctx.data().withdrawal_fee.read().await.clone();
 
-    if let Some(balance) = database::get_balance_for_user(&pool, &ctx.author().id).await? {
+    if let Some(balance) =
+        database::get_balance_for_user(&pool, &ctx.author().id, &currency.id).await?
+    {
         let balance_amount = Amount::from_sat(balance);
-        let withdrawal_amount = balance_amount.sub(*tx_fee); // no need to check for underflow, tx_fee is always low.
+        let withdrawal_amount = if &currency == native_currency {
+            // a balance below the fee leaves nothing to withdraw, which is answered below.
+            balance_amount.checked_sub(*tx_fee).unwrap_or(Amount::ZERO)
+        } else {
+            balance_amount
+        };
 
-        if withdrawal_amount > Amount::ZERO {
+        // the fee of a withdrawal of another currency is paid from the native balance
+        let fee_is_covered = &currency == native_currency
+            || balance_is_enough(
+                &Amount::from_sat(
+                    database::get_balance_for_user(&pool, &ctx.author().id, &native_currency.id)
+                        .await?
+                        .unwrap_or(0),
+                ),
+                &Amount::ZERO,
+                tx_fee,
+            );
+
+        if withdrawal_amount > Amount::ZERO && fee_is_covered {
             debug!("withdrawal_amount: {withdrawal_amount}, tx_fee: {tx_fee} must together be balance_amount: {balance_amount}");
 
-            let currency = match ctx.data().settings.application.testnet {
-                true => Some("vrsctest"),
-                false => None,
-            };
-            let sco = SendCurrencyOutput::new(currency, &withdrawal_amount, &destination);
-            let opid = client.send_currency("*", vec![sco], None, None)?;
-            debug!("sendcurrency opid: {:?}", &opid);
-
-            if let Some(txid) = wait_for_sendcurrency_finish(&pool, &client, &opid).await? {
-                // at this point the txid is known. Now blockchain shenanigans could be happening, so we should store everything in the transactions_db table
-                database::store_withdraw_transaction(
-                    &pool,
-                    &uuid,
-                    &ctx.author().id,
-                    Some(&txid),
-                    &opid,
-                    &tx_fee,
-                )
-                .await?;
-
-                trace!("transaction {txid} stored in db, now decrease balance with ({withdrawal_amount} + {tx_fee})");
-                database::decrease_balance(&pool, &ctx.author().id, &withdrawal_amount, &tx_fee)
-                    .await?;
-
-                let new_balance = database::get_balance_for_user(&pool, &ctx.author().id).await?;
-
-                ctx.send(|reply| {
-                    reply.ephemeral(true).embed(|embed| {
-                        let embed = embed
-                            .title("Withdraw")
-                            .field("Amount", withdrawal_amount, false)
-                            .field("Fees", tx_fee, false)
-                            .field(
-                                "Explorer",
-                                format!("[link](https://insight.verus.io/tx/{})", txid.to_string()),
-                                false,
-                            );
-
-                        if let Some(new_balance) = new_balance {
-                            embed.field("New balance", Amount::from_sat(new_balance), false);
-                        }
-
-                        embed
-                    })
-                })
-                .await?;
-            } else {
-                // at this point, the sendcurrency didn't finish. Maybe it went through, but we don't know.
-                // We should check this manually, so we'll let the user know to contact support and we'll store the op-id in the database.
-                let response = format!("Something went wrong trying to process your withdrawal. Please contact support with withdrawal ID: {}",
-                uuid.to_string());
-
-                database::store_withdraw_transaction(
-                    &pool,
-                    &uuid,
-                    &ctx.author().id,
-                    None,
-                    &opid,
-                    &tx_fee,
-                )
-                .await?;
-
-                ctx.send(|reply| reply.ephemeral(true).content(&response))
-                    .await?;
-            }
+            send_withdrawal(
+                ctx,
+                &currency,
+                &withdrawal_amount,
+                tx_fee,
+                &destination,
+                WithdrawalKind::Withdrawal,
+                None,
+            )
+            .await?;
 
             return Ok(());
         }
 
         ctx.send(|reply| {
             reply.ephemeral(true).content(format!(
-                "Your balance is insufficient to withdraw everything.\nMax available balance for withdraw: {}", withdrawal_amount.checked_sub(*tx_fee).unwrap_or(Amount::ZERO)
+                "Your balance is insufficient to withdraw everything.\nMax available balance for withdraw: {}", currency.display(&withdrawal_amount)
             ))
         })
         .await?;
@@ -187,9 +175,13 @@ pub async fn all(
 #[poise::command(slash_command, category = "Wallet")]
 pub async fn amount(
     ctx: Context<'_>,
-    #[description = "The amount you want to tip"] withdrawal_amount: f64,
-    #[description = "You can use any address starting with R* or i*, or use an existing identity (ends with @)."]
-    destination: String,
+    #[description = "The amount you want to withdraw, for example 1.5, 1000 sats, 50% or $5"]
+    withdrawal_amount: String,
+    #[description = "Any address starting with R* or i*, or an existing VerusID (your linked VerusID if empty)"]
+    destination: Option<String>,
+    #[description = "The currency you want to withdraw (VRSC if empty)"]
+    #[autocomplete = "autocomplete_currency"]
+    currency: Option<String>,
 ) -> Result<(), Error> {
     if *ctx.data().withdrawals_enabled.read().await == false {
         ctx.send(|reply| {
@@ -206,6 +198,15 @@ pub async fn amount(
         return Ok(());
     }
 
+    let Some(currency) = resolve_currency(ctx, currency).await? else {
+        return Ok(());
+    };
+    let native_currency = &ctx.data().native_currency;
+
+    let Some(destination) = destination_or_linked_verusid(ctx, destination).await? else {
+        return Ok(());
+    };
+
     debug!(
         "user {} ({}) demands a withdrawal of {withdrawal_amount}",
         ctx.author().name,
@@ -224,125 +225,193 @@ pub async fn amount(
         return Ok(());
     }
 
-    // if amount to withdraw <= 0.0
-    // the reason this has to be done this way is because Amount is an abstraction over floats (f64) and 2 floats with the same value are not equal
-    // according to some IEEE standard.
-    let withdrawal_amount = Amount::from_vrsc(withdrawal_amount)?;
-    if [Ordering::Less, Ordering::Equal].contains(&withdrawal_amount.cmp(&Amount::ZERO)) {
-        ctx.send(|reply| {
-            reply
-                .ephemeral(true)
-                .content("Error: Withdrawal amount should be more than 0.0")
-        })
-        .await?;
+    let tx_fee = ctx.data().withdrawal_fee.read().await.clone();
 
+    // the amount is checked to be more than 0.
+    let Some(resolved) = resolve_amount(ctx, &withdrawal_amount, &currency, tx_fee).await? else {
         return Ok(());
-    }
+    };
+    let withdrawal_amount = resolved.amount;
 
-    let pool = &ctx.data().database;
-    let uuid = Uuid::new_v4();
-    let tx_fee = ctx.data().withdrawal_fee.read().await.clone();
-
-    if get_and_check_balance(&ctx, withdrawal_amount, tx_fee)
+    if get_and_check_balance(&ctx, &currency, withdrawal_amount, tx_fee)
         .await?
         .is_some()
     {
         trace!("balance is sufficient, withdrawal address is valid; starting sendcurrency");
 
-        // until PBaaS releases on mainnet, we should not use a value for currency for "VRSC" withdrawals as there will be a daemon error
-        let currency = match ctx.data().settings.application.testnet {
-            true => Some("vrsctest"),
-            false => None,
-        };
-        let sco = SendCurrencyOutput::new(currency, &withdrawal_amount, &destination);
-        let opid = client.send_currency("*", vec![sco], None, None)?;
-        debug!("sendcurrency opid: {:?}", &opid);
-
-        if let Some(txid) = wait_for_sendcurrency_finish(&pool, &client, &opid).await? {
-            // at this point the txid is known. Now blockchain shenanigans could be happening, so we should store everything in the transactions_db table
-            database::store_withdraw_transaction(
-                &pool,
-                &uuid,
-                &ctx.author().id,
-                Some(&txid),
-                &opid,
-                &tx_fee,
+        send_withdrawal(
+            ctx,
+            &currency,
+            &withdrawal_amount,
+            &tx_fee,
+            &destination,
+            WithdrawalKind::Withdrawal,
+            resolved.conversion.as_deref(),
+        )
+        .await?;
+
+        return Ok(());
+    }
+
+    ctx.send(|reply| {
+        reply.ephemeral(true).content(format!(
+            "Your balance is insufficient to withdraw {}.\nMax available balance for withdraw: {}",
+            currency.display(&withdrawal_amount),
+            currency.display(
+                &withdrawal_amount
+                    .checked_sub(tx_fee)
+                    .unwrap_or(Amount::ZERO)
             )
-            .await?;
+        ))
+    })
+    .await?;
 
-            trace!("transaction stored, now decrease balance");
-            database::decrease_balance(&pool, &ctx.author().id, &withdrawal_amount, &tx_fee)
-                .await?;
+    Ok(())
+}
+
+/// Pay a VerusID or an address on-chain from your balance
+///
+/// -------- :robot: **Send** --------
+/// Sends the amount you enter to someone else, outside of Discord. Valid recipients are:
+/// - an address that starts with R* or i*
+/// - an existing VerusID (ends with an `@`)
+///
+/// Sending works like a withdrawal: the same withdrawal fee is paid from your VRSC balance, \
+/// and you confirm the amount, the recipient and the fee before anything is sent. \
+/// Sends are shown separately from withdrawals in `/history`.
+///
+/// -------- :robot: **Currency** --------
+/// Sends are in VRSC, unless you select another currency in the `currency` parameter.
+#[instrument(skip(ctx), fields(request_id = %Uuid::new_v4() ))]
+#[poise::command(slash_command, category = "Wallet")]
+pub async fn send(
+    ctx: Context<'_>,
+    #[description = "The VerusID (ends with @) or the R* or i* address you want to pay"]
+    recipient: String,
+    #[description = "The amount you want to send, for example 1.5, 1000 sats, 50% or $5"]
+    amount: String,
+    #[description = "The currency you want to send (VRSC if empty)"]
+    #[autocomplete = "autocomplete_currency"]
+    currency: Option<String>,
+) -> Result<(), Error> {
+    if *ctx.data().withdrawals_enabled.read().await == false {
+        ctx.send(|reply| {
+            reply
+                .ephemeral(true)
+                .content(format!("Sending is temporarily disabled."))
+        })
+        .await?;
 
-            let new_balance = database::get_balance_for_user(&pool, &ctx.author().id).await?;
+        return Ok(());
+    }
 
-            ctx.send(|reply| {
-                reply.ephemeral(true).embed(|embed| {
-                    let embed = embed
-                        .title("Withdraw")
-                        .field("Amount", withdrawal_amount, false)
-                        .field("Fees", tx_fee, false)
-                        .field(
-                            "Explorer",
-                            format!("[link](https://insight.verus.io/tx/{})", txid.to_string()),
-                            false,
-                        );
+    if user_blacklisted(ctx, ctx.author().id).await? {
+        return Ok(());
+    }
 
-                    if let Some(new_balance) = new_balance {
-                        embed.field("New balance", Amount::from_sat(new_balance), false);
-                    }
+    let Some(currency) = resolve_currency(ctx, currency).await? else {
+        return Ok(());
+    };
 
-                    embed
-                })
-            })
-            .await?;
-        } else {
-            // at this point, the sendcurrency didn't finish. Maybe it went through, but we don't know.
-            // We should check this manually, so we'll let the user know to contact support and we'll store the op-id in the database.
-            let response = format!("Something went wrong trying to process your withdrawal. Please contact support with withdrawal ID: {}",
-                uuid.to_string());
-
-            database::store_withdraw_transaction(
-                &pool,
-                &uuid,
-                &ctx.author().id,
-                None,
-                &opid,
-                &tx_fee,
-            )
-            .await?;
+    debug!(
+        "user {} ({}) wants to send {amount} to {recipient}",
+        ctx.author().name,
+        ctx.author().id
+    );
 
-            ctx.send(|reply| reply.ephemeral(true).content(&response))
-                .await?;
-        }
+    let recipient = recipient.trim();
+    let client = &ctx.data().verus()?;
+    if !destination_is_valid(recipient, &client) {
+        ctx.send(|reply| {
+            reply.ephemeral(true).content(format!(
+                "Error: {recipient} is not an address or an existing VerusID"
+            ))
+        })
+        .await?;
 
         return Ok(());
     }
 
-    ctx.send(|reply| {
-        reply.ephemeral(true).content(format!(
-            "Your balance is insufficient to withdraw {withdrawal_amount}.\nMax available balance for withdraw: {}", withdrawal_amount.checked_sub(tx_fee).unwrap_or(Amount::ZERO)
-        ))
-    })
-    .await?;
+    let tx_fee = ctx.data().withdrawal_fee.read().await.clone();
+
+    let Some(resolved) = resolve_amount(ctx, &amount, &currency, tx_fee).await? else {
+        return Ok(());
+    };
+    let amount = resolved.amount;
+
+    if get_and_check_balance(&ctx, &currency, amount, tx_fee)
+        .await?
+        .is_some()
+    {
+        send_withdrawal(
+            ctx,
+            &currency,
+            &amount,
+            &tx_fee,
+            recipient,
+            WithdrawalKind::Send,
+            resolved.conversion.as_deref(),
+        )
+        .await?;
+    }
 
     Ok(())
 }
 
 /// Show your balance
+///
+/// Shows the balances of all the currencies you hold, or only the balance of the currency you select.
 #[instrument(skip(ctx), fields(request_id = %Uuid::new_v4() ))]
 #[poise::command(slash_command, category = "Wallet")]
-pub async fn balance(ctx: Context<'_>) -> Result<(), Error> {
-    let balance = Amount::from_sat(
-        database::get_balance_for_user(&ctx.data().database, &ctx.author().id)
-            .await?
-            .unwrap_or(0),
-    );
+pub async fn balance(
+    ctx: Context<'_>,
+    #[description = "The currency to show the balance of (all currencies if empty)"]
+    #[autocomplete = "autocomplete_currency"]
+    currency: Option<String>,
+) -> Result<(), Error> {
+    let pool = &ctx.data().database;
+
+    if currency.is_some() {
+        let Some(currency) = resolve_currency(ctx, currency).await? else {
+            return Ok(());
+        };
+
+        let balance = Amount::from_sat(
+            database::get_balance_for_user(pool, &ctx.author().id, &currency.id)
+                .await?
+                .unwrap_or(0),
+        );
+
+        ctx.send(|reply| {
+            reply
+                .ephemeral(true)
+                .content(format!("Your balance is: {}", currency.display(&balance)))
+        })
+        .await?;
+
+        return Ok(());
+    }
+
+    let mut balances = vec![];
+    for (currency_id, balance) in database::get_balances_for_user(pool, &ctx.author().id).await? {
+        let currency = Currency {
+            name: ctx.data().to_currency_name(&currency_id)?,
+            id: currency_id,
+        };
+
+        balances.push(currency.display(&Amount::from_sat(balance)));
+    }
+
+    if balances.is_empty() {
+        balances.push(ctx.data().native_currency.display(&Amount::ZERO));
+    }
+
+    balances.sort_by_key(|balance| balance.to_lowercase());
 
     ctx.send(|reply| {
         reply
             .ephemeral(true)
-            .content(format!("Your balance is: {}", balance))
+            .content(format!("Your balance is:\n{}", balances.join("\n")))
     })
     .await?;
 
@@ -405,70 +474,166 @@ async fn send_deposit_address_msg(ctx: Context<'_>, address: &Address) -> Result
 
     Ok(())
 }
-// Sendcurrency works with op-ids because it can work with zk-transactions. Therefore the txid of a transactions is not always known directly after sending.
-// This function waits a bit and gets the txid once the operation_status RPC gives one.
-// if it doesn't give one, the user is notified and the op-id is stored in the database.
-async fn wait_for_sendcurrency_finish(
-    pool: &PgPool,
-    client: &Client,
-    opid: &str,
-) -> Result<Option<Txid>, Error> {
-    // from https://buildmedia.readthedocs.org/media/pdf/zcash/english-docs/zcash.pdf
-    // status can be one of queued, executing, failed or success.
-    // we should sleep if status is one of queued or executing
-    // we should return when status is one of failed or success.
-    loop {
-        trace!("getting operation status: {}", &opid);
-        let operation_status = client.z_get_operation_status(vec![&opid])?;
-        trace!("got operation status: {:?}", &operation_status);
-
-        if let Some(Some(opstatus)) = operation_status.first() {
-            if ["queued", "executing"].contains(&opstatus.status.as_ref()) {
-                tokio::time::sleep(Duration::from_millis(100)).await;
-                trace!("opid still executing");
-                continue;
-            }
-
-            let params = opstatus.params.first().as_ref().unwrap().as_ref().unwrap();
-
-            if let Some(txid) = &opstatus.result {
-                trace!(
-                    "there was an operation_status, operation was executed with status: {}",
-                    opstatus.status
-                );
-
-                database::store_opid(
-                    &pool,
-                    &opid,
-                    &opstatus.status,
-                    opstatus.creation_time as i64,
-                    opstatus.result.as_ref().map(|txid| txid.txid),
-                    &params.address,
-                    params.amount,
-                    &params.currency.as_ref().unwrap_or(&String::from("VRSC")),
-                )
-                .await?;
-                return Ok(Some(txid.txid));
-            } else {
-                error!("execution failed with status: {}", opstatus.status);
-
-                database::store_opid(
-                    &pool,
-                    &opid,
-                    &opstatus.status,
-                    opstatus.creation_time as i64,
-                    opstatus.result.as_ref().map(|txid| txid.txid),
-                    &params.address,
-                    params.amount,
-                    &params.currency.as_ref().unwrap(),
-                )
-                .await?;
-            }
-        } else {
-            trace!("there was NO operation_status");
-            tokio::time::sleep(Duration::from_millis(100)).await;
+// What the user confirms before a withdrawal: the amount, the destination, the fee and the balances after the withdrawal.
+async fn withdrawal_summary(
+    ctx: Context<'_>,
+    currency: &Currency,
+    withdrawal_amount: &Amount,
+    tx_fee: &Amount,
+    recipient: &str,
+    kind: WithdrawalKind,
+) -> Result<String, Error> {
+    let pool = &ctx.data().database;
+    let native_currency = &ctx.data().native_currency;
+    let balance = |balance: Option<u64>| Amount::from_sat(balance.unwrap_or(0));
+
+    let currency_balance =
+        balance(database::get_balance_for_user(pool, &ctx.author().id, &currency.id).await?);
+
+    let mut summary = format!(
+        "{} {} to {recipient}?\nFee: {}\n",
+        match kind {
+            WithdrawalKind::Withdrawal => "Withdraw",
+            WithdrawalKind::Send => "Send",
+        },
+        currency.display(withdrawal_amount),
+        native_currency.display(tx_fee)
+    );
+
+    if currency == native_currency {
+        summary.push_str(&format!(
+            "Balance after the {kind}: {}",
+            currency.display(
+                &currency_balance
+                    .checked_sub(*withdrawal_amount + *tx_fee)
+                    .unwrap_or(Amount::ZERO)
+            )
+        ));
+    } else {
+        let native_balance = balance(
+            database::get_balance_for_user(pool, &ctx.author().id, &native_currency.id).await?,
+        );
+
+        summary.push_str(&format!(
+            "Balance after the {kind}: {} and {}",
+            currency.display(
+                &currency_balance
+                    .checked_sub(*withdrawal_amount)
+                    .unwrap_or(Amount::ZERO)
+            ),
+            native_currency.display(&native_balance.checked_sub(*tx_fee).unwrap_or(Amount::ZERO))
+        ));
+    }
+
+    Ok(summary)
+}
+
+// Queues the withdrawal (or send), which reserves the amount and the fee, and lets the withdrawal processor send it.
+// The user gets the txid if it is known within a minute, otherwise the withdrawal ID. The withdrawal keeps being processed in the background.
+async fn send_withdrawal(
+    ctx: Context<'_>,
+    currency: &Currency,
+    withdrawal_amount: &Amount,
+    tx_fee: &Amount,
+    destination: &str,
+    kind: WithdrawalKind,
+    conversion: Option<&str>,
+) -> Result<(), Error> {
+    let pool = &ctx.data().database;
+    let withdrawal_processor = &ctx.data().withdrawal_processor;
+    let native_currency = &ctx.data().native_currency;
+    let recipient = recipient_label(destination, &ctx.data().verus()?);
+
+    if !confirm(
+        ctx,
+        &with_conversion(
+            conversion,
+            &withdrawal_summary(ctx, currency, withdrawal_amount, tx_fee, &recipient, kind).await?,
+        ),
+    )
+    .await?
+    {
+        return Ok(());
+    }
+
+    let uuid = Uuid::new_v4();
+
+    if let Err(e) = database::queue_withdrawal(
+        &pool,
+        &uuid,
+        &ctx.author().id,
+        &currency.id,
+        withdrawal_amount,
+        &native_currency.id,
+        tx_fee,
+        destination,
+        kind,
+    )
+    .await
+    {
+        return reply_if_insufficient_balance(ctx, e).await;
+    }
+
+    trace!("{kind} {uuid} queued, starting sendcurrency");
+
+    let withdrawal = withdrawal_processor
+        .wait_for_txid(&uuid, Duration::from_secs(60))
+        .await?;
+
+    match (withdrawal.status, withdrawal.txid) {
+        (WithdrawalState::Failed, _) => {
+            ctx.send(|reply| {
+                reply.ephemeral(true).content(format!(
+                    "Your {kind} failed. The amount and the fee have been returned to your balance. Withdrawal ID: {uuid}"
+                ))
+            })
+            .await?;
+        }
+        (_, Some(txid)) => {
+            let new_balance =
+                database::get_balance_for_user(&pool, &ctx.author().id, &currency.id).await?;
+
+            ctx.send(|reply| {
+                reply.ephemeral(true).embed(|embed| {
+                    let embed = embed
+                        .title(match kind {
+                            WithdrawalKind::Withdrawal => "Withdraw",
+                            WithdrawalKind::Send => "Send",
+                        })
+                        .field("Amount", currency.display(withdrawal_amount), false)
+                        .field("Recipient", &recipient, false)
+                        .field("Fees", tx_fee, false)
+                        .field(
+                            "Explorer",
+                            format!("[link](https://insight.verus.io/tx/{})", txid.to_string()),
+                            false,
+                        );
+
+                    if let Some(new_balance) = new_balance {
+                        embed.field(
+                            "New balance",
+                            currency.display(&Amount::from_sat(new_balance)),
+                            false,
+                        );
+                    }
+
+                    embed
+                })
+            })
+            .await?;
+        }
+        (_, None) => {
+            // the sendcurrency didn't finish yet. The withdrawal processor keeps checking it, and refunds it if it fails.
+            ctx.send(|reply| {
+                reply.ephemeral(true).content(format!(
+                    "Your {kind} is taking longer than usual and is still being processed. Please contact support if it doesn't arrive, with withdrawal ID: {uuid}"
+                ))
+            })
+            .await?;
         }
     }
+
+    Ok(())
 }
 
 // Let's do some address parsing
@@ -476,6 +641,42 @@ async fn wait_for_sendcurrency_finish(
 // (- is the withdrawal address a z_address?)
 // - is the withdrawal address an identity?
 // - is the withdrawal address a i-address?
+// Without a destination, withdrawals go to the VerusID the user linked with `/link`.
+// Lets the user know and returns None if there is no destination and no linked VerusID.
+async fn destination_or_linked_verusid(
+    ctx: Context<'_>,
+    destination: Option<String>,
+) -> Result<Option<String>, Error> {
+    if let Some(destination) = destination {
+        return Ok(Some(destination.trim().to_owned()));
+    }
+
+    match database::get_linked_verusid(&ctx.data().database, &ctx.author().id).await? {
+        Some((identity, _)) => Ok(Some(identity)),
+        None => {
+            ctx.send(|reply| {
+                reply.ephemeral(true).content(
+                    "Please enter a destination, or link a VerusID with `/link verusid` to withdraw to it by default",
+                )
+            })
+            .await?;
+
+            Ok(None)
+        }
+    }
+}
+
+// The destination as shown to the user. A VerusID is shown with its i-address, so the user can check who gets paid.
+fn recipient_label(dest: &str, client: &Client) -> String {
+    if Address::from_str(dest).is_err() {
+        if let Ok(identity) = client.get_identity(dest) {
+            return format!("`{dest}` (`{}`)", identity.identity.identityaddress);
+        }
+    }
+
+    format!("`{dest}`")
+}
+
 fn destination_is_valid(dest: &str, client: &Client) -> bool {
     if Address::from_str(dest).is_ok() {
         // this parses both R* addresses and i* addresses
@@ -509,21 +710,42 @@ pub fn balance_is_enough(balance: &Amount, amount_to_withdraw: &Amount, tx_fee:
 }
 
 // In this context, get the balance of the sending user, check if it is sufficient, and return it.
+// The tx_fee is always paid in the native currency, so for other currencies the native balance is checked for the fee.
 pub async fn get_and_check_balance(
     ctx: &Context<'_>,
+    currency: &Currency,
     amount_to_check: Amount,
     tx_fee: Amount,
 ) -> Result<Option<Amount>, Error> {
     let pool = &ctx.data().database;
+    let native_currency = &ctx.data().native_currency;
 
-    if let Some(balance) = database::get_balance_for_user(&pool, &ctx.author().id).await? {
+    let (tx_fee, fee_is_covered) = if currency == native_currency {
+        (tx_fee, true)
+    } else {
+        let native_balance =
+            database::get_balance_for_user(&pool, &ctx.author().id, &native_currency.id)
+                .await?
+                .unwrap_or(0);
+
+        (
+            Amount::ZERO,
+            balance_is_enough(&Amount::from_sat(native_balance), &Amount::ZERO, &tx_fee),
+        )
+    };
+
+    if let Some(balance) =
+        database::get_balance_for_user(&pool, &ctx.author().id, &currency.id).await?
+    {
         trace!("tipper has balance");
 
-        if balance_is_enough(
-            &Amount::from_sat(balance),
-            &amount_to_check,
-            &tx_fee, // no fees for tipping
-        ) {
+        if fee_is_covered
+            && balance_is_enough(
+                &Amount::from_sat(balance),
+                &amount_to_check,
+                &tx_fee, // no fees for tipping
+            )
+        {
             trace!("tipper has sufficient balance");
             return Ok(Some(Amount::from_sat(balance)));
         } else {
@@ -0,0 +1,209 @@
+use std::str::FromStr;
+
+use tracing::*;
+use uuid::Uuid;
+use vrsc::Address;
+
+use crate::{
+    commands::user_blacklisted,
+    util::{
+        database,
+        identity::{get_identity, identity_name, IdentitySnapshot},
+    },
+    Context, Error,
+};
+
+// The most identities a user can watch, and separately the most addresses.
+const MAX_WATCHES: usize = 10;
+
+/// Get a DM when a VerusID changes
+///
+/// -------- :robot: **Watching a VerusID** --------
+/// `identity` starts watching a VerusID. After every block, the bot checks if it was revoked or recovered, \
+/// locked or unlocked, or if its primary addresses or authorities changed, and sends you a DM if it did. \
+/// You can watch up to 10 VerusIDs.
+///
+/// -------- :robot: **Watching an address** --------
+/// `address` starts watching an R-address or i-address. You get a DM for every transaction that pays to it, and \
+/// for transactions that spend from it if the node indexes addresses. You can watch up to 10 addresses.
+///
+/// -------- :robot: **Managing watches** --------
+/// `list` shows what you watch, `stop` stops watching a VerusID or an address.
+#[instrument(skip(_ctx), fields(request_id = %Uuid::new_v4() ))]
+#[poise::command(
+    slash_command,
+    category = "VerusID",
+    subcommands("watch_identity", "watch_address", "watch_list", "watch_stop")
+)]
+pub async fn watch(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Watch a VerusID for changes
+#[instrument(skip(ctx), fields(request_id = %Uuid::new_v4() ))]
+#[poise::command(slash_command, category = "VerusID", rename = "identity")]
+async fn watch_identity(
+    ctx: Context<'_>,
+    #[description = "The VerusID (name@) or its i-address"] identity: String,
+) -> Result<(), Error> {
+    if user_blacklisted(ctx, ctx.author().id).await? {
+        return Ok(());
+    }
+
+    let pool = &ctx.data().database;
+    let client = ctx.data().verus()?;
+    let identity = identity_name(&identity);
+
+    let Some(result) = get_identity(&client, &identity) else {
+        ctx.send(|reply| {
+            reply
+                .ephemeral(true)
+                .content(format!("VerusID `{identity}` not found"))
+        })
+        .await?;
+
+        return Ok(());
+    };
+
+    let identity_address = &result.identity.identityaddress;
+    let watches = database::get_identity_watches_for_user(pool, &ctx.author().id).await?;
+    if watches.len() >= MAX_WATCHES
+        && !watches
+            .iter()
+            .any(|(address, _)| address == identity_address)
+    {
+        ctx.send(|reply| {
+            reply.ephemeral(true).content(format!(
+                "You can watch at most {MAX_WATCHES} VerusIDs. Use `/watch stop` to stop watching one."
+            ))
+        })
+        .await?;
+
+        return Ok(());
+    }
+
+    let state = serde_json::to_string(&IdentitySnapshot::from(&result))?;
+    database::insert_identity_watch(
+        pool,
+        &ctx.author().id,
+        identity_address,
+        &result.fullyqualifiedname,
+        &state,
+    )
+    .await?;
+    debug!("{} watches {identity_address}", ctx.author().id);
+
+    ctx.send(|reply| {
+        reply.ephemeral(true).content(format!(
+            "You are watching `{}`. You will get a DM when it changes, so make sure you accept DMs from this server.",
+            result.fullyqualifiedname
+        ))
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Get a DM for every transaction of an address
+#[instrument(skip(ctx), fields(request_id = %Uuid::new_v4() ))]
+#[poise::command(slash_command, category = "VerusID", rename = "address")]
+async fn watch_address(
+    ctx: Context<'_>,
+    #[description = "The R-address or i-address"] address: String,
+) -> Result<(), Error> {
+    if user_blacklisted(ctx, ctx.author().id).await? {
+        return Ok(());
+    }
+
+    let pool = &ctx.data().database;
+    let Ok(address) = Address::from_str(address.trim()) else {
+        ctx.send(|reply| {
+            reply
+                .ephemeral(true)
+                .content(format!("`{}` is not a valid address", address.trim()))
+        })
+        .await?;
+
+        return Ok(());
+    };
+
+    let watches = database::get_address_watches_for_user(pool, &ctx.author().id).await?;
+    if watches.len() >= MAX_WATCHES && !watches.contains(&address) {
+        ctx.send(|reply| {
+            reply.ephemeral(true).content(format!(
+                "You can watch at most {MAX_WATCHES} addresses. Use `/watch stop` to stop watching one."
+            ))
+        })
+        .await?;
+
+        return Ok(());
+    }
+
+    database::insert_address_watch(pool, &ctx.author().id, &address).await?;
+    debug!("{} watches {address}", ctx.author().id);
+
+    ctx.send(|reply| {
+        reply.ephemeral(true).content(format!(
+            "You are watching `{address}`. You will get a DM for its transactions, so make sure you accept DMs from this server."
+        ))
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Show the VerusIDs and addresses you watch
+#[instrument(skip(ctx), fields(request_id = %Uuid::new_v4() ))]
+#[poise::command(slash_command, category = "VerusID", rename = "list")]
+async fn watch_list(ctx: Context<'_>) -> Result<(), Error> {
+    let pool = &ctx.data().database;
+    let watches = database::get_identity_watches_for_user(pool, &ctx.author().id).await?;
+    let address_watches = database::get_address_watches_for_user(pool, &ctx.author().id).await?;
+
+    let content = if watches.is_empty() && address_watches.is_empty() {
+        String::from("You don't watch any VerusIDs or addresses")
+    } else {
+        watches
+            .iter()
+            .map(|(address, identity)| format!("`{identity}` (`{address}`)"))
+            .chain(address_watches.iter().map(|address| format!("`{address}`")))
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+
+    ctx.send(|reply| reply.ephemeral(true).content(content))
+        .await?;
+
+    Ok(())
+}
+
+/// Stop watching a VerusID or an address
+#[instrument(skip(ctx), fields(request_id = %Uuid::new_v4() ))]
+#[poise::command(slash_command, category = "VerusID", rename = "stop")]
+async fn watch_stop(
+    ctx: Context<'_>,
+    #[description = "The VerusID (name@), i-address or R-address"] identity: String,
+) -> Result<(), Error> {
+    let identity = identity_name(&identity);
+    // watches are stored with the fully qualified name, so a name is looked up to find its address.
+    let query = get_identity(&ctx.data().verus()?, &identity)
+        .map(|result| result.identity.identityaddress.to_string())
+        .unwrap_or_else(|| identity.clone());
+
+    let mut stopped =
+        database::delete_identity_watch(&ctx.data().database, &ctx.author().id, &query).await?;
+    if let Ok(address) = Address::from_str(&query) {
+        stopped |= database::delete_address_watch(&ctx.data().database, &ctx.author().id, &address)
+            .await?;
+    }
+
+    ctx.send(|reply| {
+        reply.ephemeral(true).content(match stopped {
+            true => format!("You stopped watching `{identity}`"),
+            false => format!("You don't watch `{identity}`"),
+        })
+    })
+    .await?;
+
+    Ok(())
+}
@@ -59,11 +59,24 @@ pub struct ApplicationSettings {
     pub global_withdrawal_fee: Amount,
     #[serde(with = "vrsc::util::amount::serde::as_sat")]
     pub min_deposit_threshold: Amount,
+    /// The smallest amount a single recipient of a role tip or rain can get.
+    #[serde(
+        with = "vrsc::util::amount::serde::as_sat",
+        default = "default_min_tip_per_recipient"
+    )]
+    pub min_tip_per_recipient: Amount,
     pub min_deposit_confirmations_small: u32,
     pub min_deposit_confirmations_large: u32,
     pub zmq_block_port: u16,
     pub zmq_tx_port: u16,
     pub owners: HashSet<String>,
+    /// Names of PBaaS currencies that are suggested when autocompleting a currency argument.
+    #[serde(default)]
+    pub currencies: Vec<String>,
+}
+
+fn default_min_tip_per_recipient() -> Amount {
+    Amount::from_sat(10_000)
 }
 
 pub fn get_configuration() -> Result<Config, config::ConfigError> {
@@ -3,11 +3,18 @@ pub mod config;
 pub mod reactdrop;
 pub mod util;
 pub mod wallet_listener;
+pub mod withdrawal;
 
 use crate::{
     config::{get_configuration, Config},
-    util::database,
+    reactdrop::ReactdropScheduler,
+    util::{
+        activity::ChannelActivity,
+        currency::{lookup_currency, Currency, CurrencyNames},
+        database,
+    },
     wallet_listener::TransactionProcessor,
+    withdrawal::WithdrawalProcessor,
 };
 use commands::*;
 // use opentelemetry::global;
@@ -16,7 +23,7 @@ use secrecy::ExposeSecret;
 use sqlx::PgPool;
 use std::{
     collections::{HashMap, HashSet},
-    sync::Arc,
+    sync::{self, Arc},
     time::Duration,
 };
 use tokio::{sync::RwLock, time::interval};
@@ -38,7 +45,6 @@ async fn app() -> Result<(), Error> {
     let config = get_configuration()?;
     let pg_url = &config.database.connection_string();
     let database = PgPool::connect_lazy(pg_url)?;
-    sqlx::migrate!("./migrations").run(&database).await?;
 
     let owners = config
         .application
@@ -60,13 +66,15 @@ async fn app() -> Result<(), Error> {
             admin::blacklist(),
             admin::checktxid(),
             admin::maintenance(),
-            admin::manuallyaddwithdraw(),
+            admin::resolvewithdrawal(),
             admin::status(),
+            admin::adjustbalance(),
             misc::help(),
             misc::info(),
             misc::source(),
             misc::register(),
             misc::notifications(),
+            misc::confirmations(),
             chain::chaininfo(),
             chain::peerinfo(),
             chain::price(),
@@ -77,11 +85,21 @@ async fn app() -> Result<(), Error> {
             chain::halving(),
             chain::time_of_block(),
             chain::currency(),
+            chain::identity(),
+            chain::convert(),
             wallet::deposit(),
             wallet::balance(),
+            history::history(),
             wallet::withdraw(),
+            wallet::send(),
             tipping::tip(),
             tipping::reactdrop(),
+            tipping::rain(),
+            leaderboard::leaderboard(),
+            verusid::link(),
+            verusid::verify(),
+            watch::watch(),
+            offers::offers(),
         ],
 
         command_check: Some(|ctx| {
@@ -138,6 +156,37 @@ async fn app() -> Result<(), Error> {
             })
         },
         on_error: |error| Box::pin(on_error(error)),
+        event_handler: |ctx, event, _framework, data| {
+            Box::pin(async move {
+                match event {
+                    // reactions are recorded as they come in, Discord does not tell in which order users reacted.
+                    poise::Event::ReactionAdd { add_reaction } => {
+                        if let Err(e) =
+                            reactdrop::record_reaction(&data.database, add_reaction).await
+                        {
+                            error!("could not record reaction: {e:?}");
+                        }
+                    }
+                    poise::Event::InteractionCreate { interaction } => {
+                        if let Err(e) =
+                            reactdrop::handle_captcha(ctx, &data.database, interaction).await
+                        {
+                            error!("could not handle reactdrop captcha: {e:?}");
+                        }
+                    }
+                    poise::Event::Message { new_message } if !new_message.author.bot => {
+                        data.channel_activity.record(
+                            new_message.channel_id,
+                            new_message.author.id,
+                            chrono::Utc::now(),
+                        );
+                    }
+                    _ => {}
+                }
+
+                Ok(())
+            })
+        },
         owners,
 
         ..Default::default()
@@ -160,6 +209,29 @@ async fn app() -> Result<(), Error> {
 
     let client = client?;
 
+    let native_currency = client.get_currency(match config.application.testnet {
+        true => "vrsctest",
+        false => "VRSC",
+    })?;
+    let native_currency = Currency {
+        id: native_currency.currencyid,
+        name: native_currency.fullyqualifiedname,
+    };
+    debug!("native currency: {native_currency:?}");
+
+    database::run_migrations(&database, &native_currency.id).await?;
+
+    let currency_names: CurrencyNames = Arc::new(sync::RwLock::new(HashMap::from([(
+        native_currency.id.clone(),
+        native_currency.name.clone(),
+    )])));
+
+    for currency in config.application.currencies.iter() {
+        if lookup_currency(&client, &currency_names, currency).is_none() {
+            warn!("configured currency {currency} not found, it will not be autocompleted");
+        }
+    }
+
     info!("starting client");
 
     poise::Framework::builder()
@@ -172,19 +244,58 @@ async fn app() -> Result<(), Error> {
             let deposits_enabled_clone = deposits_enabled.clone();
 
             Box::pin(async move {
+                let reactdrop_scheduler = Arc::new(ReactdropScheduler::new(
+                    ctx.clone(),
+                    pool.clone(),
+                    vrsc_rpc::client::Client::vrsc(
+                        config.application.testnet,
+                        vrsc_rpc::Auth::UserPass(
+                            format!("http://127.0.0.1:{}", config.application.rpc_port),
+                            config.application.rpc_user.clone(),
+                            config.application.rpc_password.clone(),
+                        ),
+                    )?,
+                    currency_names.clone(),
+                    config.application.min_tip_per_recipient,
+                ));
+
                 tokio::spawn({
-                    let ctx = ctx.clone();
-                    let pool = pool.clone();
+                    let reactdrop_scheduler = reactdrop_scheduler.clone();
 
-                    info!("starting reactdrop loop");
+                    info!("starting reactdrop scheduler");
+
+                    async move { reactdrop_scheduler.run().await }
+                });
+
+                let withdrawal_processor = Arc::new(WithdrawalProcessor {
+                    http: http.clone(),
+                    pool: pool.clone(),
+                    client: vrsc_rpc::client::Client::vrsc(
+                        config.application.testnet,
+                        vrsc_rpc::Auth::UserPass(
+                            format!("http://127.0.0.1:{}", config.application.rpc_port),
+                            config.application.rpc_user.clone(),
+                            config.application.rpc_password.clone(),
+                        ),
+                    )?,
+                    native_currency: native_currency.clone(),
+                    currency_names: currency_names.clone(),
+                    testnet: config.application.testnet,
+                });
+
+                tokio::spawn({
+                    let withdrawal_processor = withdrawal_processor.clone();
+
+                    info!("starting withdrawal loop");
 
                     async move {
-                        let mut interval = interval(Duration::from_secs(20));
+                        // the first tick completes immediately, which resumes the withdrawals that were interrupted by a restart.
+                        let mut interval = interval(Duration::from_secs(10));
 
                         loop {
                             interval.tick().await;
 
-                            if let Err(e) = reactdrop::check_running_reactdrops(&ctx, &pool).await {
+                            if let Err(e) = withdrawal_processor.process_withdrawals().await {
                                 error!("{:?}", e);
                             }
                         }
@@ -195,6 +306,8 @@ async fn app() -> Result<(), Error> {
                     http.clone(),
                     pool.clone(),
                     config_clone,
+                    native_currency.clone(),
+                    currency_names.clone(),
                     Arc::new(RwLock::new(false)),
                     deposits_enabled_clone,
                 ));
@@ -251,8 +364,12 @@ async fn app() -> Result<(), Error> {
                     deposits_enabled,
                     blacklist: std::sync::Mutex::new(HashSet::new()),
                     tx_processor: tx_proc,
+                    withdrawal_processor,
+                    reactdrop_scheduler,
+                    channel_activity: ChannelActivity::default(),
                     owners: owners_clone,
-                    currency_names: HashMap::new(),
+                    native_currency,
+                    currency_names,
                 })
             })
         })
@@ -338,8 +455,12 @@ pub struct Data {
     deposits_enabled: Arc<RwLock<bool>>,
     blacklist: std::sync::Mutex<HashSet<UserId>>,
     tx_processor: Arc<TransactionProcessor>,
+    withdrawal_processor: Arc<WithdrawalProcessor>,
+    reactdrop_scheduler: Arc<ReactdropScheduler>,
+    channel_activity: ChannelActivity,
     owners: HashSet<UserId>,
-    currency_names: HashMap<Address, String>,
+    native_currency: Currency,
+    currency_names: CurrencyNames,
 }
 
 impl Data {
@@ -357,16 +478,28 @@ impl Data {
 
     // TODO: cow?
     pub fn to_currency_name(&self, address: &Address) -> Result<String, Error> {
-        if let Some(name) = self.currency_names.get(address) {
+        if let Some(name) = self.currency_names.read().unwrap().get(address) {
             return Ok(name.to_owned());
         } else {
             let client = self.verus()?;
 
             let currency = client.get_currency(&address.to_string())?;
             let currency_name = currency.fullyqualifiedname;
+            self.currency_names
+                .write()
+                .unwrap()
+                .insert(address.clone(), currency_name.clone());
+
             return Ok(currency_name);
         }
     }
+
+    /// Finds a currency by name or currency id. Returns None if the currency does not exist.
+    pub fn currency(&self, name_or_id: &str) -> Result<Option<Currency>, Error> {
+        let client = self.verus()?;
+
+        Ok(lookup_currency(&client, &self.currency_names, name_or_id))
+    }
 }
 
 #[tokio::main(worker_threads = 2)]
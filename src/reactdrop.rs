@@ -1,21 +1,59 @@
-use std::{fmt::Display, str::FromStr};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    str::FromStr,
+    sync::Mutex,
+    time::Duration as StdDuration,
+};
 
 use poise::serenity_prelude::{
-    ArgumentConvert, ChannelId, Context, Message, MessageId, ReactionType, UserId,
+    self as serenity, ActionRowComponent, ChannelId, Context, GuildId, InputTextStyle, Interaction,
+    InteractionResponseType, Message, MessageComponentInteraction, MessageId,
+    ModalSubmitInteraction, Reaction, ReactionType, RoleId, UserId,
 };
 use sqlx::{
-    types::chrono::{self, DateTime, Utc},
+    types::chrono::{self, DateTime, Duration, Utc},
     PgPool,
 };
-use tracing::{debug, error, info, trace};
-use vrsc::Amount;
+use tokio::sync::Notify;
+use tracing::{debug, error, info, trace, warn};
+use uuid::Uuid;
+use vrsc::{Address, Amount};
+use vrsc_rpc::{
+    bitcoin::hashes::{sha256, Hash},
+    client::{Client, RpcApi},
+};
 
-use crate::{commands, util::database, Error};
+use crate::{
+    commands,
+    util::{
+        amount::Split,
+        currency::{cached_currency, Currency, CurrencyNames},
+        database,
+    },
+    Error,
+};
+
+// The scheduler looks at the pending reactdrops at least this often, whether it is woken up or not.
+const MAX_SLEEP: StdDuration = StdDuration::from_secs(60);
+/// The custom id of the button under a reactdrop that asks for a captcha.
+pub const CAPTCHA_BUTTON_ID: &str = "reactdrop-captcha";
+// The custom id of the form with the captcha, followed by the message id of the reactdrop.
+const CAPTCHA_FORM_ID: &str = "reactdrop-captcha-answer";
+// The number of answers a user can give on the captcha of a reactdrop.
+const MAX_CAPTCHA_ATTEMPTS: i32 = 3;
+// The terms of a captcha are written out, so a bot can't just read the sum.
+const CAPTCHA_NUMBERS: [&str; 10] = [
+    "zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine",
+];
 
 #[derive(Debug)]
 pub enum ReactdropState {
     Pending,
     Processed,
+    Cancelled,
+    /// The message of the reactdrop was deleted before it finished.
+    Failed,
 }
 
 impl Display for ReactdropState {
@@ -23,6 +61,8 @@ impl Display for ReactdropState {
         match self {
             Self::Pending => write!(f, "pending"),
             Self::Processed => write!(f, "processed"),
+            Self::Cancelled => write!(f, "cancelled"),
+            Self::Failed => write!(f, "failed"),
         }
     }
 }
@@ -32,6 +72,8 @@ impl From<String> for ReactdropState {
         match value.as_ref() {
             "pending" => ReactdropState::Pending,
             "processed" => ReactdropState::Processed,
+            "cancelled" => ReactdropState::Cancelled,
+            "failed" => ReactdropState::Failed,
             _ => unreachable!(),
         }
     }
@@ -42,134 +84,1172 @@ pub struct Reactdrop {
     pub author: UserId,
     pub status: ReactdropState,
     pub emoji: String,
+    pub currency_id: Address,
     pub tip_amount: Amount,
     pub channel_id: ChannelId,
     pub message_id: MessageId,
     pub finish_time: DateTime<Utc>,
+    /// Whether the amount was moved to escrow when the reactdrop started.
+    pub escrowed: bool,
+    pub guild_id: Option<GuildId>,
+    pub rules: ReactdropRules,
+    pub mode: DropMode,
+    /// The block whose hash draws the random winners, stored when the reactdrop ends.
+    pub seed_height: Option<i64>,
 }
 
-pub async fn check_running_reactdrops(ctx: &Context, pool: &PgPool) -> Result<(), Error> {
-    let pending_reactdrops = database::get_pending_reactdrops(&pool).await?;
+/// How the amount of a reactdrop is divided over the participants.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DropMode {
+    /// Everybody gets the same amount.
+    Split,
+    /// A number of participants, drawn with the hash of the first block after the reactdrop ended, share the amount.
+    RandomWinners { winners: i32 },
+    /// The participants that reacted first share the amount.
+    FirstReactors { winners: i32 },
+    /// Participants with the role get `weight` times the amount of the others.
+    WeightedByRole { role_id: RoleId, weight: i32 },
+}
 
-    let now = chrono::Utc::now();
-    debug!(
-        "number of pending reactdrops.{} at.{}",
-        pending_reactdrops.len(),
-        now
-    );
+impl DropMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Split => "split",
+            Self::RandomWinners { .. } => "random-winners",
+            Self::FirstReactors { .. } => "first-reactors",
+            Self::WeightedByRole { .. } => "weighted-by-role",
+        }
+    }
 
-    for reactdrop in pending_reactdrops {
-        let mut message: Message = ArgumentConvert::convert(
-            &ctx,
-            None,
-            Some(reactdrop.channel_id),
-            reactdrop.message_id.to_string().as_ref(),
-        )
-        .await?;
+    /// Builds the mode from its stored columns. Falls back to split if a column the mode needs is missing.
+    pub fn from_columns(
+        name: &str,
+        winners: Option<i32>,
+        weighted_role_id: Option<i64>,
+        role_weight: Option<i32>,
+    ) -> Self {
+        match (name, winners, weighted_role_id, role_weight) {
+            ("random-winners", Some(winners), _, _) => Self::RandomWinners { winners },
+            ("first-reactors", Some(winners), _, _) => Self::FirstReactors { winners },
+            ("weighted-by-role", _, Some(role_id), Some(weight)) => Self::WeightedByRole {
+                role_id: RoleId(role_id as u64),
+                weight,
+            },
+            _ => Self::Split,
+        }
+    }
+
+    /// Describes the mode for the reactdrop message.
+    pub fn describe(&self) -> Option<String> {
+        match self {
+            Self::Split => None,
+            Self::RandomWinners { winners } => Some(format!(
+                "{winners} random winner(s) will be drawn with the hash of the first block after the reactdrop ends"
+            )),
+            Self::FirstReactors { winners } => {
+                Some(format!("The first {winners} participant(s) win"))
+            }
+            Self::WeightedByRole { role_id, weight } => Some(format!(
+                "Members with the <@&{role_id}> role get {weight} times as much"
+            )),
+        }
+    }
+}
+
+/// Divides `amount` over the participants according to the mode. Participants are in the order they reacted,
+/// together with whether they have the weighted role. `seed` is the block hash used to draw random winners.
+///
+/// Amounts are rounded down to the satoshi, participants whose share is less than `min_share` (or zero) get nothing.
+pub fn divide(
+    mode: &DropMode,
+    participants: &[(UserId, bool)],
+    amount: &Amount,
+    min_share: &Amount,
+    seed: &str,
+) -> Vec<(UserId, Amount)> {
+    let weighted: Vec<(UserId, u64)> = match mode {
+        DropMode::Split => participants
+            .iter()
+            .map(|(user_id, _)| (*user_id, 1))
+            .collect(),
+        DropMode::RandomWinners { winners } => {
+            // every participant gets a ticket: the hash of the seed and their user id. The lowest tickets win.
+            let mut tickets = participants
+                .iter()
+                .map(|(user_id, _)| {
+                    let ticket = sha256::Hash::hash(format!("{seed}:{user_id}").as_bytes());
+
+                    (ticket.to_string(), *user_id)
+                })
+                .collect::<Vec<_>>();
+            tickets.sort();
+
+            tickets
+                .into_iter()
+                .take(*winners as usize)
+                .map(|(_, user_id)| (user_id, 1))
+                .collect()
+        }
+        DropMode::FirstReactors { winners } => participants
+            .iter()
+            .take(*winners as usize)
+            .map(|(user_id, _)| (*user_id, 1))
+            .collect(),
+        DropMode::WeightedByRole { weight, .. } => participants
+            .iter()
+            .map(|(user_id, has_role)| (*user_id, if *has_role { *weight as u64 } else { 1 }))
+            .collect(),
+    };
+
+    let total_weight: u128 = weighted.iter().map(|(_, weight)| *weight as u128).sum();
+    if total_weight == 0 {
+        return vec![];
+    }
 
-        let diff = reactdrop.finish_time.signed_duration_since(now);
-        let diff_fmt = || -> String {
-            match diff.num_seconds() {
-                t @ 0..=3600 => format!("{} minute(s)", t / 60),
-                t @ _ => {
-                    format!("{} hour(s) and {} minute(s)", t / (60 * 60), (t / 60) % 60)
+    weighted
+        .into_iter()
+        .map(|(user_id, weight)| {
+            let share = amount.as_sat() as u128 * weight as u128 / total_weight;
+
+            (user_id, Amount::from_sat(share as u64))
+        })
+        .filter(|(_, share)| share >= min_share && share > &Amount::ZERO)
+        .collect()
+}
+
+/// The optional rules a user must meet to take part in a reactdrop, to keep alt accounts from farming drops.
+#[derive(Debug, Clone, Default)]
+pub struct ReactdropRules {
+    pub required_role: Option<RoleId>,
+    pub min_account_age_days: Option<i32>,
+    pub min_member_age_days: Option<i32>,
+    pub max_participants: Option<i32>,
+    /// Participants must solve a captcha.
+    pub captcha: bool,
+}
+
+impl ReactdropRules {
+    /// Describes the rules for the reactdrop message, one line per rule.
+    pub fn describe(&self) -> Vec<String> {
+        let mut lines = vec![];
+
+        if let Some(role_id) = self.required_role {
+            lines.push(format!(
+                "Only members with the <@&{role_id}> role can participate"
+            ));
+        }
+        if let Some(days) = self.min_account_age_days {
+            lines.push(format!(
+                "Discord accounts must be at least {days} day(s) old"
+            ));
+        }
+        if let Some(days) = self.min_member_age_days {
+            lines.push(format!(
+                "You must have been a member of this server for at least {days} day(s)"
+            ));
+        }
+        if let Some(max) = self.max_participants {
+            lines.push(format!("At most {max} participants"));
+        }
+        if self.captcha {
+            lines.push(String::from(
+                "Press Verify and solve the captcha before the reactdrop ends",
+            ));
+        }
+
+        lines
+    }
+
+    // the member is only needed for rules about the server.
+    fn needs_member(&self) -> bool {
+        self.required_role.is_some() || self.min_member_age_days.is_some()
+    }
+}
+
+/// Runs the pending reactdrops: keeps their countdown up to date and pays them out when they finish.
+///
+/// The scheduler sleeps until the next reactdrop finishes or needs a countdown update, or until it is woken up
+/// because a reactdrop was started or extended. Every reactdrop is handled on its own, so a failing
+/// reactdrop does not hold up the others.
+#[derive(Debug)]
+pub struct ReactdropScheduler {
+    pub ctx: Context,
+    pub pool: PgPool,
+    pub client: Client,
+    pub currency_names: CurrencyNames,
+    /// The smallest share a participant of a reactdrop can get.
+    pub min_share: Amount,
+    wake: Notify,
+    // the last time the countdown of a reactdrop was updated, by message.
+    countdowns: Mutex<HashMap<MessageId, DateTime<Utc>>>,
+}
+
+impl ReactdropScheduler {
+    pub fn new(
+        ctx: Context,
+        pool: PgPool,
+        client: Client,
+        currency_names: CurrencyNames,
+        min_share: Amount,
+    ) -> Self {
+        Self {
+            ctx,
+            pool,
+            client,
+            currency_names,
+            min_share,
+            wake: Notify::new(),
+            countdowns: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Lets the scheduler look at a reactdrop that was started or extended right away, instead of at its next deadline.
+    pub fn reschedule(&self, message_id: MessageId) {
+        self.countdowns.lock().unwrap().remove(&message_id);
+        self.wake.notify_one();
+    }
+
+    /// Runs forever. The first pass happens right away, which finishes the reactdrops that ended while the bot was offline.
+    pub async fn run(&self) {
+        loop {
+            let next = match self.process_reactdrops().await {
+                Ok(next) => next,
+                Err(e) => {
+                    error!("could not process reactdrops: {e:?}");
+
+                    None
                 }
+            };
+
+            let sleep = next
+                .and_then(|next| next.signed_duration_since(chrono::Utc::now()).to_std().ok())
+                .map_or(MAX_SLEEP, |sleep| sleep.min(MAX_SLEEP));
+            trace!("reactdrop scheduler sleeps for {sleep:?}");
+
+            tokio::select! {
+                _ = tokio::time::sleep(sleep) => {}
+                _ = self.wake.notified() => {}
             }
+        }
+    }
+
+    // Finishes the reactdrops that are due, updates the countdowns that are due and returns when the next one is.
+    async fn process_reactdrops(&self) -> Result<Option<DateTime<Utc>>, Error> {
+        let pending_reactdrops = database::get_pending_reactdrops(&self.pool).await?;
+
+        let now = chrono::Utc::now();
+        debug!(
+            "number of pending reactdrops.{} at.{}",
+            pending_reactdrops.len(),
+            now
+        );
+
+        // cancelled reactdrops don't need a countdown anymore.
+        self.countdowns.lock().unwrap().retain(|message_id, _| {
+            pending_reactdrops
+                .iter()
+                .any(|reactdrop| &reactdrop.message_id == message_id)
+        });
+
+        let mut next = None;
+
+        for reactdrop in pending_reactdrops {
+            let result = if reactdrop.finish_time <= now {
+                self.finish(&reactdrop).await
+            } else if self.countdown_due(&reactdrop, now) {
+                self.update_countdown(&reactdrop, now).await
+            } else {
+                Ok(())
+            };
+
+            if let Err(e) = result {
+                error!(
+                    "could not process reactdrop {}: {e:?}",
+                    reactdrop.message_id
+                );
+            }
+
+            if reactdrop.finish_time > now {
+                let next_countdown = self.next_countdown(&reactdrop, now);
+                let due = next_countdown.min(reactdrop.finish_time);
+
+                next = Some(next.map_or(due, |next: DateTime<Utc>| next.min(due)));
+            }
+        }
+
+        Ok(next)
+    }
+
+    fn countdown_due(&self, reactdrop: &Reactdrop, now: DateTime<Utc>) -> bool {
+        self.next_countdown(reactdrop, now) <= now
+    }
+
+    fn next_countdown(&self, reactdrop: &Reactdrop, now: DateTime<Utc>) -> DateTime<Utc> {
+        let countdowns = self.countdowns.lock().unwrap();
+
+        match countdowns.get(&reactdrop.message_id) {
+            Some(last_update) => {
+                *last_update + countdown_interval(reactdrop.finish_time.signed_duration_since(now))
+            }
+            None => now,
+        }
+    }
+
+    async fn update_countdown(
+        &self,
+        reactdrop: &Reactdrop,
+        now: DateTime<Utc>,
+    ) -> Result<(), Error> {
+        let Some(mut message) = self.fetch_message(reactdrop).await? else {
+            return Ok(());
         };
-        debug!("{diff:?}");
 
         let content: &str = message.content.as_ref();
-        let split = content.find("Time remaining: ").unwrap();
-        let new_content = format!("{}Time remaining: {}", &content[..split], diff_fmt());
+        let Some(split) = content.find("Time remaining: ") else {
+            warn!(
+                "reactdrop message {} has no countdown",
+                reactdrop.message_id
+            );
 
-        message.edit(&ctx, |edit| edit.content(new_content)).await?;
+            return Ok(());
+        };
+        let new_content = format!(
+            "{}Time remaining: {}",
+            &content[..split],
+            format_remaining(reactdrop.finish_time.signed_duration_since(now))
+        );
 
-        if reactdrop.finish_time <= now {
-            let mut last_user = None;
-            let mut reaction_users = vec![];
+        message
+            .edit(&self.ctx, |edit| edit.content(new_content))
+            .await?;
 
-            while let Ok(users) = message
-                .reaction_users(
-                    &ctx,
-                    ReactionType::from_str(&reactdrop.emoji)?,
-                    Some(50),
-                    last_user,
-                )
-                .await
-            {
-                debug!("appending {} users", users.len());
-                reaction_users.extend(users.clone());
+        self.countdowns
+            .lock()
+            .unwrap()
+            .insert(reactdrop.message_id, now);
 
-                debug!("{users:?}");
+        Ok(())
+    }
 
-                last_user = users.last().map(|user| user.id);
-                if last_user.is_none() {
-                    break;
+    // Returns None if the message of the reactdrop was deleted, in which case the reactdrop has failed and is refunded.
+    async fn fetch_message(&self, reactdrop: &Reactdrop) -> Result<Option<Message>, Error> {
+        match reactdrop
+            .channel_id
+            .message(&self.ctx.http, reactdrop.message_id)
+            .await
+        {
+            Ok(message) => Ok(Some(message)),
+            Err(e) if is_unknown_message(&e) => {
+                warn!(
+                    "the message of reactdrop {} was deleted, refunding",
+                    reactdrop.message_id
+                );
+                self.forget(reactdrop);
+
+                if database::refund_reactdrop(&self.pool, reactdrop, ReactdropState::Failed).await?
+                {
+                    self.send_failed_dm(reactdrop).await;
                 }
+
+                Ok(None)
             }
+            Err(e) => Err(e.into()),
+        }
+    }
 
-            debug!(
-                "retrieved {} users who reacted on reactdrop tip\n{:#?}",
-                reaction_users.len(),
-                reaction_users
-            );
+    async fn finish(&self, reactdrop: &Reactdrop) -> Result<(), Error> {
+        let Some(message) = self.fetch_message(reactdrop).await? else {
+            return Ok(());
+        };
+        self.forget(reactdrop);
 
-            let reaction_users = reaction_users
-                .iter()
-                .filter(|user| !user.bot)
-                .map(|u| u.id)
-                .collect::<Vec<_>>();
+        let (ctx, pool) = (&self.ctx, &self.pool);
 
-            if reaction_users.len() == 0 {
-                trace!("no users to tip, abort");
-            } else {
-                trace!("tipping {} users in reactdrop", reaction_users.len());
+        // Random winners are drawn with the hash of the first block after the reactdrop ended. The participants are
+        // stored before that block is known, and the winners are drawn from them once it exists, so nobody can
+        // join when they know who would win.
+        let (reaction_users, seed) = match (reactdrop.mode, reactdrop.seed_height) {
+            (DropMode::RandomWinners { .. }, None) => {
+                let participants = self.participants(reactdrop, &message).await?;
 
-                if let Err(e) = commands::tipping::tip_multiple_users(
-                    &pool,
-                    reactdrop.author,
-                    &ctx.http,
-                    &reactdrop.channel_id,
+                if participants.is_empty() {
+                    (participants, None)
+                } else {
+                    let height = self.client.get_blockchain_info()?.blocks + 1;
+                    database::snapshot_reactdrop(pool, reactdrop, &participants, height as i64)
+                        .await?;
+                    debug!(
+                        "reactdrop {} draws its winners from {} participants with block {height}",
+                        reactdrop.message_id,
+                        participants.len()
+                    );
+
+                    return Ok(());
+                }
+            }
+            (DropMode::RandomWinners { .. }, Some(height)) => {
+                match self.seed(reactdrop, height as u64)? {
+                    Some(seed) => (
+                        database::get_reactdrop_participants(pool, reactdrop).await?,
+                        Some(seed),
+                    ),
+                    // the block is not there yet, the next pass tries again.
+                    None => return Ok(()),
+                }
+            }
+            _ => (self.participants(reactdrop, &message).await?, None),
+        };
+
+        if reaction_users.len() == 0 {
+            trace!("no users to tip, refund the author");
+
+            database::refund_reactdrop(pool, reactdrop, ReactdropState::Processed).await?;
+        } else {
+            trace!("tipping {} users in reactdrop", reaction_users.len());
+
+            // the currency was looked up when the reactdrop started, so it is in the cache.
+            let currency = cached_currency(&self.currency_names, &reactdrop.currency_id);
+
+            if reactdrop.escrowed {
+                pay_out(
+                    ctx,
+                    pool,
+                    reactdrop,
                     &reaction_users,
-                    &reactdrop.tip_amount,
-                    "reactdrop",
+                    &currency,
+                    &self.min_share,
+                    seed,
                 )
-                .await
-                {
-                    error!("{e:?}");
+                .await?;
+            } else if database::update_reactdrop(
+                pool,
+                reactdrop.channel_id.0 as i64,
+                reactdrop.message_id.0 as i64,
+                ReactdropState::Processed,
+            )
+            .await?
+            {
+                // a reactdrop without escrow is tipped from the balance of the author, which can be spent by now.
+                // It is marked as processed first, so it is never tipped twice.
+                let failure =
+                    match Split::new(reactdrop.tip_amount, reaction_users.len(), self.min_share) {
+                        Ok(split) => commands::tipping::tip_multiple_users(
+                            pool,
+                            reactdrop.author,
+                            &ctx.http,
+                            &reactdrop.channel_id,
+                            &reaction_users,
+                            &currency,
+                            &split,
+                            "reactdrop",
+                            None,
+                        )
+                        .await
+                        .err()
+                        .map(|e| {
+                            error!("{e:?}");
 
+                            String::from("the author does not have enough funds")
+                        }),
+                        Err(e) => Some(e.to_string()),
+                    };
+
+                if let Some(failure) = failure {
                     reactdrop
                         .channel_id
                         .send_message(&ctx.http, |msg| {
                             msg.content(format!(
-                                "<@{}> didn't have enough funds, reactdrop failed",
-                                &message.author.id,
+                                "The reactdrop of <@{}> failed: {failure}",
+                                &reactdrop.author,
                             ))
                         })
                         .await?;
                 }
             }
+        }
+
+        // paying out or refunding the reactdrop moved it to its final status.
+        info!("processed reactdrop: {reactdrop:#?}");
+
+        // the reactdrop is paid out at this point, a failure to clean up the message should not process it again.
+        let cleanup = async {
+            let mut message = message;
+            let content: &str = message.content.as_ref();
+            let new_content = format!(
+                "{}Time remaining: finished",
+                &content[..content.find("Time remaining: ").unwrap_or(content.len())]
+            );
+            // the captcha button is removed as well.
+            message
+                .edit(ctx, |edit| edit.content(new_content).components(|c| c))
+                .await?;
 
             reactdrop
                 .channel_id
                 .delete_reaction_emoji(
                     &ctx.http,
-                    message,
+                    message.id,
                     ReactionType::from_str(&reactdrop.emoji)?,
                 )
                 .await?;
 
-            database::update_reactdrop(
-                &pool,
-                reactdrop.channel_id.0 as i64,
-                reactdrop.message_id.0 as i64,
-                ReactdropState::Processed,
+            Ok::<_, Error>(())
+        };
+
+        if let Err(e) = cleanup.await {
+            warn!(
+                "could not clean up the message of reactdrop {}: {e:?}",
+                reactdrop.message_id
+            );
+        }
+
+        Ok(())
+    }
+
+    // The users that reacted on the reactdrop and follow its rules, in the order they reacted.
+    async fn participants(
+        &self,
+        reactdrop: &Reactdrop,
+        message: &Message,
+    ) -> Result<Vec<UserId>, Error> {
+        let (ctx, pool) = (&self.ctx, &self.pool);
+        let mut last_user = None;
+        let mut reaction_users = vec![];
+
+        while let Ok(users) = message
+            .reaction_users(
+                ctx,
+                ReactionType::from_str(&reactdrop.emoji)?,
+                Some(50),
+                last_user,
             )
+            .await
+        {
+            debug!("appending {} users", users.len());
+            reaction_users.extend(users.clone());
+
+            debug!("{users:?}");
+
+            last_user = users.last().map(|user| user.id);
+            if last_user.is_none() {
+                break;
+            }
+        }
+
+        debug!(
+            "retrieved {} users who reacted on reactdrop tip\n{:#?}",
+            reaction_users.len(),
+            reaction_users
+        );
+
+        let mut reaction_users = reaction_users
+            .iter()
+            .filter(|user| !user.bot)
+            .map(|u| u.id)
+            .collect::<Vec<_>>();
+
+        // Discord returns the users ordered by user id. Put them in the order they reacted, as far as the bot saw it:
+        // reactions the bot missed (while it was offline) go last.
+        let reaction_order = database::get_reactdrop_reactions(pool, reactdrop).await?;
+        reaction_users.sort_by_key(|user_id| {
+            reaction_order
+                .iter()
+                .position(|id| id == user_id)
+                .unwrap_or(usize::MAX)
+        });
+
+        if reactdrop.rules.captcha {
+            let solvers = database::get_reactdrop_captcha_solvers(pool, reactdrop).await?;
+            reaction_users.retain(|user_id| solvers.contains(user_id));
+        }
+
+        Ok(eligible_users(ctx, reactdrop, reaction_users).await)
+    }
+
+    // Returns the height and hash of the block that draws the winners, or None if that block does not exist yet.
+    fn seed(&self, reactdrop: &Reactdrop, height: u64) -> Result<Option<(u64, String)>, Error> {
+        if self.client.get_blockchain_info()?.blocks < height {
+            trace!(
+                "reactdrop {} waits for block {height}",
+                reactdrop.message_id
+            );
+
+            return Ok(None);
+        }
+
+        let hash: String = self
+            .client
+            .call("getblockhash", &[serde_json::json!(height)])?;
+        debug!("drawing winners with block {height}: {hash}");
+
+        Ok(Some((height, hash)))
+    }
+
+    fn forget(&self, reactdrop: &Reactdrop) {
+        self.countdowns
+            .lock()
+            .unwrap()
+            .remove(&reactdrop.message_id);
+    }
+
+    async fn send_failed_dm(&self, reactdrop: &Reactdrop) {
+        let currency = cached_currency(&self.currency_names, &reactdrop.currency_id);
+
+        let result = async {
+            let user = self.ctx.http.get_user(reactdrop.author.0).await?;
+            user.direct_message(&self.ctx.http, |message| {
+                message.content(format!(
+                    "The message of your reactdrop of {} in <#{}> was deleted, so the reactdrop was stopped. The amount has been returned to your balance.",
+                    currency.display(&reactdrop.tip_amount),
+                    reactdrop.channel_id
+                ))
+            })
             .await?;
 
-            info!("processed reactdrop: {reactdrop:#?}");
+            Ok::<_, Error>(())
+        };
+
+        if let Err(e) = result.await {
+            warn!(
+                "could not notify {} of a failed reactdrop: {e:?}",
+                reactdrop.author
+            );
+        }
+    }
+}
+
+// Countdowns far from the end are updated less often, to keep the number of message edits down.
+fn countdown_interval(remaining: Duration) -> Duration {
+    if remaining > Duration::hours(24) {
+        Duration::hours(1)
+    } else if remaining > Duration::hours(1) {
+        Duration::minutes(10)
+    } else {
+        Duration::minutes(1)
+    }
+}
+
+fn format_remaining(remaining: Duration) -> String {
+    match remaining.num_seconds() {
+        t @ 0..=3600 => format!("{} minute(s)", t / 60),
+        t => format!("{} hour(s) and {} minute(s)", t / (60 * 60), (t / 60) % 60),
+    }
+}
+
+// Unknown Channel and Unknown Message, see https://discord.com/developers/docs/topics/opcodes-and-status-codes#json
+fn is_unknown_message(error: &serenity::Error) -> bool {
+    match error {
+        serenity::Error::Http(http_error) => matches!(
+            &**http_error,
+            serenity::HttpError::UnsuccessfulRequest(response)
+                if [10003, 10008].contains(&response.error.code)
+        ),
+        _ => false,
+    }
+}
+
+// Divides the escrowed amount over the users that reacted according to the mode of the reactdrop, pays it out and lets them know.
+// `seed` is the height and hash of the block that draws random winners. Anyone can look up the hash to verify the draw.
+async fn pay_out(
+    ctx: &Context,
+    pool: &PgPool,
+    reactdrop: &Reactdrop,
+    users: &Vec<UserId>,
+    currency: &Currency,
+    min_share: &Amount,
+    seed: Option<(u64, String)>,
+) -> Result<(), Error> {
+    let mut participants = vec![];
+    for user_id in users {
+        let has_role = match (reactdrop.mode, reactdrop.guild_id) {
+            (DropMode::WeightedByRole { role_id, .. }, Some(guild_id)) => guild_id
+                .member(ctx, user_id)
+                .await
+                .map_or(false, |member| member.roles.contains(&role_id)),
+            _ => false,
+        };
+
+        participants.push((*user_id, has_role));
+    }
+
+    let payouts = divide(
+        &reactdrop.mode,
+        &participants,
+        &reactdrop.tip_amount,
+        min_share,
+        seed.as_ref().map_or("", |(_, hash)| hash.as_str()),
+    );
+
+    if payouts.is_empty() {
+        trace!("every share is below the minimum, refund the author");
+
+        if database::refund_reactdrop(pool, reactdrop, ReactdropState::Processed).await? {
+            reactdrop
+                .channel_id
+                .send_message(&ctx.http, |message| {
+                    message.content(format!(
+                        "The reactdrop of <@{}> was not paid out: every share would be less than the minimum of {}. The amount has been returned.",
+                        reactdrop.author,
+                        currency.display(min_share)
+                    ))
+                })
+                .await?;
+        }
+
+        return Ok(());
+    }
+
+    let tip_event_id = Uuid::new_v4();
+
+    if !database::payout_reactdrop(pool, &tip_event_id, reactdrop, &payouts).await? {
+        debug!("reactdrop was already paid out");
+
+        return Ok(());
+    }
+
+    if let Some((_, hash)) = &seed {
+        database::set_reactdrop_seed_hash(pool, reactdrop, hash).await?;
+    }
+
+    // tips are stored and notified per amount.
+    let mut groups: BTreeMap<Amount, Vec<UserId>> = BTreeMap::new();
+    for (user_id, amount) in &payouts {
+        groups.entry(*amount).or_default().push(*user_id);
+    }
+
+    // the payout is committed at this point, so nothing below may stop the other tips from being stored or the
+    // announcement from being sent.
+    for (amount, users) in &groups {
+        if let Err(e) = database::store_tip_transactions(
+            pool,
+            &tip_event_id,
+            users,
+            "reactdrop",
+            &reactdrop.currency_id,
+            amount,
+            reactdrop.author,
+            None,
+        )
+        .await
+        {
+            error!(
+                "could not store the tips of reactdrop {}: {e:?}",
+                reactdrop.message_id
+            );
+        }
+    }
+
+    for (amount, users) in &groups {
+        if let Err(e) = commands::tipping::dm_tipped_users(
+            pool,
+            reactdrop.author,
+            &ctx.http,
+            users,
+            currency,
+            amount,
+            None,
+        )
+        .await
+        {
+            warn!(
+                "could not DM the recipients of reactdrop {}: {e:?}",
+                reactdrop.message_id
+            );
+        }
+    }
+
+    let total = payouts.iter().fold(Amount::ZERO, |total, (_, amount)| {
+        total.checked_add(*amount).unwrap_or(total)
+    });
+    let mut content = format!(
+        "<@{}> just tipped {} to {} users!",
+        reactdrop.author,
+        currency.display(&total),
+        payouts.len()
+    );
+
+    if let Some((height, hash)) = &seed {
+        content.push_str(&format!(
+            "\nWinners: {}\nDrawn with the hash of block {height}: `{hash}`",
+            payouts
+                .iter()
+                .map(|(user_id, _)| format!("<@{user_id}>"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+
+    if let Some(remainder) = reactdrop
+        .tip_amount
+        .checked_sub(total)
+        .filter(|remainder| *remainder > Amount::ZERO)
+    {
+        content.push_str(&format!(
+            "\n{} was left over and was returned to <@{}>.",
+            currency.display(&remainder),
+            reactdrop.author
+        ));
+    }
+
+    reactdrop
+        .channel_id
+        .send_message(&ctx.http, |message| message.content(content))
+        .await?;
+
+    Ok(())
+}
+
+/// Records the time a user reacted on a running reactdrop with its emoji, so the first reactors can be determined.
+/// Reactions on any other message are ignored.
+pub async fn record_reaction(pool: &PgPool, reaction: &Reaction) -> Result<(), Error> {
+    let Some(user_id) = reaction.user_id else {
+        return Ok(());
+    };
+
+    database::insert_reactdrop_reaction(
+        pool,
+        reaction.message_id,
+        user_id,
+        &reaction.emoji.to_string(),
+    )
+    .await
+}
+
+/// Handles the captchas of reactdrops. The Verify button under a reactdrop opens a form that asks for the sum of
+/// two numbers, written out. Users that answer it right can participate. Any other interaction is ignored.
+pub async fn handle_captcha(
+    ctx: &Context,
+    pool: &PgPool,
+    interaction: &Interaction,
+) -> Result<(), Error> {
+    match interaction {
+        Interaction::MessageComponent(press) if press.data.custom_id == CAPTCHA_BUTTON_ID => {
+            send_captcha(ctx, pool, press).await
         }
+        Interaction::ModalSubmit(submit) if submit.data.custom_id.starts_with(CAPTCHA_FORM_ID) => {
+            check_captcha(ctx, pool, submit).await
+        }
+        _ => Ok(()),
     }
+}
+
+async fn send_captcha(
+    ctx: &Context,
+    pool: &PgPool,
+    press: &MessageComponentInteraction,
+) -> Result<(), Error> {
+    let message_id = press.message.id;
+    let user_id = press.user.id;
+
+    // a uuid is random enough to pick two numbers.
+    let random = Uuid::new_v4();
+    let (a, b) = (
+        random.as_bytes()[0] as usize % 9 + 1,
+        random.as_bytes()[1] as usize % 9 + 1,
+    );
+
+    let refusal = match database::get_reactdrop_captcha(pool, message_id, user_id).await? {
+        Some((true, _)) => {
+            Some("You solved the captcha already. React to participate, if you haven't yet.")
+        }
+        Some((false, attempts)) if attempts >= MAX_CAPTCHA_ATTEMPTS => {
+            Some("You have no attempts left for the captcha of this reactdrop.")
+        }
+        _ if !database::insert_reactdrop_captcha(pool, message_id, user_id, (a + b) as i32)
+            .await? =>
+        {
+            Some("This reactdrop has ended.")
+        }
+        _ => None,
+    };
+
+    if let Some(refusal) = refusal {
+        press
+            .create_interaction_response(&ctx.http, |response| {
+                response
+                    .kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|data| data.ephemeral(true).content(refusal))
+            })
+            .await?;
+
+        return Ok(());
+    }
+
+    press
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::Modal)
+                .interaction_response_data(|data| {
+                    data.custom_id(format!("{CAPTCHA_FORM_ID}:{message_id}"))
+                        .title("Reactdrop captcha")
+                        .components(|components| {
+                            components.create_action_row(|row| {
+                                row.create_input_text(|input| {
+                                    input
+                                        .custom_id("answer")
+                                        .style(InputTextStyle::Short)
+                                        .label(format!(
+                                            "What is {} plus {}?",
+                                            CAPTCHA_NUMBERS[a], CAPTCHA_NUMBERS[b]
+                                        ))
+                                        .placeholder("Answer with a number")
+                                        .max_length(2)
+                                        .required(true)
+                                })
+                            })
+                        })
+                })
+        })
+        .await?;
 
     Ok(())
 }
+
+async fn check_captcha(
+    ctx: &Context,
+    pool: &PgPool,
+    submit: &ModalSubmitInteraction,
+) -> Result<(), Error> {
+    let Some(message_id) = submit
+        .data
+        .custom_id
+        .strip_prefix(&format!("{CAPTCHA_FORM_ID}:"))
+        .and_then(|message_id| message_id.parse::<u64>().ok())
+    else {
+        return Ok(());
+    };
+
+    // an answer that is not a number is a wrong answer.
+    let answer = submit
+        .data
+        .components
+        .iter()
+        .flat_map(|row| &row.components)
+        .find_map(|component| match component {
+            ActionRowComponent::InputText(input) => input.value.trim().parse::<i32>().ok(),
+            _ => None,
+        })
+        .unwrap_or(-1);
+
+    let solved = database::solve_reactdrop_captcha(
+        pool,
+        MessageId(message_id),
+        submit.user.id,
+        answer,
+        MAX_CAPTCHA_ATTEMPTS,
+    )
+    .await?;
+    debug!(
+        "{} answered the captcha of reactdrop {message_id}, solved: {solved:?}",
+        submit.user.id
+    );
+
+    let content = match solved {
+        Some(true) => String::from("You solved the captcha. React on the reactdrop to participate, if you haven't yet."),
+        None => String::from("This reactdrop has ended, or you have no attempts left for its captcha."),
+        Some(false) => format!(
+            "That is not the right answer. Press Verify to try again, you have {MAX_CAPTCHA_ATTEMPTS} attempts in total."
+        ),
+    };
+
+    submit
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|data| data.ephemeral(true).content(content))
+        })
+        .await?;
+
+    Ok(())
+}
+
+// Filters the users that reacted on the rules of the reactdrop. The users are in the order they reacted, so the participant cap keeps the first ones.
+async fn eligible_users(ctx: &Context, reactdrop: &Reactdrop, users: Vec<UserId>) -> Vec<UserId> {
+    let rules = &reactdrop.rules;
+    let now = chrono::Utc::now().timestamp();
+    let mut eligible = vec![];
+
+    for user_id in users {
+        if let Some(days) = rules.min_account_age_days {
+            if now - user_id.created_at().unix_timestamp() < days as i64 * 24 * 60 * 60 {
+                trace!("{user_id} account is too young");
+                continue;
+            }
+        }
+
+        if rules.needs_member() {
+            let Some(guild_id) = reactdrop.guild_id else {
+                continue;
+            };
+
+            let Ok(member) = guild_id.member(ctx, user_id).await else {
+                trace!("{user_id} is not a member of {guild_id}");
+                continue;
+            };
+
+            if let Some(role_id) = rules.required_role {
+                if !member.roles.contains(&role_id) {
+                    trace!("{user_id} does not have role {role_id}");
+                    continue;
+                }
+            }
+
+            if let Some(days) = rules.min_member_age_days {
+                let joined_long_enough = member.joined_at.map_or(false, |joined_at| {
+                    now - joined_at.unix_timestamp() >= days as i64 * 24 * 60 * 60
+                });
+
+                if !joined_long_enough {
+                    trace!("{user_id} joined {guild_id} too recently");
+                    continue;
+                }
+            }
+        }
+
+        eligible.push(user_id);
+
+        if rules
+            .max_participants
+            .map_or(false, |max| eligible.len() >= max as usize)
+        {
+            debug!("reached the maximum number of participants");
+            break;
+        }
+    }
+
+    eligible
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn participants(n: u64) -> Vec<(UserId, bool)> {
+        (1..=n).map(|id| (UserId(id), id % 2 == 0)).collect()
+    }
+
+    #[test]
+    fn split_rounds_down() {
+        let payouts = divide(
+            &DropMode::Split,
+            &participants(3),
+            &Amount::from_sat(100),
+            &Amount::from_sat(1),
+            "",
+        );
+
+        assert_eq!(payouts.len(), 3);
+        assert!(payouts
+            .iter()
+            .all(|(_, amount)| amount == &Amount::from_sat(33)));
+    }
+
+    #[test]
+    fn random_winners_are_deterministic() {
+        let mode = DropMode::RandomWinners { winners: 2 };
+        let first = divide(
+            &mode,
+            &participants(10),
+            &Amount::from_sat(1000),
+            &Amount::from_sat(1),
+            "00ab",
+        );
+        let second = divide(
+            &mode,
+            &participants(10),
+            &Amount::from_sat(1000),
+            &Amount::from_sat(1),
+            "00ab",
+        );
+
+        assert_eq!(first, second);
+        assert_eq!(first.len(), 2);
+        assert!(first
+            .iter()
+            .all(|(_, amount)| amount == &Amount::from_sat(500)));
+    }
+
+    #[test]
+    fn first_reactors() {
+        let payouts = divide(
+            &DropMode::FirstReactors { winners: 2 },
+            &participants(5),
+            &Amount::from_sat(1000),
+            &Amount::from_sat(1),
+            "",
+        );
+
+        assert_eq!(
+            payouts,
+            vec![
+                (UserId(1), Amount::from_sat(500)),
+                (UserId(2), Amount::from_sat(500))
+            ]
+        );
+    }
+
+    #[test]
+    fn shares_below_the_minimum_are_dropped() {
+        let mode = DropMode::WeightedByRole {
+            role_id: RoleId(1),
+            weight: 3,
+        };
+        let payouts = divide(
+            &mode,
+            &participants(2),
+            &Amount::from_sat(1000),
+            &Amount::from_sat(300),
+            "",
+        );
+
+        assert_eq!(payouts, vec![(UserId(2), Amount::from_sat(750))]);
+        assert!(divide(
+            &DropMode::Split,
+            &participants(4),
+            &Amount::from_sat(1000),
+            &Amount::from_sat(300),
+            ""
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn countdowns_slow_down_far_from_the_end() {
+        assert_eq!(countdown_interval(Duration::days(3)), Duration::hours(1));
+        assert_eq!(
+            countdown_interval(Duration::hours(5)),
+            Duration::minutes(10)
+        );
+        assert_eq!(
+            countdown_interval(Duration::minutes(30)),
+            Duration::minutes(1)
+        );
+        assert_eq!(
+            format_remaining(Duration::minutes(90)),
+            "1 hour(s) and 30 minute(s)"
+        );
+    }
+
+    #[test]
+    fn weighted_by_role() {
+        let mode = DropMode::WeightedByRole {
+            role_id: RoleId(1),
+            weight: 3,
+        };
+        let payouts = divide(
+            &mode,
+            &participants(2),
+            &Amount::from_sat(1000),
+            &Amount::from_sat(1),
+            "",
+        );
+
+        assert_eq!(
+            payouts,
+            vec![
+                (UserId(1), Amount::from_sat(250)),
+                (UserId(2), Amount::from_sat(750))
+            ]
+        );
+    }
+}
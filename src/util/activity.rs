@@ -0,0 +1,93 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use poise::serenity_prelude::{ChannelId, UserId};
+use sqlx::types::chrono::{DateTime, Duration, Utc};
+
+/// The longest time a user counts as active after their last message.
+pub const MAX_ACTIVITY_WINDOW: i64 = 24 * 60;
+
+/// Keeps track of the last time users posted in a channel, so `/rain` can find the recently active users.
+///
+/// Activity is only kept in memory: after a restart, only messages posted since are known.
+#[derive(Debug, Default)]
+pub struct ChannelActivity {
+    last_messages: Mutex<HashMap<ChannelId, HashMap<UserId, DateTime<Utc>>>>,
+}
+
+impl ChannelActivity {
+    /// Stores that a user posted in a channel, and forgets activity that is older than the maximum window.
+    pub fn record(&self, channel_id: ChannelId, user_id: UserId, at: DateTime<Utc>) {
+        let cutoff = at - Duration::minutes(MAX_ACTIVITY_WINDOW);
+        let mut last_messages = self.last_messages.lock().unwrap();
+
+        last_messages
+            .entry(channel_id)
+            .or_default()
+            .insert(user_id, at);
+
+        for users in last_messages.values_mut() {
+            users.retain(|_, last_message| *last_message >= cutoff);
+        }
+        last_messages.retain(|_, users| !users.is_empty());
+    }
+
+    /// Returns the users that posted in a channel since the given time, ordered by user id.
+    pub fn active_users(&self, channel_id: ChannelId, since: DateTime<Utc>) -> Vec<UserId> {
+        let last_messages = self.last_messages.lock().unwrap();
+
+        let mut users = last_messages
+            .get(&channel_id)
+            .map(|users| {
+                users
+                    .iter()
+                    .filter(|(_, last_message)| **last_message >= since)
+                    .map(|(user_id, _)| *user_id)
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        users.sort();
+
+        users
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn active_users_within_window() {
+        let activity = ChannelActivity::default();
+        let now = Utc::now();
+
+        activity.record(ChannelId(1), UserId(1), now - Duration::minutes(30));
+        activity.record(ChannelId(1), UserId(2), now - Duration::minutes(5));
+        activity.record(ChannelId(2), UserId(3), now);
+
+        assert_eq!(
+            activity.active_users(ChannelId(1), now - Duration::minutes(10)),
+            vec![UserId(2)]
+        );
+        assert_eq!(
+            activity.active_users(ChannelId(1), now - Duration::minutes(60)),
+            vec![UserId(1), UserId(2)]
+        );
+    }
+
+    #[test]
+    fn old_activity_is_forgotten() {
+        let activity = ChannelActivity::default();
+        let now = Utc::now();
+
+        activity.record(
+            ChannelId(1),
+            UserId(1),
+            now - Duration::minutes(MAX_ACTIVITY_WINDOW + 1),
+        );
+        activity.record(ChannelId(2), UserId(2), now);
+
+        assert!(activity
+            .active_users(ChannelId(1), now - Duration::days(2))
+            .is_empty());
+    }
+}
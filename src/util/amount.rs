@@ -0,0 +1,255 @@
+use std::fmt::Display;
+
+use vrsc::Amount;
+
+/// An amount as a user entered it, before it is turned into an `Amount`.
+///
+/// Accepted inputs:
+/// - a number of coins, optionally with a `k` or `m` suffix and a `vrsc` unit: `1.5`, `10k`, `2.5 vrsc`
+/// - a number of satoshis: `1000 sats`, `10k sat`
+/// - a percentage of the balance: `50%`, `half`, `all`, `max`
+/// - a USD value, converted with the VRSC price: `$5`, `5$`, `5 usd`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AmountInput {
+    Exact(Amount),
+    /// A percentage of the balance, in basis points (1/100th of a percent).
+    Percent(u32),
+    Usd(f64),
+}
+
+/// An amount could not be parsed or converted. The message is meant for the user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidAmount(pub String);
+
+impl Display for InvalidAmount {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidAmount {}
+
+impl AmountInput {
+    pub fn parse(input: &str) -> Result<Self, InvalidAmount> {
+        let input = input.trim().to_lowercase();
+        let invalid = || {
+            InvalidAmount(format!(
+                "`{input}` is not a valid amount. Try for example `1.5`, `1000 sats`, `50%`, `all` or `$5`"
+            ))
+        };
+
+        match input.as_str() {
+            "all" | "max" => return Ok(Self::Percent(10_000)),
+            "half" => return Ok(Self::Percent(5_000)),
+            _ => {}
+        }
+
+        if let Some(percent) = input.strip_suffix('%') {
+            let percent = parse_number(percent).ok_or_else(invalid)?;
+            if percent <= 0.0 || percent > 100.0 {
+                return Err(InvalidAmount(String::from(
+                    "A percentage must be more than 0% and at most 100%",
+                )));
+            }
+
+            return Ok(Self::Percent((percent * 100.0).round() as u32));
+        }
+
+        if let Some(usd) = input
+            .strip_prefix('$')
+            .or_else(|| input.strip_suffix('$'))
+            .or_else(|| input.strip_suffix("usd"))
+        {
+            return Ok(Self::Usd(parse_number(usd).ok_or_else(invalid)?));
+        }
+
+        if let Some(sats) = input
+            .strip_suffix("sats")
+            .or_else(|| input.strip_suffix("sat"))
+        {
+            let sats = parse_number(sats).ok_or_else(invalid)?;
+            if sats.fract() != 0.0 {
+                return Err(InvalidAmount(String::from(
+                    "An amount in sats can't have decimals",
+                )));
+            }
+
+            return Ok(Self::Exact(Amount::from_sat(sats as u64)));
+        }
+
+        let coins =
+            parse_number(input.strip_suffix("vrsc").unwrap_or(&input)).ok_or_else(invalid)?;
+
+        Amount::from_vrsc(coins)
+            .map(Self::Exact)
+            .map_err(|_| invalid())
+    }
+
+    /// Whether the amount depends on the balance or the price, so the user should see what it was converted to.
+    pub fn is_converted(&self) -> bool {
+        !matches!(self, Self::Exact(_))
+    }
+
+    /// Turns the input into an amount. `spendable` is the balance the user can spend, `usd_price` the price of one coin in USD
+    /// (only needed for USD values).
+    pub fn resolve(
+        &self,
+        spendable: Amount,
+        usd_price: Option<f64>,
+    ) -> Result<Amount, InvalidAmount> {
+        let amount = match *self {
+            Self::Exact(amount) => amount,
+            Self::Percent(basis_points) => Amount::from_sat(
+                (spendable.as_sat() as u128 * basis_points as u128 / 10_000) as u64,
+            ),
+            Self::Usd(usd) => {
+                let Some(price) = usd_price.filter(|price| *price > 0.0) else {
+                    return Err(InvalidAmount(String::from(
+                        "USD amounts can't be used right now, please enter the amount in coins",
+                    )));
+                };
+
+                Amount::from_vrsc(usd / price)
+                    .map_err(|_| InvalidAmount(format!("${usd} can't be converted to an amount")))?
+            }
+        };
+
+        if amount == Amount::ZERO {
+            return Err(InvalidAmount(String::from(
+                "The amount must be more than 0",
+            )));
+        }
+
+        Ok(amount)
+    }
+}
+
+/// An amount divided evenly over a number of recipients, in whole satoshis.
+///
+/// What can't be divided evenly (at most `recipients - 1` sats) is the remainder: it is not sent and stays with the sender.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Split {
+    pub share: Amount,
+    pub recipients: usize,
+    pub remainder: Amount,
+}
+
+impl Split {
+    /// Fails if there are no recipients, or if a share would be less than `min_share`.
+    pub fn new(
+        amount: Amount,
+        recipients: usize,
+        min_share: Amount,
+    ) -> Result<Self, InvalidAmount> {
+        if recipients == 0 {
+            return Err(InvalidAmount(String::from("There is nobody to tip")));
+        }
+
+        let share = Amount::from_sat(amount.as_sat() / recipients as u64);
+        if share < min_share || share == Amount::ZERO {
+            return Err(InvalidAmount(format!(
+                "Split over {recipients} recipients, everyone would get {:.8}, which is less than the minimum of {:.8}",
+                share.as_vrsc(),
+                min_share.as_vrsc()
+            )));
+        }
+
+        Ok(Self {
+            share,
+            recipients,
+            remainder: Amount::from_sat(amount.as_sat() % recipients as u64),
+        })
+    }
+
+    /// The amount that is actually sent: the shares of all recipients.
+    pub fn total(&self) -> Amount {
+        Amount::from_sat(self.share.as_sat() * self.recipients as u64)
+    }
+}
+
+// A non-negative number with an optional `k` (thousand) or `m` (million) suffix.
+fn parse_number(number: &str) -> Option<f64> {
+    let number = number.trim();
+    let (number, multiplier) = match number.strip_suffix('k') {
+        Some(number) => (number, 1_000.0),
+        None => match number.strip_suffix('m') {
+            Some(number) => (number, 1_000_000.0),
+            None => (number, 1.0),
+        },
+    };
+
+    number
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|number| number.is_finite() && *number >= 0.0)
+        .map(|number| number * multiplier)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_coins_and_sats() {
+        assert_eq!(
+            AmountInput::parse("1.5"),
+            Ok(AmountInput::Exact(Amount::from_sat(150_000_000)))
+        );
+        assert_eq!(
+            AmountInput::parse("2k VRSC"),
+            Ok(AmountInput::Exact(Amount::from_sat(200_000_000_000)))
+        );
+        assert_eq!(
+            AmountInput::parse("10k sats"),
+            Ok(AmountInput::Exact(Amount::from_sat(10_000)))
+        );
+        assert!(AmountInput::parse("1.5 sats").is_err());
+        assert!(AmountInput::parse("-1").is_err());
+        assert!(AmountInput::parse("lots").is_err());
+    }
+
+    #[test]
+    fn parses_keywords_percentages_and_usd() {
+        assert_eq!(AmountInput::parse("all"), Ok(AmountInput::Percent(10_000)));
+        assert_eq!(AmountInput::parse("Half"), Ok(AmountInput::Percent(5_000)));
+        assert_eq!(AmountInput::parse("12.5%"), Ok(AmountInput::Percent(1_250)));
+        assert!(AmountInput::parse("150%").is_err());
+        assert_eq!(AmountInput::parse("$5"), Ok(AmountInput::Usd(5.0)));
+        assert_eq!(AmountInput::parse("5 usd"), Ok(AmountInput::Usd(5.0)));
+    }
+
+    #[test]
+    fn split_keeps_the_remainder() {
+        let split = Split::new(Amount::from_sat(100), 3, Amount::from_sat(1)).unwrap();
+
+        assert_eq!(split.share, Amount::from_sat(33));
+        assert_eq!(split.remainder, Amount::from_sat(1));
+        assert_eq!(split.total(), Amount::from_sat(99));
+    }
+
+    #[test]
+    fn split_below_minimum_or_without_recipients_fails() {
+        assert!(Split::new(Amount::from_sat(100), 0, Amount::from_sat(1)).is_err());
+        assert!(Split::new(Amount::from_sat(100), 3, Amount::from_sat(34)).is_err());
+        assert!(Split::new(Amount::from_sat(2), 3, Amount::ZERO).is_err());
+    }
+
+    #[test]
+    fn resolves_against_balance_and_price() {
+        let balance = Amount::from_sat(1_000);
+
+        assert_eq!(
+            AmountInput::Percent(5_000).resolve(balance, None),
+            Ok(Amount::from_sat(500))
+        );
+        assert_eq!(
+            AmountInput::Usd(1.0).resolve(balance, Some(0.5)),
+            Ok(Amount::from_sat(200_000_000))
+        );
+        assert!(AmountInput::Usd(1.0).resolve(balance, None).is_err());
+        assert!(AmountInput::Percent(10_000)
+            .resolve(Amount::ZERO, None)
+            .is_err());
+    }
+}
@@ -0,0 +1,79 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use tracing::debug;
+use vrsc::{Address, Amount};
+use vrsc_rpc::client::{Client, RpcApi};
+
+/// Maps currency ids to their fully qualified names. Shared between the commands and the background tasks,
+/// every currency that is looked up in the daemon gets added to it.
+pub type CurrencyNames = Arc<RwLock<HashMap<Address, String>>>;
+
+/// A currency that can be deposited, tipped and withdrawn: VRSC or any PBaaS currency / token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Currency {
+    pub id: Address,
+    pub name: String,
+}
+
+impl Currency {
+    /// Formats an amount of this currency, e.g. `1.50000000 vETH`.
+    ///
+    /// `Amount` implements `Display`, but it always uses VRSC as the denomination.
+    pub fn display(&self, amount: &Amount) -> String {
+        format!("{:.8} {}", amount.as_vrsc(), self.name)
+    }
+}
+
+/// Builds the currency for an id from the cache, without asking the daemon. Falls back to the id as name if the currency is not cached.
+pub fn cached_currency(names: &CurrencyNames, id: &Address) -> Currency {
+    Currency {
+        name: names
+            .read()
+            .unwrap()
+            .get(id)
+            .cloned()
+            .unwrap_or_else(|| id.to_string()),
+        id: id.clone(),
+    }
+}
+
+/// Finds a currency by its name or its i-address. The cache is checked first, the daemon is asked if it is not in there.
+///
+/// Returns None if the daemon does not know the currency.
+pub fn lookup_currency(client: &Client, names: &CurrencyNames, query: &str) -> Option<Currency> {
+    if let Some((id, name)) = names
+        .read()
+        .unwrap()
+        .iter()
+        .find(|(id, name)| name.eq_ignore_ascii_case(query) || id.to_string() == query)
+    {
+        return Some(Currency {
+            id: id.clone(),
+            name: name.clone(),
+        });
+    }
+
+    match client.get_currency(query) {
+        Ok(currency) => {
+            let currency = Currency {
+                id: currency.currencyid,
+                name: currency.fullyqualifiedname,
+            };
+
+            names
+                .write()
+                .unwrap()
+                .insert(currency.id.clone(), currency.name.clone());
+
+            Some(currency)
+        }
+        Err(e) => {
+            debug!("currency {query} not found: {e:?}");
+
+            None
+        }
+    }
+}
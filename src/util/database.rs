@@ -1,22 +1,43 @@
-use std::str::FromStr;
+use std::{collections::HashSet, str::FromStr};
 
 use crate::{
     commands::misc::Notification,
-    reactdrop::{Reactdrop, ReactdropState},
+    reactdrop::{DropMode, Reactdrop, ReactdropRules, ReactdropState},
+    util::ledger::{
+        self, Account, HistoryEntry, HistoryFilter, InsufficientBalance, LedgerEntry, LedgerKind,
+    },
+    withdrawal::{Withdrawal, WithdrawalKind, WithdrawalState},
     Error,
 };
 use color_eyre::eyre::Report;
 use num_traits::cast::ToPrimitive;
-use poise::serenity_prelude::UserId;
+use poise::serenity_prelude::{GuildId, MessageId, RoleId, UserId};
 use sqlx::{
     types::chrono::{DateTime, Utc},
-    PgPool, Postgres, QueryBuilder,
+    PgPool, Postgres, QueryBuilder, Transaction,
 };
 use tracing::*;
 use uuid::Uuid;
 use vrsc::{Address, Amount};
 use vrsc_rpc::bitcoin::Txid;
 
+/// Runs the migrations. Migrations that fill in currencies for existing rows read the id of the native currency
+/// from the `verusbot.native_currency_id` setting, so they use the id of the chain the bot runs on.
+pub async fn run_migrations(pool: &PgPool, native_currency_id: &Address) -> Result<(), Error> {
+    let mut conn = pool.acquire().await?;
+
+    sqlx::query!(
+        "SELECT set_config('verusbot.native_currency_id', $1, false)",
+        native_currency_id.to_string()
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::migrate!("./migrations").run(&mut *conn).await?;
+
+    Ok(())
+}
+
 pub async fn insert_discord_user(pool: &PgPool, user_id: &UserId) -> Result<(), Error> {
     sqlx::query!(
         "INSERT INTO discord_users(discord_id) 
@@ -37,19 +58,24 @@ pub async fn store_tip_transactions(
     uuid: &Uuid,
     user_ids: &Vec<UserId>,
     kind: &str,
+    currency_id: &Address,
     amount: &Amount,
     counterparty: UserId, // this is always a user
+    memo: Option<&str>,
 ) -> Result<(), Error> {
-    let mut query_builder: QueryBuilder<Postgres> =
-        QueryBuilder::new("INSERT INTO tips_vrsc(uuid, discord_id, kind, amount, counterparty) ");
+    let mut query_builder: QueryBuilder<Postgres> = QueryBuilder::new(
+        "INSERT INTO tips_vrsc(uuid, discord_id, kind, currency_id, amount, counterparty, memo) ",
+    );
 
     let tuples = user_ids.iter().map(|user| {
         (
             uuid.to_string(),
             user.0 as i64,
             kind,
+            currency_id.to_string(),
             amount.as_sat() as i64,
             counterparty.0 as i64,
+            memo,
         )
     });
 
@@ -58,7 +84,9 @@ pub async fn store_tip_transactions(
             .push_bind(tuple.1)
             .push_bind(tuple.2)
             .push_bind(tuple.3)
-            .push_bind(tuple.4);
+            .push_bind(tuple.4)
+            .push_bind(tuple.5)
+            .push_bind(tuple.6);
     });
 
     query_builder.build().execute(pool).await?;
@@ -66,14 +94,19 @@ pub async fn store_tip_transactions(
     Ok(())
 }
 
-/// Queries the database and retrieves the balance for the user, if it exists.
-/// If there is no row for this user, None will be returned.
+/// Queries the database and retrieves the balance of a currency for the user, if it exists.
+/// If there is no row for this user and currency, None will be returned.
 ///
 /// The database has a constraint that balances can not go below 0.
-pub async fn get_balance_for_user(pool: &PgPool, user_id: &UserId) -> Result<Option<u64>, Error> {
+pub async fn get_balance_for_user(
+    pool: &PgPool,
+    user_id: &UserId,
+    currency_id: &Address,
+) -> Result<Option<u64>, Error> {
     if let Some(row) = sqlx::query!(
-        "SELECT balance FROM balance_vrsc WHERE discord_id = $1",
-        user_id.0 as i64
+        "SELECT balance FROM balances WHERE discord_id = $1 AND currency_id = $2",
+        user_id.0 as i64,
+        &currency_id.to_string()
     )
     .fetch_optional(pool)
     .await?
@@ -87,53 +120,62 @@ pub async fn get_balance_for_user(pool: &PgPool, user_id: &UserId) -> Result<Opt
     }
 }
 
+/// Returns the non-zero balances of all the currencies a user holds.
+pub async fn get_balances_for_user(
+    pool: &PgPool,
+    user_id: &UserId,
+) -> Result<Vec<(Address, u64)>, Error> {
+    let rows = sqlx::query!(
+        "SELECT currency_id, balance FROM balances WHERE discord_id = $1 AND balance > 0",
+        user_id.0 as i64
+    )
+    .fetch_all(pool)
+    .await?;
+
+    rows.into_iter()
+        .map(|row| Ok((Address::from_str(&row.currency_id)?, row.balance as u64)))
+        .collect()
+}
+
 // process a tip from 1 user to 1 or more users.
 // The tipper can tip himself.
-// This function both increases the balances for the tip receivers and decreases the balance of the tipper.
-// If one of these 2 actions fail, the database is not updated.
+// The tip is written to the ledger as one transfer: the tipper is debited for all the receivers, every receiver is credited.
+// If one of these actions fail, the database is not updated.
 pub async fn process_a_tip(
     pool: &PgPool,
+    tip_id: &Uuid,
     from_user: &UserId,
     to_users: &Vec<UserId>,
+    currency_id: &Address,
     tip_amount: &Amount,
 ) -> Result<(), Error> {
-    let mut tx = pool.begin().await?;
-
-    let mut query_builder: QueryBuilder<Postgres> =
-        QueryBuilder::new("INSERT INTO balance_vrsc (discord_id, balance) ");
-
-    let tuples = to_users
-        .iter()
-        .map(|user| (user.0 as i64, tip_amount.as_sat() as i64));
-
-    query_builder.push_values(tuples, |mut b, tuple| {
-        b.push_bind(tuple.0).push_bind(tuple.1);
-    });
-
-    query_builder
-        .push(" ON CONFLICT (discord_id) DO UPDATE SET balance = balance_vrsc.balance + $2");
+    let Some(mul) = tip_amount.checked_mul(to_users.len() as u64) else {
+        error!("something went wrong while processing a tip to multiple users");
 
-    query_builder.build().execute(&mut *tx).await?;
-
-    debug!("updated balances");
+        return Err("tip amount overflowed".into());
+    };
 
-    if let Some(mul) = tip_amount.checked_mul(to_users.len() as u64) {
-        sqlx::query!(
-            "UPDATE balance_vrsc SET balance = balance - $1 WHERE discord_id = $2",
-            mul.as_sat() as i64,
-            from_user.0 as i64
+    let mut entries = vec![LedgerEntry::new(
+        Account::User(*from_user),
+        currency_id,
+        LedgerKind::TipSend,
+        -(mul.as_sat() as i64),
+    )];
+
+    entries.extend(to_users.iter().map(|user| {
+        LedgerEntry::new(
+            Account::User(*user),
+            currency_id,
+            LedgerKind::TipReceive,
+            tip_amount.as_sat() as i64,
         )
-        .execute(&mut *tx)
-        .await?;
+    }));
 
-        tx.commit().await?;
-
-        debug!("decreased balances");
-        return Ok(());
-    }
+    let mut tx = pool.begin().await?;
+    apply_transfer(&mut tx, tip_id, None, &entries).await?;
+    tx.commit().await?;
 
-    error!("something went wrong while processing a tip to multiple users");
-    tx.rollback().await?;
+    debug!("updated balances");
 
     Ok(())
 }
@@ -209,66 +251,445 @@ pub async fn transaction_processed(pool: &PgPool, txid: &Txid) -> Result<bool, E
     }
 }
 
-pub async fn increase_balance(
+/// Credits a deposit to the balance of a user. The counterparty in the ledger is the chain account.
+pub async fn process_deposit(
     pool: &PgPool,
+    uuid: &Uuid,
     user_id: &UserId,
+    currency_id: &Address,
     amount: Amount,
+    txid: &Txid,
 ) -> Result<(), Error> {
     debug!(
-        "going to increase balance for {user_id} with {} VRSC",
+        "going to increase {currency_id} balance for {user_id} with {}",
         amount.as_vrsc()
     );
-    let result = sqlx::query!(
-        "INSERT INTO balance_vrsc (discord_id, balance)
-        VALUES ($1, $2)
-        ON CONFLICT (discord_id)
-        DO UPDATE SET balance = balance_vrsc.balance + $2",
+    let entries = vec![
+        LedgerEntry::new(
+            Account::User(*user_id),
+            currency_id,
+            LedgerKind::Deposit,
+            amount.as_sat() as i64,
+        ),
+        LedgerEntry::new(
+            Account::Chain,
+            currency_id,
+            LedgerKind::Deposit,
+            -(amount.as_sat() as i64),
+        ),
+    ];
+
+    let mut tx = pool.begin().await?;
+    apply_transfer(&mut tx, uuid, Some(&txid.to_string()), &entries).await?;
+    tx.commit().await?;
+
+    info!("increasing the balance went ok!");
+
+    Ok(())
+}
+
+/// Stores a queued withdrawal and reserves its amount and the `tx_fee`, which is always paid in `fee_currency_id`:
+/// both are moved from the balance of the user to the outbox, in the same database transaction.
+///
+/// Fails if the balance of the user is insufficient, in which case nothing is stored.
+pub async fn queue_withdrawal(
+    pool: &PgPool,
+    uuid: &Uuid,
+    user_id: &UserId,
+    currency_id: &Address,
+    amount: &Amount,
+    fee_currency_id: &Address,
+    tx_fee: &Amount,
+    destination: &str,
+    kind: WithdrawalKind,
+) -> Result<(), Error> {
+    debug!(
+        "going to reserve {} {currency_id} and {} {fee_currency_id} for a {kind} of {user_id}",
+        amount.as_vrsc(),
+        tx_fee.as_vrsc()
+    );
+    let entries = withdrawal_entries(
+        currency_id,
+        amount,
+        fee_currency_id,
+        tx_fee,
+        (Account::User(*user_id), Account::Outbox, Account::Outbox),
+        (kind.ledger_kind(), LedgerKind::Fee),
+    );
+
+    let mut tx = pool.begin().await?;
+    apply_transfer(&mut tx, uuid, Some(&uuid.to_string()), &entries).await?;
+
+    sqlx::query!(
+        "INSERT INTO withdrawals (id, discord_id, currency_id, amount, fee_currency_id, fee, destination, kind, status) \
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+        uuid.to_string(),
         user_id.0 as i64,
-        amount.as_sat() as i64
+        currency_id.to_string(),
+        amount.as_sat() as i64,
+        fee_currency_id.to_string(),
+        tx_fee.as_sat() as i64,
+        destination,
+        kind.to_string(),
+        WithdrawalState::Queued.to_string()
     )
-    .execute(pool)
-    .await;
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    info!("withdrawal {uuid} queued");
 
-    match result {
-        Ok(result) => info!("increasing the balance went ok! {:?}", result),
-        Err(e) => return Err(e.into()),
+    Ok(())
+}
+
+// A row of the withdrawals table, as it is stored.
+struct WithdrawalRow {
+    id: String,
+    discord_id: i64,
+    currency_id: String,
+    amount: i64,
+    fee_currency_id: String,
+    fee: i64,
+    destination: String,
+    kind: String,
+    status: String,
+    opid: Option<String>,
+    txid: Option<String>,
+}
+
+impl TryFrom<WithdrawalRow> for Withdrawal {
+    type Error = Error;
+
+    fn try_from(row: WithdrawalRow) -> Result<Self, Self::Error> {
+        Ok(Withdrawal {
+            id: Uuid::parse_str(&row.id)?,
+            user_id: UserId(row.discord_id as u64),
+            currency_id: Address::from_str(&row.currency_id)?,
+            amount: Amount::from_sat(row.amount as u64),
+            fee_currency_id: Address::from_str(&row.fee_currency_id)?,
+            fee: Amount::from_sat(row.fee as u64),
+            destination: row.destination,
+            kind: row.kind.try_into()?,
+            status: row.status.try_into()?,
+            opid: row.opid,
+            txid: row.txid.map(|txid| Txid::from_str(&txid)).transpose()?,
+        })
     }
+}
+
+pub async fn get_withdrawal(pool: &PgPool, uuid: &Uuid) -> Result<Option<Withdrawal>, Error> {
+    let row = sqlx::query_as!(
+        WithdrawalRow,
+        "SELECT id, discord_id, currency_id, amount, fee_currency_id, fee, destination, kind, status, opid, txid \
+        FROM withdrawals WHERE id = $1",
+        uuid.to_string()
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    row.map(Withdrawal::try_from).transpose()
+}
+
+/// Returns the withdrawals that are queued or broadcast, oldest first.
+///
+/// A withdrawal that can't be read is returned as an error, so it doesn't stop the others from being processed.
+pub async fn get_unfinished_withdrawals(
+    pool: &PgPool,
+) -> Result<Vec<Result<Withdrawal, Error>>, Error> {
+    let rows = sqlx::query_as!(
+        WithdrawalRow,
+        "SELECT id, discord_id, currency_id, amount, fee_currency_id, fee, destination, kind, status, opid, txid \
+        FROM withdrawals WHERE status IN ('queued', 'broadcast') ORDER BY created_at"
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            let id = row.id.clone();
+
+            Withdrawal::try_from(row).map_err(|e| format!("withdrawal {id} is invalid: {e}").into())
+        })
+        .collect())
+}
+
+/// Moves a queued withdrawal to broadcast, right before sendcurrency is called.
+///
+/// Returns false if the withdrawal was not queued (anymore), in which case it must not be sent.
+pub async fn claim_withdrawal(pool: &PgPool, uuid: &Uuid) -> Result<bool, Error> {
+    let result = sqlx::query!(
+        "UPDATE withdrawals SET status = 'broadcast' WHERE id = $1 AND status = 'queued'",
+        uuid.to_string()
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+pub async fn set_withdrawal_opid(pool: &PgPool, uuid: &Uuid, opid: &str) -> Result<(), Error> {
+    sqlx::query!(
+        "UPDATE withdrawals SET opid = $2 WHERE id = $1",
+        uuid.to_string(),
+        opid
+    )
+    .execute(pool)
+    .await?;
 
     Ok(())
 }
 
-pub async fn decrease_balance(
+/// Stores the txid of a broadcast withdrawal. Returns false if the txid was already known, or if the withdrawal is not broadcast.
+pub async fn set_withdrawal_txid(
+    tx: &mut Transaction<'_, Postgres>,
+    uuid: &Uuid,
+    txid: &Txid,
+) -> Result<bool, Error> {
+    let result = sqlx::query!(
+        "UPDATE withdrawals SET txid = $2 WHERE id = $1 AND status = 'broadcast' AND txid IS NULL",
+        uuid.to_string(),
+        txid.to_string()
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+/// Marks a broadcast withdrawal as confirmed and moves its reserved amount to the chain account and its fee to the fees account.
+///
+/// Returns false if the withdrawal was not broadcast, in which case nothing is changed.
+pub async fn confirm_withdrawal(pool: &PgPool, withdrawal: &Withdrawal) -> Result<bool, Error> {
+    let mut tx = pool.begin().await?;
+
+    let result = sqlx::query!(
+        "UPDATE withdrawals SET status = 'confirmed' WHERE id = $1 AND status = 'broadcast'",
+        withdrawal.id.to_string()
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    // the amount went on-chain, the fee stays with the bot.
+    let entries = withdrawal_entries(
+        &withdrawal.currency_id,
+        &withdrawal.amount,
+        &withdrawal.fee_currency_id,
+        &withdrawal.fee,
+        (Account::Outbox, Account::Chain, Account::Fees),
+        (withdrawal.kind.ledger_kind(), LedgerKind::Fee),
+    );
+
+    apply_transfer(
+        &mut tx,
+        &Uuid::new_v4(),
+        Some(&withdrawal.id.to_string()),
+        &entries,
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    info!("withdrawal {} confirmed", withdrawal.id);
+
+    Ok(true)
+}
+
+/// Marks a queued or broadcast withdrawal as failed and refunds its reserved amount and fee to the user.
+///
+/// Returns false if the withdrawal was already confirmed or failed, in which case nothing is changed.
+pub async fn fail_withdrawal(
     pool: &PgPool,
-    user_id: &UserId,
+    withdrawal: &Withdrawal,
+    reason: &str,
+) -> Result<bool, Error> {
+    let mut tx = pool.begin().await?;
+
+    let result = sqlx::query!(
+        "UPDATE withdrawals SET status = 'failed', error = $2 WHERE id = $1 AND status IN ('queued', 'broadcast')",
+        withdrawal.id.to_string(),
+        reason
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    let entries = withdrawal_entries(
+        &withdrawal.currency_id,
+        &withdrawal.amount,
+        &withdrawal.fee_currency_id,
+        &withdrawal.fee,
+        (
+            Account::Outbox,
+            Account::User(withdrawal.user_id),
+            Account::User(withdrawal.user_id),
+        ),
+        (LedgerKind::Refund, LedgerKind::Refund),
+    );
+
+    apply_transfer(
+        &mut tx,
+        &Uuid::new_v4(),
+        Some(&withdrawal.id.to_string()),
+        &entries,
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    warn!(
+        "withdrawal {} failed and was refunded: {reason}",
+        withdrawal.id
+    );
+
+    Ok(true)
+}
+
+// Moves the amount and the fee of a withdrawal from one account to another.
+fn withdrawal_entries(
+    currency_id: &Address,
     amount: &Amount,
+    fee_currency_id: &Address,
     tx_fee: &Amount,
+    (from, to, fee_to): (Account, Account, Account),
+    (amount_kind, fee_kind): (LedgerKind, LedgerKind),
+) -> Vec<LedgerEntry> {
+    let mut entries = vec![
+        LedgerEntry::new(from, currency_id, amount_kind, -(amount.as_sat() as i64)),
+        LedgerEntry::new(to, currency_id, amount_kind, amount.as_sat() as i64),
+    ];
+
+    if tx_fee > &Amount::ZERO {
+        entries.push(LedgerEntry::new(
+            from,
+            fee_currency_id,
+            fee_kind,
+            -(tx_fee.as_sat() as i64),
+        ));
+        entries.push(LedgerEntry::new(
+            fee_to,
+            fee_currency_id,
+            fee_kind,
+            tx_fee.as_sat() as i64,
+        ));
+    }
+
+    entries
+}
+
+/// Manually corrects the balance of a user with a positive or negative amount. The counterparty in the ledger is the adjustment account.
+pub async fn adjust_balance(
+    pool: &PgPool,
+    user_id: &UserId,
+    currency_id: &Address,
+    amount: i64,
+    reason: &str,
 ) -> Result<(), Error> {
-    if let Some(to_decrease) = amount.checked_add(*tx_fee) {
-        debug!(
-            "going to decrease balance for {user_id} with {} VRSC",
-            to_decrease.as_vrsc()
-        );
-        let result = sqlx::query!(
-            "UPDATE balance_vrsc SET balance = balance - $1 WHERE discord_id = $2",
-            to_decrease.as_sat() as i64,
-            user_id.0 as i64
-        )
-        .execute(pool)
-        .await;
+    let entries = vec![
+        LedgerEntry::new(
+            Account::User(*user_id),
+            currency_id,
+            LedgerKind::Adjustment,
+            amount,
+        ),
+        LedgerEntry::new(
+            Account::Adjustment,
+            currency_id,
+            LedgerKind::Adjustment,
+            -amount,
+        ),
+    ];
+
+    let mut tx = pool.begin().await?;
+    apply_transfer(&mut tx, &Uuid::new_v4(), Some(reason), &entries).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+// Writes the entries of a transfer to the ledger and applies the entries of user accounts to their balances.
+// This is the only place where balances are changed.
+//
+// Credits are upserted, debits are updated, as a debit on a balance that doesn't exist can never succeed.
+// Returns `InsufficientBalance` if a debit would make a balance negative, in which case the caller must not commit.
+async fn apply_transfer(
+    tx: &mut Transaction<'_, Postgres>,
+    transfer_id: &Uuid,
+    reference: Option<&str>,
+    entries: &[LedgerEntry],
+) -> Result<(), Error> {
+    if !ledger::is_balanced(entries) {
+        error!("transfer {transfer_id} does not balance: {entries:?}");
+
+        return Err(format!("transfer {transfer_id} does not balance").into());
+    }
+
+    let mut query_builder: QueryBuilder<Postgres> = QueryBuilder::new(
+        "INSERT INTO ledger (transfer_id, account, discord_id, currency_id, kind, amount, reference) ",
+    );
+
+    query_builder.push_values(entries, |mut b, entry| {
+        b.push_bind(transfer_id.to_string())
+            .push_bind(entry.account.name())
+            .push_bind(entry.account.discord_id())
+            .push_bind(entry.currency_id.to_string())
+            .push_bind(entry.kind.to_string())
+            .push_bind(entry.amount)
+            .push_bind(reference);
+    });
+
+    query_builder.build().execute(&mut **tx).await?;
+
+    let mut changes = ledger::user_balance_changes(entries);
+    // balances are always locked in the same order, so concurrent transfers between the same users can't deadlock.
+    changes.sort_by_key(|(user_id, currency_id, _)| (user_id.0, currency_id.to_string()));
+
+    for (user_id, currency_id, amount) in changes {
+        if amount > 0 {
+            sqlx::query!(
+                "INSERT INTO balances (discord_id, currency_id, balance) VALUES ($1, $2, $3) \
+                ON CONFLICT (discord_id, currency_id) DO UPDATE SET balance = balances.balance + EXCLUDED.balance",
+                user_id.0 as i64,
+                currency_id.to_string(),
+                amount
+            )
+            .execute(&mut **tx)
+            .await?;
+        } else {
+            // the balance is checked and debited in one statement, which locks the row until the transaction ends.
+            let result = sqlx::query!(
+                "UPDATE balances SET balance = balance + $1 WHERE discord_id = $2 AND currency_id = $3 AND balance + $1 >= 0",
+                amount,
+                user_id.0 as i64,
+                currency_id.to_string()
+            )
+            .execute(&mut **tx)
+            .await?;
 
-        match result {
-            Ok(result) => info!("decreasing the balance went ok! {:?}", result),
-            Err(e) => {
-                error!("something went wrong while decreasing the balance: {:?}", e);
-                return Err(e.into());
+            if result.rows_affected() == 0 {
+                debug!(
+                    "{user_id} has insufficient {currency_id} balance for transfer {transfer_id}"
+                );
+
+                return Err(InsufficientBalance {
+                    user_id,
+                    currency_id,
+                }
+                .into());
             }
         }
-    } else {
-        // summing the 2 balances went wrong. This is an edge case that only happens when someone is withdrawing more than 184,467,440,737.09551615 VRSC,
-        // which is more than the supply of VRSC will ever be.
-        unreachable!()
-        // TODO: It could be that a PBaaS chain will have such a supply, in which case we need to catch the error and inform the user. But not needed right now.
     }
+
+    debug!("applied transfer {transfer_id}");
+
     Ok(())
 }
 
@@ -276,14 +697,16 @@ pub async fn store_deposit_transaction(
     pool: &PgPool,
     uuid: &Uuid,
     user_id: &UserId,
+    currency_id: &Address,
     tx_hash: &Txid,
 ) -> Result<(), Error> {
     sqlx::query!(
-        "INSERT INTO transactions_vrsc (uuid, discord_id, transaction_id, transaction_action) VALUES ($1, $2, $3, $4)",
+        "INSERT INTO transactions_vrsc (uuid, discord_id, transaction_id, transaction_action, currency_id) VALUES ($1, $2, $3, $4, $5)",
         uuid.to_string(),
         user_id.0 as i64,
         tx_hash.to_string(),
-        "deposit"
+        "deposit",
+        currency_id.to_string()
         )
         .execute(pool)
         .await?;
@@ -292,9 +715,10 @@ pub async fn store_deposit_transaction(
 }
 
 pub async fn store_withdraw_transaction(
-    pool: &PgPool,
+    tx: &mut Transaction<'_, Postgres>,
     uuid: &Uuid,
     user_id: &UserId,
+    currency_id: &Address,
     tx_hash: Option<&Txid>,
     opid: &str,
     tx_fee: &Amount,
@@ -305,22 +729,24 @@ pub async fn store_withdraw_transaction(
         String::from("")
     };
     sqlx::query!(
-        "INSERT INTO transactions_vrsc (uuid, discord_id, transaction_id, opid, transaction_action, fee) VALUES ($1, $2, $3, $4, $5, $6)",
+        "INSERT INTO transactions_vrsc (uuid, discord_id, transaction_id, opid, transaction_action, fee, currency_id) VALUES ($1, $2, $3, $4, $5, $6, $7)",
         uuid.to_string(),
         user_id.0 as i64,
         tx_hash,
         opid,
         "withdraw",
-        tx_fee.as_sat() as i64
+        tx_fee.as_sat() as i64,
+        currency_id.to_string()
         )
-        .execute(pool)
+        .execute(&mut **tx)
         .await?;
 
     Ok(())
 }
 
+/// Stores what the daemon reported about a finished operation. Storing the same operation again is a no-op.
 pub async fn store_opid(
-    pool: &PgPool,
+    tx: &mut Transaction<'_, Postgres>,
     opid: &str,
     status: &str,
     creation_time: i64,
@@ -330,7 +756,8 @@ pub async fn store_opid(
     currency: &str,
 ) -> Result<(), Error> {
     sqlx::query!(
-        "INSERT INTO opids (opid, status, creation_time, result, address, amount, currency) VALUES ($1, $2, $3, $4, $5, $6, $7)",
+        "INSERT INTO opids (opid, status, creation_time, result, address, amount, currency) VALUES ($1, $2, $3, $4, $5, $6, $7) \
+        ON CONFLICT (opid) DO NOTHING",
         opid.to_string(),
         status.to_string(),
         creation_time,
@@ -339,7 +766,7 @@ pub async fn store_opid(
         (amount * 100_000_000.0) as i64,
         currency.to_string()
         )
-        .execute(pool)
+        .execute(&mut **tx)
         .await?;
 
     Ok(())
@@ -362,6 +789,40 @@ pub async fn update_notifications(
     Ok(())
 }
 
+/// Returns the amount above which tips of a user need to be confirmed, if the user set one.
+pub async fn get_confirm_threshold(
+    pool: &PgPool,
+    user_id: &UserId,
+) -> Result<Option<Amount>, Error> {
+    let row = sqlx::query!(
+        "SELECT confirm_threshold FROM discord_users WHERE discord_id = $1",
+        user_id.0 as i64
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row
+        .and_then(|row| row.confirm_threshold)
+        .map(|threshold| Amount::from_sat(threshold as u64)))
+}
+
+pub async fn set_confirm_threshold(
+    pool: &PgPool,
+    user_id: &UserId,
+    threshold: Option<Amount>,
+) -> Result<(), Error> {
+    // pre_command takes care of having a db row at this point for this user.
+    sqlx::query!(
+        "UPDATE discord_users SET confirm_threshold = $1 WHERE discord_id = $2",
+        threshold.map(|threshold| threshold.as_sat() as i64),
+        user_id.0 as i64
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
 pub async fn get_notification_settings(
     pool: &PgPool,
     user_ids: &Vec<UserId>,
@@ -398,6 +859,18 @@ pub async fn get_blacklist_status(pool: &PgPool, user_id: UserId) -> Result<Opti
     }
 }
 
+/// Returns all users that are blacklisted.
+pub async fn get_blacklisted_users(pool: &PgPool) -> Result<HashSet<UserId>, Error> {
+    let rows = sqlx::query!("SELECT discord_id FROM discord_users WHERE blacklisted = true")
+        .fetch_all(pool)
+        .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| UserId(row.discord_id as u64))
+        .collect())
+}
+
 // TODO user might not exist?
 pub async fn set_blacklist_status(
     pool: &PgPool,
@@ -450,11 +923,14 @@ pub async fn set_stored_txid_to_processed(pool: &PgPool, txid: &Txid) -> Result<
     Ok(())
 }
 
-// sums all the balances currently in the database and returns them
-pub async fn get_total_balance(pool: &PgPool) -> Result<u64, Error> {
-    let record = sqlx::query!("SELECT SUM(CAST(balance AS BIGINT)) FROM balance_vrsc")
-        .fetch_one(pool)
-        .await?;
+// sums all the balances of a currency currently in the database and returns them
+pub async fn get_total_balance(pool: &PgPool, currency_id: &Address) -> Result<u64, Error> {
+    let record = sqlx::query!(
+        "SELECT SUM(CAST(balance AS BIGINT)) FROM balances WHERE currency_id = $1",
+        &currency_id.to_string()
+    )
+    .fetch_one(pool)
+    .await?;
 
     if let Some(balance) = record.sum {
         return Ok(balance.to_u64().unwrap());
@@ -463,10 +939,13 @@ pub async fn get_total_balance(pool: &PgPool) -> Result<u64, Error> {
     Ok(0)
 }
 
-pub async fn get_total_tipped(pool: &PgPool) -> Result<u64, Error> {
-    let record = sqlx::query!("SELECT SUM(CAST(amount AS BIGINT)) FROM tips_vrsc")
-        .fetch_one(pool)
-        .await?;
+pub async fn get_total_tipped(pool: &PgPool, currency_id: &Address) -> Result<u64, Error> {
+    let record = sqlx::query!(
+        "SELECT SUM(CAST(amount AS BIGINT)) FROM tips_vrsc WHERE currency_id = $1",
+        &currency_id.to_string()
+    )
+    .fetch_one(pool)
+    .await?;
 
     if let Some(total) = record.sum {
         return Ok(total.to_u64().unwrap());
@@ -475,10 +954,13 @@ pub async fn get_total_tipped(pool: &PgPool) -> Result<u64, Error> {
     Ok(0)
 }
 
-pub async fn get_largest_tip(pool: &PgPool) -> Result<u64, Error> {
-    let record = sqlx::query!("SELECT MAX(amount) FROM tips_vrsc")
-        .fetch_one(pool)
-        .await?;
+pub async fn get_largest_tip(pool: &PgPool, currency_id: &Address) -> Result<u64, Error> {
+    let record = sqlx::query!(
+        "SELECT MAX(amount) FROM tips_vrsc WHERE currency_id = $1",
+        &currency_id.to_string()
+    )
+    .fetch_one(pool)
+    .await?;
 
     if let Some(max) = record.max {
         return Ok(max.to_u64().unwrap());
@@ -487,32 +969,171 @@ pub async fn get_largest_tip(pool: &PgPool) -> Result<u64, Error> {
     Ok(0)
 }
 
-pub async fn get_all_txids(pool: &PgPool, transaction_action: &str) -> Result<Vec<Txid>, Error> {
+/// Sets whether a user is left out of the leaderboards.
+pub async fn set_leaderboard_opt_out(
+    pool: &PgPool,
+    user_id: &UserId,
+    opt_out: bool,
+) -> Result<(), Error> {
+    // pre_command takes care of having a db row at this point for this user.
+    sqlx::query!(
+        "UPDATE discord_users SET leaderboard_opt_out = $1 WHERE discord_id = $2",
+        opt_out,
+        user_id.0 as i64
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Returns the users that tipped the most of a currency since a time, leaving out users that opted out.
+/// With a kind, only tips of that kind (`reactdrop`, `rain`, ...) are counted.
+pub async fn get_top_tippers(
+    pool: &PgPool,
+    currency_id: &Address,
+    since: Option<DateTime<Utc>>,
+    kind: Option<&str>,
+    limit: i64,
+) -> Result<Vec<(UserId, Amount)>, Error> {
+    // the tipper is stored as the counterparty of the rows of the receivers.
     let rows = sqlx::query!(
-        "SELECT transaction_id FROM transactions_vrsc WHERE transaction_action = $1",
-        transaction_action
+        r#"SELECT u.discord_id, SUM(t.amount)::BIGINT AS "total!"
+        FROM tips_vrsc t
+        JOIN discord_users u ON u.discord_id = CAST(t.counterparty AS BIGINT)
+        WHERE t.currency_id = $1
+        AND ($2::timestamptz IS NULL OR t.created_at >= $2)
+        AND ($3::text IS NULL OR t.kind = $3)
+        AND NOT u.leaderboard_opt_out
+        GROUP BY u.discord_id
+        ORDER BY 2 DESC
+        LIMIT $4"#,
+        currency_id.to_string(),
+        since,
+        kind,
+        limit
     )
     .fetch_all(pool)
     .await?;
 
-    return Ok(rows
+    Ok(rows
         .into_iter()
-        .map(|row| Txid::from_str(&row.transaction_id).unwrap())
-        .collect::<Vec<_>>());
+        .map(|row| {
+            (
+                UserId(row.discord_id as u64),
+                Amount::from_sat(row.total as u64),
+            )
+        })
+        .collect())
 }
 
-pub async fn insert_reactdrop(
+/// Returns the users that received the most of a currency since a time, leaving out users that opted out.
+pub async fn get_top_receivers(
     pool: &PgPool,
-    author: i64,
-    emoji: String,
-    amount: i64,
-    channel_id: i64,
+    currency_id: &Address,
+    since: Option<DateTime<Utc>>,
+    limit: i64,
+) -> Result<Vec<(UserId, Amount)>, Error> {
+    let rows = sqlx::query!(
+        r#"SELECT u.discord_id, SUM(t.amount)::BIGINT AS "total!"
+        FROM tips_vrsc t
+        JOIN discord_users u ON u.discord_id = t.discord_id
+        WHERE t.currency_id = $1
+        AND ($2::timestamptz IS NULL OR t.created_at >= $2)
+        AND NOT u.leaderboard_opt_out
+        GROUP BY u.discord_id
+        ORDER BY 2 DESC
+        LIMIT $3"#,
+        currency_id.to_string(),
+        since,
+        limit
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            (
+                UserId(row.discord_id as u64),
+                Amount::from_sat(row.total as u64),
+            )
+        })
+        .collect())
+}
+
+/// Returns the biggest single tips of a currency since a time: the tipper, the kind of tip, the total amount and the number of receivers.
+/// Tips of users that opted out are left out.
+pub async fn get_biggest_tips(
+    pool: &PgPool,
+    currency_id: &Address,
+    since: Option<DateTime<Utc>>,
+    limit: i64,
+) -> Result<Vec<(UserId, String, Amount, i64)>, Error> {
+    // the rows of a tip share the same uuid, one for every receiver.
+    let rows = sqlx::query!(
+        r#"SELECT u.discord_id, t.kind, SUM(t.amount)::BIGINT AS "total!", COUNT(*) AS "receivers!"
+        FROM tips_vrsc t
+        JOIN discord_users u ON u.discord_id = CAST(t.counterparty AS BIGINT)
+        WHERE t.currency_id = $1
+        AND ($2::timestamptz IS NULL OR t.created_at >= $2)
+        AND NOT u.leaderboard_opt_out
+        GROUP BY t.uuid, u.discord_id, t.kind
+        ORDER BY 3 DESC
+        LIMIT $3"#,
+        currency_id.to_string(),
+        since,
+        limit
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            (
+                UserId(row.discord_id as u64),
+                row.kind,
+                Amount::from_sat(row.total as u64),
+                row.receivers,
+            )
+        })
+        .collect())
+}
+
+/// Stores a new reactdrop and moves its amount from the balance of the author to escrow, in the same database transaction.
+///
+/// Fails with `InsufficientBalance` if the author can't cover the amount, in which case nothing is stored.
+pub async fn insert_reactdrop(
+    pool: &PgPool,
+    author: i64,
+    emoji: String,
+    currency_id: &Address,
+    amount: i64,
+    guild_id: Option<i64>,
+    channel_id: i64,
     message_id: i64,
     finish_time: DateTime<Utc>,
+    rules: &ReactdropRules,
+    mode: &DropMode,
 ) -> Result<(), Error> {
-    sqlx::query!(
-        "INSERT INTO reactdrops(author, channel_id, message_id, finish_time, emojistr, amount, status) \
-    VALUES ($1, $2, $3, $4, $5, $6, 'pending') \
+    let (winners, weighted_role_id, role_weight) = match *mode {
+        DropMode::Split => (None, None, None),
+        DropMode::RandomWinners { winners } | DropMode::FirstReactors { winners } => {
+            (Some(winners), None, None)
+        }
+        DropMode::WeightedByRole { role_id, weight } => {
+            (None, Some(role_id.0 as i64), Some(weight))
+        }
+    };
+
+    let mut tx = pool.begin().await?;
+
+    let result = sqlx::query!(
+        "INSERT INTO reactdrops(author, channel_id, message_id, finish_time, emojistr, currency_id, amount, status, escrowed, \
+        guild_id, required_role_id, min_account_age_days, min_member_age_days, max_participants, \
+        mode, winners, weighted_role_id, role_weight, captcha) \
+    VALUES ($1, $2, $3, $4, $5, $6, $7, 'pending', true, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17) \
     ON CONFLICT (channel_id, message_id) \
     DO NOTHING",
         author,
@@ -520,7 +1141,263 @@ pub async fn insert_reactdrop(
         message_id,
         finish_time,
         emoji,
+        currency_id.to_string(),
         amount,
+        guild_id,
+        rules.required_role.map(|role_id| role_id.0 as i64),
+        rules.min_account_age_days,
+        rules.min_member_age_days,
+        rules.max_participants,
+        mode.name(),
+        winners,
+        weighted_role_id,
+        role_weight,
+        rules.captcha,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(format!("reactdrop {channel_id}/{message_id} already exists").into());
+    }
+
+    let entries = vec![
+        LedgerEntry::new(
+            Account::User(UserId(author as u64)),
+            currency_id,
+            LedgerKind::ReactdropEscrow,
+            -amount,
+        ),
+        LedgerEntry::new(
+            Account::Escrow,
+            currency_id,
+            LedgerKind::ReactdropEscrow,
+            amount,
+        ),
+    ];
+
+    apply_transfer(
+        &mut tx,
+        &Uuid::new_v4(),
+        Some(&reactdrop_reference(channel_id, message_id)),
+        &entries,
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Pays a finished (escrowed) reactdrop out of escrow: every user gets their amount, what is left is refunded to the author.
+/// The reactdrop is marked as processed in the same database transaction, so it can only be paid out once.
+///
+/// Returns false if the reactdrop was not pending anymore, in which case nothing is paid.
+pub async fn payout_reactdrop(
+    pool: &PgPool,
+    tip_id: &Uuid,
+    reactdrop: &Reactdrop,
+    payouts: &[(UserId, Amount)],
+) -> Result<bool, Error> {
+    let total = payouts
+        .iter()
+        .map(|(_, amount)| amount.as_sat())
+        .sum::<u64>();
+    let Some(remainder) = reactdrop.tip_amount.checked_sub(Amount::from_sat(total)) else {
+        return Err("reactdrop pays out more than is in escrow".into());
+    };
+
+    let mut entries = vec![LedgerEntry::new(
+        Account::Escrow,
+        &reactdrop.currency_id,
+        LedgerKind::ReactdropEscrow,
+        -(reactdrop.tip_amount.as_sat() as i64),
+    )];
+
+    entries.extend(payouts.iter().map(|(user, amount)| {
+        LedgerEntry::new(
+            Account::User(*user),
+            &reactdrop.currency_id,
+            LedgerKind::TipReceive,
+            amount.as_sat() as i64,
+        )
+    }));
+
+    if remainder > Amount::ZERO {
+        entries.push(LedgerEntry::new(
+            Account::User(reactdrop.author),
+            &reactdrop.currency_id,
+            LedgerKind::Refund,
+            remainder.as_sat() as i64,
+        ));
+    }
+
+    let mut tx = pool.begin().await?;
+
+    if !finish_reactdrop(&mut tx, reactdrop, ReactdropState::Processed).await? {
+        return Ok(false);
+    }
+
+    apply_transfer(
+        &mut tx,
+        tip_id,
+        Some(&reactdrop_reference(
+            reactdrop.channel_id.0 as i64,
+            reactdrop.message_id.0 as i64,
+        )),
+        &entries,
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(true)
+}
+
+/// Refunds the escrow of a reactdrop to its author and sets its new status.
+///
+/// Returns false if the reactdrop was not pending anymore, in which case nothing is refunded.
+pub async fn refund_reactdrop(
+    pool: &PgPool,
+    reactdrop: &Reactdrop,
+    status: ReactdropState,
+) -> Result<bool, Error> {
+    let mut tx = pool.begin().await?;
+
+    if !finish_reactdrop(&mut tx, reactdrop, status).await? {
+        return Ok(false);
+    }
+
+    // reactdrops from before the escrow was introduced have nothing to refund.
+    if reactdrop.escrowed {
+        let entries = vec![
+            LedgerEntry::new(
+                Account::Escrow,
+                &reactdrop.currency_id,
+                LedgerKind::ReactdropEscrow,
+                -(reactdrop.tip_amount.as_sat() as i64),
+            ),
+            LedgerEntry::new(
+                Account::User(reactdrop.author),
+                &reactdrop.currency_id,
+                LedgerKind::Refund,
+                reactdrop.tip_amount.as_sat() as i64,
+            ),
+        ];
+
+        apply_transfer(
+            &mut tx,
+            &Uuid::new_v4(),
+            Some(&reactdrop_reference(
+                reactdrop.channel_id.0 as i64,
+                reactdrop.message_id.0 as i64,
+            )),
+            &entries,
+        )
+        .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(true)
+}
+
+/// Stores the participants of a reactdrop that has ended, together with the height of the block that draws its
+/// winners. Does nothing if they were already stored.
+pub async fn snapshot_reactdrop(
+    pool: &PgPool,
+    reactdrop: &Reactdrop,
+    participants: &[UserId],
+    height: i64,
+) -> Result<(), Error> {
+    let mut tx = pool.begin().await?;
+
+    let result = sqlx::query!(
+        "UPDATE reactdrops SET seed_height = $3 WHERE channel_id = $1 AND message_id = $2 AND status = 'pending' AND seed_height IS NULL",
+        reactdrop.channel_id.0 as i64,
+        reactdrop.message_id.0 as i64,
+        height
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Ok(());
+    }
+
+    if !participants.is_empty() {
+        let mut query_builder: QueryBuilder<Postgres> = QueryBuilder::new(
+            "INSERT INTO reactdrop_participants (message_id, discord_id, position) ",
+        );
+
+        query_builder.push_values(
+            participants.iter().enumerate(),
+            |mut b, (position, user_id)| {
+                b.push_bind(reactdrop.message_id.0 as i64)
+                    .push_bind(user_id.0 as i64)
+                    .push_bind(position as i32);
+            },
+        );
+
+        query_builder.build().execute(&mut *tx).await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Returns the participants that were stored when a reactdrop ended, in the order they reacted.
+pub async fn get_reactdrop_participants(
+    pool: &PgPool,
+    reactdrop: &Reactdrop,
+) -> Result<Vec<UserId>, Error> {
+    let rows = sqlx::query!(
+        "SELECT discord_id FROM reactdrop_participants WHERE message_id = $1 ORDER BY position",
+        reactdrop.message_id.0 as i64
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| UserId(row.discord_id as u64))
+        .collect())
+}
+
+/// Stores the hash of the block that was used to draw the winners of a reactdrop.
+pub async fn set_reactdrop_seed_hash(
+    pool: &PgPool,
+    reactdrop: &Reactdrop,
+    hash: &str,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "UPDATE reactdrops SET seed_hash = $3 WHERE channel_id = $1 AND message_id = $2",
+        reactdrop.channel_id.0 as i64,
+        reactdrop.message_id.0 as i64,
+        hash
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Stores the first time a user reacted on a running reactdrop with its emoji. Does nothing for any other message or
+/// emoji, or once the reactdrop has ended.
+pub async fn insert_reactdrop_reaction(
+    pool: &PgPool,
+    message_id: MessageId,
+    user_id: UserId,
+    emoji: &str,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "INSERT INTO reactdrop_reactions (message_id, discord_id) \
+        SELECT message_id, $2 FROM reactdrops WHERE message_id = $1 AND emojistr = $3 AND status = 'pending' AND finish_time > NOW() \
+        ON CONFLICT (message_id, discord_id) DO NOTHING",
+        message_id.0 as i64,
+        user_id.0 as i64,
+        emoji
     )
     .execute(pool)
     .await?;
@@ -528,6 +1405,149 @@ pub async fn insert_reactdrop(
     Ok(())
 }
 
+/// Returns the users that reacted on a reactdrop, in the order they reacted.
+pub async fn get_reactdrop_reactions(
+    pool: &PgPool,
+    reactdrop: &Reactdrop,
+) -> Result<Vec<UserId>, Error> {
+    let rows = sqlx::query!(
+        "SELECT discord_id FROM reactdrop_reactions WHERE message_id = $1 ORDER BY reacted_at",
+        reactdrop.message_id.0 as i64
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| UserId(row.discord_id as u64))
+        .collect())
+}
+
+/// Returns whether the captcha of a reactdrop was solved by a user, and how many answers they gave.
+/// Returns None if the user didn't get a captcha for the reactdrop yet.
+pub async fn get_reactdrop_captcha(
+    pool: &PgPool,
+    message_id: MessageId,
+    user_id: UserId,
+) -> Result<Option<(bool, i32)>, Error> {
+    let row = sqlx::query!(
+        "SELECT solved, attempts FROM reactdrop_captchas WHERE message_id = $1 AND discord_id = $2",
+        message_id.0 as i64,
+        user_id.0 as i64
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|row| (row.solved, row.attempts)))
+}
+
+/// Stores the answer of a new captcha for a user, if the reactdrop is running and asks for a captcha.
+/// Returns false otherwise.
+pub async fn insert_reactdrop_captcha(
+    pool: &PgPool,
+    message_id: MessageId,
+    user_id: UserId,
+    answer: i32,
+) -> Result<bool, Error> {
+    let result = sqlx::query!(
+        "INSERT INTO reactdrop_captchas (message_id, discord_id, answer) \
+        SELECT message_id, $2, $3 FROM reactdrops WHERE message_id = $1 AND captcha AND status = 'pending' AND finish_time > NOW() \
+        ON CONFLICT (message_id, discord_id) DO UPDATE SET answer = $3 WHERE reactdrop_captchas.solved = false",
+        message_id.0 as i64,
+        user_id.0 as i64,
+        answer
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+/// Checks the answer of a user on their captcha. Every answer counts as an attempt, nothing is changed once the
+/// user has `max_attempts` attempts or the reactdrop has ended. Returns whether the answer was right, or None if
+/// the answer was not checked.
+pub async fn solve_reactdrop_captcha(
+    pool: &PgPool,
+    message_id: MessageId,
+    user_id: UserId,
+    answer: i32,
+    max_attempts: i32,
+) -> Result<Option<bool>, Error> {
+    let row = sqlx::query!(
+        "UPDATE reactdrop_captchas SET solved = (answer = $3), attempts = attempts + 1 \
+        WHERE message_id = $1 AND discord_id = $2 AND solved = false AND attempts < $4 \
+        AND EXISTS (SELECT 1 FROM reactdrops WHERE reactdrops.message_id = $1 AND status = 'pending' AND finish_time > NOW()) \
+        RETURNING solved",
+        message_id.0 as i64,
+        user_id.0 as i64,
+        answer,
+        max_attempts
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|row| row.solved))
+}
+
+/// Returns the users that solved the captcha of a reactdrop.
+pub async fn get_reactdrop_captcha_solvers(
+    pool: &PgPool,
+    reactdrop: &Reactdrop,
+) -> Result<Vec<UserId>, Error> {
+    let rows = sqlx::query!(
+        "SELECT discord_id FROM reactdrop_captchas WHERE message_id = $1 AND solved",
+        reactdrop.message_id.0 as i64
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| UserId(row.discord_id as u64))
+        .collect())
+}
+
+/// Sets a new finish time for a pending reactdrop. Returns false if the reactdrop was not pending anymore or has
+/// already ended, which would let the block that draws its winners be known before it finishes.
+pub async fn extend_reactdrop(
+    pool: &PgPool,
+    reactdrop: &Reactdrop,
+    finish_time: DateTime<Utc>,
+) -> Result<bool, Error> {
+    let result = sqlx::query!(
+        "UPDATE reactdrops SET finish_time = $3 WHERE channel_id = $1 AND message_id = $2 AND status = 'pending' AND finish_time > NOW()",
+        reactdrop.channel_id.0 as i64,
+        reactdrop.message_id.0 as i64,
+        finish_time
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+// Moves a pending reactdrop to its final status. Returns false if it was not pending.
+async fn finish_reactdrop(
+    tx: &mut Transaction<'_, Postgres>,
+    reactdrop: &Reactdrop,
+    status: ReactdropState,
+) -> Result<bool, Error> {
+    let result = sqlx::query!(
+        "UPDATE reactdrops SET status = $3 WHERE channel_id = $1 AND message_id = $2 AND status = 'pending'",
+        reactdrop.channel_id.0 as i64,
+        reactdrop.message_id.0 as i64,
+        status.to_string()
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+fn reactdrop_reference(channel_id: i64, message_id: i64) -> String {
+    format!("reactdrop-{channel_id}-{message_id}")
+}
+
 /// Returns pending reactdrops, or an emtpy Vec if no pending reactdrops present
 pub async fn get_pending_reactdrops(pool: &PgPool) -> Result<Vec<Reactdrop>, Error> {
     let rows = sqlx::query!(
@@ -538,30 +1558,48 @@ WHERE status = 'pending'"
     .fetch_all(pool)
     .await?;
 
-    let vec: Vec<Reactdrop> = rows
-        .into_iter()
-        .map(|row| Reactdrop {
-            status: crate::reactdrop::ReactdropState::Pending,
-            author: (row.author as u64).into(),
-            emoji: row.emojistr,
-            tip_amount: Amount::from_sat(row.amount as u64),
-            channel_id: (row.channel_id as u64).into(),
-            message_id: (row.message_id as u64).into(),
-            finish_time: row.finish_time,
+    rows.into_iter()
+        .map(|row| {
+            Ok(Reactdrop {
+                status: crate::reactdrop::ReactdropState::Pending,
+                author: (row.author as u64).into(),
+                emoji: row.emojistr,
+                currency_id: Address::from_str(&row.currency_id)?,
+                tip_amount: Amount::from_sat(row.amount as u64),
+                channel_id: (row.channel_id as u64).into(),
+                message_id: (row.message_id as u64).into(),
+                finish_time: row.finish_time,
+                escrowed: row.escrowed,
+                guild_id: row.guild_id.map(|guild_id| GuildId(guild_id as u64)),
+                rules: ReactdropRules {
+                    required_role: row.required_role_id.map(|role_id| RoleId(role_id as u64)),
+                    min_account_age_days: row.min_account_age_days,
+                    min_member_age_days: row.min_member_age_days,
+                    max_participants: row.max_participants,
+                    captcha: row.captcha,
+                },
+                mode: DropMode::from_columns(
+                    &row.mode,
+                    row.winners,
+                    row.weighted_role_id,
+                    row.role_weight,
+                ),
+                seed_height: row.seed_height,
+            })
         })
-        .collect();
-
-    Ok(vec)
+        .collect()
 }
 
+/// Moves a pending reactdrop to its final status. Returns false if it was not pending anymore, for example because
+/// it was cancelled in the meantime.
 pub async fn update_reactdrop(
     pool: &PgPool,
     channel_id: i64,
     message_id: i64,
     status: ReactdropState,
-) -> Result<(), Error> {
-    sqlx::query!(
-        "UPDATE reactdrops SET status = $3 WHERE channel_id = $1 AND message_id = $2",
+) -> Result<bool, Error> {
+    let result = sqlx::query!(
+        "UPDATE reactdrops SET status = $3 WHERE channel_id = $1 AND message_id = $2 AND status = 'pending'",
         channel_id,
         message_id,
         status.to_string()
@@ -569,5 +1607,568 @@ pub async fn update_reactdrop(
     .execute(pool)
     .await?;
 
+    Ok(result.rows_affected() == 1)
+}
+
+/// Returns a page of the ledger entries of a user, newest first.
+pub async fn get_history(
+    pool: &PgPool,
+    user_id: &UserId,
+    filter: &HistoryFilter,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<HistoryEntry>, Error> {
+    let kinds = filter
+        .kinds
+        .iter()
+        .map(|kind| kind.to_string())
+        .collect::<Vec<_>>();
+
+    // the counterparties of a tip are the other users in the same transfer.
+    let rows = sqlx::query!(
+        r#"SELECT l.transfer_id, l.currency_id, l.kind, l.amount, l.reference, l.created_at,
+            ARRAY(
+                SELECT o.discord_id FROM ledger o
+                WHERE o.transfer_id = l.transfer_id AND o.discord_id IS NOT NULL AND o.discord_id <> l.discord_id
+                AND l.kind IN ('tip-send', 'tip-receive')
+                ORDER BY o.discord_id
+            ) AS "counterparties!",
+            (SELECT t.memo FROM tips_vrsc t WHERE t.uuid = l.transfer_id AND t.memo IS NOT NULL LIMIT 1) AS memo
+        FROM ledger l
+        WHERE l.discord_id = $1
+        AND (cardinality($2::text[]) = 0 OR l.kind = ANY($2))
+        AND ($3::timestamptz IS NULL OR l.created_at >= $3)
+        AND ($4::timestamptz IS NULL OR l.created_at < $4)
+        ORDER BY l.id DESC
+        LIMIT $5 OFFSET $6"#,
+        user_id.0 as i64,
+        &kinds,
+        filter.from,
+        filter.until,
+        limit,
+        offset
+    )
+    .fetch_all(pool)
+    .await?;
+
+    rows.into_iter()
+        .map(|row| {
+            Ok(HistoryEntry {
+                transfer_id: row.transfer_id,
+                currency_id: Address::from_str(&row.currency_id)?,
+                kind: row.kind,
+                amount: row.amount,
+                reference: row.reference,
+                counterparties: row
+                    .counterparties
+                    .into_iter()
+                    .map(|id| UserId(id as u64))
+                    .collect(),
+                memo: row.memo,
+                created_at: row.created_at,
+            })
+        })
+        .collect()
+}
+
+/// Counts the ledger entries of a user that match the filter.
+pub async fn count_history(
+    pool: &PgPool,
+    user_id: &UserId,
+    filter: &HistoryFilter,
+) -> Result<i64, Error> {
+    let kinds = filter
+        .kinds
+        .iter()
+        .map(|kind| kind.to_string())
+        .collect::<Vec<_>>();
+
+    let row = sqlx::query!(
+        r#"SELECT COUNT(*) AS "count!" FROM ledger
+        WHERE discord_id = $1
+        AND (cardinality($2::text[]) = 0 OR kind = ANY($2))
+        AND ($3::timestamptz IS NULL OR created_at >= $3)
+        AND ($4::timestamptz IS NULL OR created_at < $4)"#,
+        user_id.0 as i64,
+        &kinds,
+        filter.from,
+        filter.until
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(row.count)
+}
+
+/// Sums the ledger per account and kind for a currency.
+pub async fn get_ledger_totals(
+    pool: &PgPool,
+    currency_id: &Address,
+) -> Result<Vec<(String, String, i64)>, Error> {
+    let rows = sqlx::query!(
+        r#"SELECT account, kind, CAST(SUM(amount) AS BIGINT) AS "total!" FROM ledger WHERE currency_id = $1 GROUP BY account, kind"#,
+        &currency_id.to_string()
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| (row.account, row.kind, row.total))
+        .collect())
+}
+
+/// Returns per currency what the bot owes: the balances of users, reserved withdrawals and reactdrops in escrow.
+pub async fn get_owed_totals(pool: &PgPool) -> Result<Vec<(String, i64)>, Error> {
+    let rows = sqlx::query!(
+        r#"SELECT currency_id, CAST(SUM(amount) AS BIGINT) AS "total!" FROM ledger WHERE account IN ('user', 'outbox', 'escrow') GROUP BY currency_id"#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| (row.currency_id, row.total))
+        .collect())
+}
+
+/// Returns the ids of transfers whose entries do not sum up to zero. This should always be empty.
+pub async fn get_unbalanced_transfers(pool: &PgPool) -> Result<Vec<String>, Error> {
+    let rows = sqlx::query!(
+        "SELECT transfer_id FROM ledger GROUP BY transfer_id, currency_id HAVING SUM(amount) <> 0"
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(|row| row.transfer_id).collect())
+}
+
+/// Returns the balances that differ from the sum of the ledger entries of that user and currency, as
+/// (user, currency, balance, ledger sum). This should always be empty.
+pub async fn get_balance_mismatches(
+    pool: &PgPool,
+) -> Result<Vec<(UserId, String, i64, i64)>, Error> {
+    let rows = sqlx::query!(
+        r#"SELECT COALESCE(b.discord_id, l.discord_id) AS "discord_id!",
+        COALESCE(b.currency_id, l.currency_id) AS "currency_id!",
+        COALESCE(b.balance, 0) AS "balance!",
+        COALESCE(l.total, 0) AS "total!"
+        FROM balances b
+        FULL OUTER JOIN (
+            SELECT discord_id, currency_id, CAST(SUM(amount) AS BIGINT) AS total
+            FROM ledger
+            WHERE account = 'user'
+            GROUP BY discord_id, currency_id
+        ) l ON b.discord_id = l.discord_id AND b.currency_id = l.currency_id
+        WHERE COALESCE(b.balance, 0) <> COALESCE(l.total, 0)"#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            (
+                UserId(row.discord_id as u64),
+                row.currency_id,
+                row.balance,
+                row.total,
+            )
+        })
+        .collect())
+}
+
+/// Stores the challenge a user needs to sign to link a VerusID, replacing an earlier challenge of the user.
+pub async fn insert_verusid_challenge(
+    pool: &PgPool,
+    user_id: &UserId,
+    identity: &str,
+    identity_address: &Address,
+    challenge: &str,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "INSERT INTO verusid_challenges (discord_id, identity, identity_address, challenge) VALUES ($1, $2, $3, $4) \
+        ON CONFLICT (discord_id) DO UPDATE SET identity = $2, identity_address = $3, challenge = $4, created_at = NOW()",
+        user_id.0 as i64,
+        identity,
+        identity_address.to_string(),
+        challenge
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Returns the identity, its address, the challenge and the time the challenge was issued.
+pub async fn get_verusid_challenge(
+    pool: &PgPool,
+    user_id: &UserId,
+) -> Result<Option<(String, Address, String, DateTime<Utc>)>, Error> {
+    let row = sqlx::query!(
+        "SELECT identity, identity_address, challenge, created_at FROM verusid_challenges WHERE discord_id = $1",
+        user_id.0 as i64
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    row.map(|row| {
+        Ok((
+            row.identity,
+            Address::from_str(&row.identity_address)?,
+            row.challenge,
+            row.created_at,
+        ))
+    })
+    .transpose()
+}
+
+/// Links a VerusID to a user after the challenge was verified, and removes the challenge.
+///
+/// An identity can only be linked to one user: a link of the same identity to another user is removed.
+pub async fn link_verusid(
+    pool: &PgPool,
+    user_id: &UserId,
+    identity: &str,
+    identity_address: &Address,
+) -> Result<(), Error> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query!(
+        "DELETE FROM verusid_challenges WHERE discord_id = $1",
+        user_id.0 as i64
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        "DELETE FROM verusid_links WHERE identity_address = $1 AND discord_id <> $2",
+        identity_address.to_string(),
+        user_id.0 as i64
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        "INSERT INTO verusid_links (discord_id, identity, identity_address) VALUES ($1, $2, $3) \
+        ON CONFLICT (discord_id) DO UPDATE SET identity = $2, identity_address = $3, created_at = NOW()",
+        user_id.0 as i64,
+        identity,
+        identity_address.to_string()
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Returns the VerusID a user linked and its address, if any.
+pub async fn get_linked_verusid(
+    pool: &PgPool,
+    user_id: &UserId,
+) -> Result<Option<(String, Address)>, Error> {
+    let row = sqlx::query!(
+        "SELECT identity, identity_address FROM verusid_links WHERE discord_id = $1",
+        user_id.0 as i64
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    row.map(|row| Ok((row.identity, Address::from_str(&row.identity_address)?)))
+        .transpose()
+}
+
+/// Removes the VerusID link of a user. Returns false if the user had no linked VerusID.
+pub async fn unlink_verusid(pool: &PgPool, user_id: &UserId) -> Result<bool, Error> {
+    let result = sqlx::query!(
+        "DELETE FROM verusid_links WHERE discord_id = $1",
+        user_id.0 as i64
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+/// Starts watching an identity for a user, or refreshes the state of an existing watch.
+pub async fn insert_identity_watch(
+    pool: &PgPool,
+    user_id: &UserId,
+    identity_address: &Address,
+    identity: &str,
+    state: &str,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "INSERT INTO identity_watches (discord_id, identity_address, identity, state) VALUES ($1, $2, $3, $4) \
+        ON CONFLICT (discord_id, identity_address) DO UPDATE SET identity = $3, state = $4",
+        user_id.0 as i64,
+        identity_address.to_string(),
+        identity,
+        state
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Returns the identities a user watches and their names, ordered by name.
+pub async fn get_identity_watches_for_user(
+    pool: &PgPool,
+    user_id: &UserId,
+) -> Result<Vec<(Address, String)>, Error> {
+    let rows = sqlx::query!(
+        "SELECT identity_address, identity FROM identity_watches WHERE discord_id = $1 ORDER BY identity",
+        user_id.0 as i64
+    )
+    .fetch_all(pool)
+    .await?;
+
+    rows.into_iter()
+        .map(|row| Ok((Address::from_str(&row.identity_address)?, row.identity)))
+        .collect()
+}
+
+/// Returns all watches: the user, the identity address, the identity name and the last state the user saw.
+pub async fn get_identity_watches(
+    pool: &PgPool,
+) -> Result<Vec<(UserId, Address, String, String)>, Error> {
+    let rows = sqlx::query!(
+        "SELECT discord_id, identity_address, identity, state FROM identity_watches ORDER BY identity_address"
+    )
+    .fetch_all(pool)
+    .await?;
+
+    rows.into_iter()
+        .map(|row| {
+            Ok((
+                UserId(row.discord_id as u64),
+                Address::from_str(&row.identity_address)?,
+                row.identity,
+                row.state,
+            ))
+        })
+        .collect()
+}
+
+pub async fn update_identity_watch_state(
+    pool: &PgPool,
+    user_id: &UserId,
+    identity_address: &Address,
+    state: &str,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "UPDATE identity_watches SET state = $3 WHERE discord_id = $1 AND identity_address = $2",
+        user_id.0 as i64,
+        identity_address.to_string(),
+        state
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Stops a watch, by identity name or i-address. Returns false if the user did not watch it.
+pub async fn delete_identity_watch(
+    pool: &PgPool,
+    user_id: &UserId,
+    identity: &str,
+) -> Result<bool, Error> {
+    let result = sqlx::query!(
+        "DELETE FROM identity_watches WHERE discord_id = $1 AND (lower(identity) = lower($2) OR identity_address = $2)",
+        user_id.0 as i64,
+        identity
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Starts watching an address for a user. Watching an address twice is a no-op.
+pub async fn insert_address_watch(
+    pool: &PgPool,
+    user_id: &UserId,
+    address: &Address,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "INSERT INTO address_watches (discord_id, address) VALUES ($1, $2) ON CONFLICT (discord_id, address) DO NOTHING",
+        user_id.0 as i64,
+        address.to_string()
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Returns the addresses a user watches, ordered by address.
+pub async fn get_address_watches_for_user(
+    pool: &PgPool,
+    user_id: &UserId,
+) -> Result<Vec<Address>, Error> {
+    let rows = sqlx::query!(
+        "SELECT address FROM address_watches WHERE discord_id = $1 ORDER BY address",
+        user_id.0 as i64
+    )
+    .fetch_all(pool)
+    .await?;
+
+    rows.into_iter()
+        .map(|row| Ok(Address::from_str(&row.address)?))
+        .collect()
+}
+
+/// Returns the users that watch any of the given addresses, with the address they watch.
+pub async fn get_address_watchers(
+    pool: &PgPool,
+    addresses: &[String],
+) -> Result<Vec<(UserId, Address)>, Error> {
+    let rows = sqlx::query!(
+        "SELECT discord_id, address FROM address_watches WHERE address = ANY($1)",
+        addresses
+    )
+    .fetch_all(pool)
+    .await?;
+
+    rows.into_iter()
+        .map(|row| {
+            Ok((
+                UserId(row.discord_id as u64),
+                Address::from_str(&row.address)?,
+            ))
+        })
+        .collect()
+}
+
+/// Records that a user was notified of a transaction for a watched address.
+/// Returns false if the user was already notified of it.
+pub async fn insert_address_watch_notification(
+    pool: &PgPool,
+    user_id: &UserId,
+    address: &Address,
+    txid: &Txid,
+) -> Result<bool, Error> {
+    let result = sqlx::query!(
+        "INSERT INTO address_watch_notifications (discord_id, address, txid) VALUES ($1, $2, $3) \
+        ON CONFLICT (discord_id, address, txid) DO NOTHING",
+        user_id.0 as i64,
+        address.to_string(),
+        txid.to_string()
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Stops watching an address. Returns false if the user did not watch it.
+pub async fn delete_address_watch(
+    pool: &PgPool,
+    user_id: &UserId,
+    address: &Address,
+) -> Result<bool, Error> {
+    let result = sqlx::query!(
+        "DELETE FROM address_watches WHERE discord_id = $1 AND address = $2",
+        user_id.0 as i64,
+        address.to_string()
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Starts watching the offers for an identity, or refreshes the known offers of an existing watch.
+pub async fn insert_offer_watch(
+    pool: &PgPool,
+    user_id: &UserId,
+    identity_address: &Address,
+    identity: &str,
+    offers: &str,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "INSERT INTO offer_watches (discord_id, identity_address, identity, offers) VALUES ($1, $2, $3, $4) \
+        ON CONFLICT (discord_id, identity_address) DO UPDATE SET identity = $3, offers = $4",
+        user_id.0 as i64,
+        identity_address.to_string(),
+        identity,
+        offers
+    )
+    .execute(pool)
+    .await?;
+
     Ok(())
 }
+
+/// Returns the number of identities a user watches the offers of.
+pub async fn count_offer_watches_for_user(pool: &PgPool, user_id: &UserId) -> Result<i64, Error> {
+    let row = sqlx::query!(
+        "SELECT COUNT(*) AS count FROM offer_watches WHERE discord_id = $1",
+        user_id.0 as i64
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(row.count.unwrap_or(0))
+}
+
+/// Returns all offer watches: the user, the identity address, the identity name and the offers the user knows about.
+pub async fn get_offer_watches(
+    pool: &PgPool,
+) -> Result<Vec<(UserId, Address, String, String)>, Error> {
+    let rows = sqlx::query!(
+        "SELECT discord_id, identity_address, identity, offers FROM offer_watches ORDER BY identity_address"
+    )
+    .fetch_all(pool)
+    .await?;
+
+    rows.into_iter()
+        .map(|row| {
+            Ok((
+                UserId(row.discord_id as u64),
+                Address::from_str(&row.identity_address)?,
+                row.identity,
+                row.offers,
+            ))
+        })
+        .collect()
+}
+
+pub async fn update_offer_watch_offers(
+    pool: &PgPool,
+    user_id: &UserId,
+    identity_address: &Address,
+    offers: &str,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "UPDATE offer_watches SET offers = $3 WHERE discord_id = $1 AND identity_address = $2",
+        user_id.0 as i64,
+        identity_address.to_string(),
+        offers
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Stops an offer watch, by identity name or i-address. Returns false if the user did not watch it.
+pub async fn delete_offer_watch(
+    pool: &PgPool,
+    user_id: &UserId,
+    identity: &str,
+) -> Result<bool, Error> {
+    let result = sqlx::query!(
+        "DELETE FROM offer_watches WHERE discord_id = $1 AND (lower(identity) = lower($2) OR identity_address = $2)",
+        user_id.0 as i64,
+        identity
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
@@ -0,0 +1,242 @@
+use std::{collections::HashMap, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+use vrsc::Address;
+use vrsc_rpc::client::{Client, RpcApi};
+
+/// The identity flag that is set while an identity is locked.
+pub const IDENTITY_FLAG_LOCKED: u32 = 2;
+
+/// The result of `getidentity`, with the fields the bot uses.
+#[derive(Deserialize, Debug)]
+pub struct GetIdentityResult {
+    pub fullyqualifiedname: String,
+    pub identity: IdentityDefinition,
+    pub status: String,
+    pub blockheight: Option<u64>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct IdentityDefinition {
+    pub flags: u32,
+    pub primaryaddresses: Vec<Address>,
+    pub minimumsignatures: u32,
+    pub identityaddress: Address,
+    pub parent: Address,
+    pub revocationauthority: Address,
+    pub recoveryauthority: Address,
+    pub timelock: u64,
+    #[serde(default)]
+    pub contentmap: HashMap<String, String>,
+}
+
+/// Looks up an identity by name (`name@`) or i-address. Returns None if it does not exist.
+pub fn get_identity(client: &Client, name: &str) -> Option<GetIdentityResult> {
+    match client.call::<GetIdentityResult>("getidentity", &[serde_json::json!(name)]) {
+        Ok(identity) => Some(identity),
+        Err(e) => {
+            debug!("getidentity {name} failed: {e:?}");
+
+            None
+        }
+    }
+}
+
+/// Normalizes an identity entered by a user: an i-address is used as is, a name always ends with an `@`.
+pub fn identity_name(identity: &str) -> String {
+    let identity = identity.trim();
+
+    if identity.ends_with('@') || Address::from_str(identity).is_ok() {
+        identity.to_owned()
+    } else {
+        format!("{identity}@")
+    }
+}
+
+/// A locked identity can only be spent from after an unlock delay (in blocks). After an unlock was requested,
+/// the timelock is the block at which the identity is unlocked.
+pub fn identity_state(status: &str, flags: u32, timelock: u64) -> String {
+    if status != "active" {
+        return status.to_owned();
+    }
+
+    if flags & IDENTITY_FLAG_LOCKED != 0 {
+        format!("locked (unlock delay of {timelock} blocks)")
+    } else if timelock > 0 {
+        format!("unlocking at block {timelock}")
+    } else {
+        String::from("active")
+    }
+}
+
+/// The parts of an identity that watchers get notified about. It is stored as JSON with every watch,
+/// and compared with the identity as it is after every block.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct IdentitySnapshot {
+    pub status: String,
+    pub flags: u32,
+    pub timelock: u64,
+    pub primary_addresses: Vec<String>,
+    pub minimum_signatures: u32,
+    pub revocation_authority: String,
+    pub recovery_authority: String,
+}
+
+impl From<&GetIdentityResult> for IdentitySnapshot {
+    fn from(identity: &GetIdentityResult) -> Self {
+        let definition = &identity.identity;
+
+        IdentitySnapshot {
+            status: identity.status.clone(),
+            flags: definition.flags,
+            timelock: definition.timelock,
+            primary_addresses: definition
+                .primaryaddresses
+                .iter()
+                .map(|address| address.to_string())
+                .collect(),
+            minimum_signatures: definition.minimumsignatures,
+            revocation_authority: definition.revocationauthority.to_string(),
+            recovery_authority: definition.recoveryauthority.to_string(),
+        }
+    }
+}
+
+impl IdentitySnapshot {
+    /// Describes what changed since `old`, one line per change. Empty if nothing a watcher cares about changed.
+    pub fn changes_since(&self, old: &IdentitySnapshot) -> Vec<String> {
+        let mut changes = vec![];
+
+        match (old.status.as_str(), self.status.as_str()) {
+            (old_status, new_status) if old_status == new_status => {}
+            (_, "revoked") => changes.push(String::from("was revoked")),
+            ("revoked", "active") => changes.push(String::from("was recovered")),
+            (old_status, new_status) => {
+                changes.push(format!("status changed from {old_status} to {new_status}"))
+            }
+        }
+
+        let state = identity_state("active", self.flags, self.timelock);
+        if (old.flags & IDENTITY_FLAG_LOCKED, old.timelock)
+            != (self.flags & IDENTITY_FLAG_LOCKED, self.timelock)
+        {
+            changes.push(match state.as_str() {
+                "active" => String::from("is unlocked"),
+                _ => format!("is {state}"),
+            });
+        }
+
+        if old.primary_addresses != self.primary_addresses {
+            changes.push(format!(
+                "primary addresses changed to {}",
+                self.primary_addresses
+                    .iter()
+                    .map(|address| format!("`{address}`"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+
+        if old.minimum_signatures != self.minimum_signatures {
+            changes.push(format!(
+                "needs {} signature(s) now",
+                self.minimum_signatures
+            ));
+        }
+
+        if old.revocation_authority != self.revocation_authority {
+            changes.push(format!(
+                "revocation authority changed to `{}`",
+                self.revocation_authority
+            ));
+        }
+
+        if old.recovery_authority != self.recovery_authority {
+            changes.push(format!(
+                "recovery authority changed to `{}`",
+                self.recovery_authority
+            ));
+        }
+
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identity_names_end_with_at() {
+        assert_eq!(identity_name("verus"), "verus@");
+        assert_eq!(identity_name(" verus@ "), "verus@");
+        assert_eq!(identity_name("sub.parent@"), "sub.parent@");
+    }
+
+    fn snapshot() -> IdentitySnapshot {
+        IdentitySnapshot {
+            status: String::from("active"),
+            flags: 0,
+            timelock: 0,
+            primary_addresses: vec![String::from("RAddress")],
+            minimum_signatures: 1,
+            revocation_authority: String::from("iAddress"),
+            recovery_authority: String::from("iAddress"),
+        }
+    }
+
+    #[test]
+    fn identity_states() {
+        assert_eq!(identity_state("revoked", 0, 0), "revoked");
+        assert_eq!(identity_state("active", 0, 0), "active");
+        assert_eq!(
+            identity_state("active", IDENTITY_FLAG_LOCKED, 1440),
+            "locked (unlock delay of 1440 blocks)"
+        );
+        assert_eq!(
+            identity_state("active", 0, 3_000_000),
+            "unlocking at block 3000000"
+        );
+    }
+
+    #[test]
+    fn unchanged_identity_has_no_changes() {
+        assert!(snapshot().changes_since(&snapshot()).is_empty());
+    }
+
+    #[test]
+    fn describes_revoke_recover_and_lock() {
+        let old = snapshot();
+        let revoked = IdentitySnapshot {
+            status: String::from("revoked"),
+            ..snapshot()
+        };
+        let locked = IdentitySnapshot {
+            flags: IDENTITY_FLAG_LOCKED,
+            timelock: 1440,
+            ..snapshot()
+        };
+
+        assert_eq!(revoked.changes_since(&old), vec!["was revoked"]);
+        assert_eq!(old.changes_since(&revoked), vec!["was recovered"]);
+        assert_eq!(
+            locked.changes_since(&old),
+            vec!["is locked (unlock delay of 1440 blocks)"]
+        );
+        assert_eq!(old.changes_since(&locked), vec!["is unlocked"]);
+    }
+
+    #[test]
+    fn describes_new_primary_addresses() {
+        let new = IdentitySnapshot {
+            primary_addresses: vec![String::from("RNewAddress")],
+            ..snapshot()
+        };
+
+        assert_eq!(
+            new.changes_since(&snapshot()),
+            vec!["primary addresses changed to `RNewAddress`"]
+        );
+    }
+}
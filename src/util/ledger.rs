@@ -0,0 +1,236 @@
+use std::{collections::HashMap, fmt::Display};
+
+use poise::serenity_prelude::UserId;
+use sqlx::types::chrono::{DateTime, Utc};
+use vrsc::Address;
+
+/// An account in the ledger. Every user has an account per currency, the other accounts belong to the bot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Account {
+    /// The balance of a Discord user.
+    User(UserId),
+    /// The counterparty of deposits (negative) and withdrawals (positive): funds that moved in or out of the bot wallet on-chain.
+    Chain,
+    /// The withdrawal fees the bot collected.
+    Fees,
+    /// Amounts and fees of withdrawals that are reserved, but not yet confirmed on-chain.
+    Outbox,
+    /// Amounts of running reactdrops, until they are paid out or refunded.
+    Escrow,
+    /// The counterparty of manual corrections and of the balances that existed before the ledger was introduced.
+    Adjustment,
+}
+
+impl Account {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::User(_) => "user",
+            Self::Chain => "chain",
+            Self::Fees => "fees",
+            Self::Outbox => "outbox",
+            Self::Escrow => "escrow",
+            Self::Adjustment => "adjustment",
+        }
+    }
+
+    pub fn discord_id(&self) -> Option<i64> {
+        match self {
+            Self::User(user_id) => Some(user_id.0 as i64),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LedgerKind {
+    Deposit,
+    Withdrawal,
+    Fee,
+    TipSend,
+    TipReceive,
+    Adjustment,
+    Refund,
+    ReactdropEscrow,
+    Send,
+}
+
+impl Display for LedgerKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Deposit => write!(f, "deposit"),
+            Self::Withdrawal => write!(f, "withdrawal"),
+            Self::Fee => write!(f, "fee"),
+            Self::TipSend => write!(f, "tip-send"),
+            Self::TipReceive => write!(f, "tip-receive"),
+            Self::Adjustment => write!(f, "adjustment"),
+            Self::Refund => write!(f, "refund"),
+            Self::ReactdropEscrow => write!(f, "reactdrop-escrow"),
+            Self::Send => write!(f, "send"),
+        }
+    }
+}
+
+/// One line in the ledger. A positive amount (in sats) credits the account, a negative amount debits it.
+///
+/// Entries are always written as part of a transfer, and the entries of a transfer sum up to zero for every currency.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LedgerEntry {
+    pub account: Account,
+    pub currency_id: Address,
+    pub kind: LedgerKind,
+    pub amount: i64,
+}
+
+impl LedgerEntry {
+    pub fn new(account: Account, currency_id: &Address, kind: LedgerKind, amount: i64) -> Self {
+        LedgerEntry {
+            account,
+            currency_id: currency_id.clone(),
+            kind,
+            amount,
+        }
+    }
+}
+
+/// A ledger entry of a user, as shown in their history.
+#[derive(Debug, Clone)]
+pub struct HistoryEntry {
+    pub transfer_id: String,
+    pub currency_id: Address,
+    /// The `LedgerKind` of the entry, or `opening-balance`.
+    pub kind: String,
+    pub amount: i64,
+    pub reference: Option<String>,
+    /// The other users in a tip.
+    pub counterparties: Vec<UserId>,
+    /// The memo the tipper added to a tip.
+    pub memo: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Which ledger entries of a user to show. An empty list of kinds shows all kinds.
+#[derive(Debug, Clone, Default)]
+pub struct HistoryFilter {
+    pub kinds: Vec<LedgerKind>,
+    pub from: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+/// A transfer would make the balance of a user negative. Nothing of the transfer is applied.
+#[derive(Debug)]
+pub struct InsufficientBalance {
+    pub user_id: UserId,
+    pub currency_id: Address,
+}
+
+impl Display for InsufficientBalance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} has insufficient {} balance",
+            self.user_id, self.currency_id
+        )
+    }
+}
+
+impl std::error::Error for InsufficientBalance {}
+
+/// Returns true if the entries sum up to zero for every currency.
+pub fn is_balanced(entries: &[LedgerEntry]) -> bool {
+    let mut sums: HashMap<&Address, i128> = HashMap::new();
+
+    for entry in entries {
+        *sums.entry(&entry.currency_id).or_default() += entry.amount as i128;
+    }
+
+    sums.values().all(|sum| *sum == 0)
+}
+
+/// Sums the entries per user and currency, which gives the changes that need to be applied to the balances.
+/// Changes that net out to zero are left out.
+pub fn user_balance_changes(entries: &[LedgerEntry]) -> Vec<(UserId, Address, i64)> {
+    let mut changes: Vec<(UserId, Address, i64)> = vec![];
+
+    for entry in entries {
+        if let Account::User(user_id) = entry.account {
+            match changes
+                .iter_mut()
+                .find(|(id, currency_id, _)| *id == user_id && currency_id == &entry.currency_id)
+            {
+                Some((_, _, amount)) => *amount += entry.amount,
+                None => changes.push((user_id, entry.currency_id.clone(), entry.amount)),
+            }
+        }
+    }
+
+    changes.retain(|(_, _, amount)| *amount != 0);
+
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn vrsc() -> Address {
+        Address::from_str("i5w5MuNik5NtLcYmNzcvaoixooEebB6MGV").unwrap()
+    }
+
+    #[test]
+    fn tip_to_self_nets_out() {
+        let entries = vec![
+            LedgerEntry::new(Account::User(UserId(1)), &vrsc(), LedgerKind::TipSend, -200),
+            LedgerEntry::new(
+                Account::User(UserId(1)),
+                &vrsc(),
+                LedgerKind::TipReceive,
+                100,
+            ),
+            LedgerEntry::new(
+                Account::User(UserId(2)),
+                &vrsc(),
+                LedgerKind::TipReceive,
+                100,
+            ),
+        ];
+
+        assert!(is_balanced(&entries));
+        assert_eq!(
+            user_balance_changes(&entries),
+            vec![(UserId(1), vrsc(), -100), (UserId(2), vrsc(), 100)]
+        );
+    }
+
+    #[test]
+    fn withdrawal_with_fee() {
+        let entries = vec![
+            LedgerEntry::new(
+                Account::User(UserId(1)),
+                &vrsc(),
+                LedgerKind::Withdrawal,
+                -1000,
+            ),
+            LedgerEntry::new(Account::Chain, &vrsc(), LedgerKind::Withdrawal, 1000),
+            LedgerEntry::new(Account::User(UserId(1)), &vrsc(), LedgerKind::Fee, -10),
+            LedgerEntry::new(Account::Fees, &vrsc(), LedgerKind::Fee, 10),
+        ];
+
+        assert!(is_balanced(&entries));
+        assert_eq!(
+            user_balance_changes(&entries),
+            vec![(UserId(1), vrsc(), -1010)]
+        );
+    }
+
+    #[test]
+    fn unbalanced_transfer() {
+        let entries = vec![
+            LedgerEntry::new(Account::User(UserId(1)), &vrsc(), LedgerKind::Deposit, 1000),
+            LedgerEntry::new(Account::Chain, &vrsc(), LedgerKind::Deposit, -999),
+        ];
+
+        assert!(!is_balanced(&entries));
+    }
+}
@@ -1 +1,7 @@
+pub mod activity;
+pub mod amount;
+pub mod currency;
 pub mod database;
+pub mod identity;
+pub mod ledger;
+pub mod offers;
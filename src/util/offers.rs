@@ -0,0 +1,112 @@
+use std::{collections::HashMap, str::FromStr};
+
+use serde::Deserialize;
+use serde_json::Value;
+use vrsc::{Address, Amount};
+use vrsc_rpc::client::{Client, RpcApi};
+
+use crate::{
+    util::currency::{cached_currency, CurrencyNames},
+    Error,
+};
+
+// `getoffers` groups the offers by what they are for, e.g. `identity_<id>_for_currencies`.
+// Every entry has the offer itself next to some fields that depend on the group.
+#[derive(Deserialize, Debug)]
+struct OfferEntry {
+    offer: Offer,
+}
+
+/// An open offer on the marketplace. `offer` is what the maker gives, `accept` is what the maker asks for in return.
+///
+/// Both are either a map of currency ids to amounts, or an identity definition.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Offer {
+    pub offer: Value,
+    pub accept: Value,
+    pub blockexpiry: u64,
+    pub txid: String,
+}
+
+/// Returns the open offers for a currency or an identity, ordered by the block they expire at.
+pub fn get_offers(
+    client: &Client,
+    currency_or_identity: &str,
+    is_currency: bool,
+) -> Result<Vec<Offer>, Error> {
+    let groups: HashMap<String, Vec<OfferEntry>> = client.call(
+        "getoffers",
+        &[
+            serde_json::json!(currency_or_identity),
+            serde_json::json!(is_currency),
+        ],
+    )?;
+
+    let mut offers = groups
+        .into_values()
+        .flatten()
+        .map(|entry| entry.offer)
+        .collect::<Vec<_>>();
+    // an offer can be in more than one group.
+    offers.sort_by(|a, b| a.txid.cmp(&b.txid));
+    offers.dedup_by(|a, b| a.txid == b.txid);
+    offers.sort_by_key(|offer| offer.blockexpiry);
+
+    Ok(offers)
+}
+
+/// Describes one side of an offer, e.g. `100.00000000 VRSC` or `VerusID name@`.
+pub fn offer_side(side: &Value, currency_names: &CurrencyNames) -> String {
+    let Some(side) = side.as_object() else {
+        return side.to_string();
+    };
+
+    if let Some(name) = side.get("name").and_then(Value::as_str) {
+        return format!("VerusID `{name}@`");
+    }
+
+    let currencies = side
+        .iter()
+        .filter_map(|(id, amount)| {
+            let id = Address::from_str(id).ok()?;
+            let amount = Amount::from_vrsc(amount.as_f64()?).ok()?;
+
+            Some(cached_currency(currency_names, &id).display(&amount))
+        })
+        .collect::<Vec<_>>();
+
+    if currencies.is_empty() {
+        Value::Object(side.clone()).to_string()
+    } else {
+        currencies.join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, RwLock};
+
+    use super::*;
+
+    #[test]
+    fn describes_currencies_and_identities() {
+        let vrsc = Address::from_str("i5w5MuNik5NtLcYmNzcvaoixooEebB6MGV").unwrap();
+        let names: CurrencyNames =
+            Arc::new(RwLock::new(HashMap::from([(vrsc, String::from("VRSC"))])));
+
+        assert_eq!(
+            offer_side(
+                &serde_json::json!({ "i5w5MuNik5NtLcYmNzcvaoixooEebB6MGV": 12.5 }),
+                &names
+            ),
+            "12.50000000 VRSC"
+        );
+        assert_eq!(
+            offer_side(
+                &serde_json::json!({ "name": "alice", "identityid": "iJhCezBExJHvtyH3fGhNnt2NhU4Ztkf2yq" }),
+                &names
+            ),
+            "VerusID `alice@`"
+        );
+    }
+}
@@ -1,23 +1,28 @@
 use color_eyre::Report;
 use futures::StreamExt;
 use poise::serenity_prelude::{Http, UserId};
+use serde::Deserialize;
 use sqlx::PgPool;
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use std::str::FromStr;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use tracing::{debug, error, instrument, trace, warn};
 use uuid::Uuid;
-use vrsc::Amount;
+use vrsc::{Address, Amount, Denomination};
 use vrsc_rpc::bitcoin::Txid;
-use vrsc_rpc::json::GetRawTransactionResultVerbose;
 use vrsc_rpc::{
     client::{Client, RpcApi},
     Auth,
 };
 
 use crate::config::Config;
-use crate::util::database::*;
+use crate::util::{
+    currency::{lookup_currency, Currency, CurrencyNames},
+    database::*,
+    identity::{get_identity, IdentitySnapshot},
+    offers::{get_offers, offer_side, Offer},
+};
 use crate::Error;
 
 /// Listens for wallet transactions and processes them.
@@ -36,6 +41,8 @@ pub struct TransactionProcessor {
     http: Arc<Http>,
     pool: PgPool,
     config: Config,
+    native_currency: Currency,
+    currency_names: CurrencyNames,
     pub maintenance: Arc<RwLock<bool>>,
     pub deposits_enabled: Arc<RwLock<bool>>,
     queue_small_txns: Arc<RwLock<VecDeque<(Txid, Amount)>>>,
@@ -47,6 +54,8 @@ impl TransactionProcessor {
         http: Arc<Http>,
         pool: PgPool,
         config: Config,
+        native_currency: Currency,
+        currency_names: CurrencyNames,
         maintenance: Arc<RwLock<bool>>,
         deposits_enabled: Arc<RwLock<bool>>,
     ) -> Self {
@@ -54,6 +63,8 @@ impl TransactionProcessor {
             http,
             pool,
             config,
+            native_currency,
+            currency_names,
             maintenance,
             deposits_enabled,
             queue_small_txns: Arc::new(RwLock::new(VecDeque::new())),
@@ -81,31 +92,13 @@ impl TransactionProcessor {
                     trace!("new tx: {tx_hash_str}");
 
                     let txid = Txid::from_str(&tx_hash_str)?;
-                    let raw_tx = verus_client.get_raw_transaction_verbose(&txid)?;
-
-                    for vout in raw_tx.vout.iter() {
-                        if let Some(addresses) = &vout.script_pubkey.addresses {
-                            for address in addresses {
-                                if let Some(user_id) =
-                                    get_user_from_address(&self.pool, address).await?
-                                {
-                                    trace!(?user_id, "there is a user for this address");
-
-                                    if vout
-                                        .value
-                                        .gt(&self.config.application.min_deposit_threshold)
-                                    {
-                                        trace!("{txid} put in long queue");
-                                        let mut long_write = self.queue_large_txns.write().await;
-                                        long_write.push_back((txid.clone(), vout.value))
-                                    } else {
-                                        trace!("{txid} put in short queue");
-                                        let mut write = self.queue_small_txns.write().await;
-                                        write.push_back((txid.clone(), vout.value))
-                                    }
-                                }
-                            }
-                        }
+
+                    if let Err(e) = self.queue_deposits(verus_client, txid).await {
+                        error!("could not check {txid} for deposits: {e:?}");
+                    }
+
+                    if let Err(e) = self.notify_address_watchers(verus_client, txid).await {
+                        error!("could not check {txid} for watched addresses: {e:?}");
                     }
                 } else {
                     error!(?msg, "not a valid message");
@@ -137,6 +130,14 @@ impl TransactionProcessor {
 
                     self.process_short_queue().await?;
                     self.process_long_queue().await?;
+
+                    if let Err(e) = self.check_identity_watches().await {
+                        error!("could not check watched identities: {e:?}");
+                    }
+
+                    if let Err(e) = self.check_offer_watches().await {
+                        error!("could not check watched offers: {e:?}");
+                    }
                 } else {
                     error!("not a valid message!");
                 }
@@ -146,7 +147,25 @@ impl TransactionProcessor {
         }
     }
 
-    pub async fn check_tx(&self, txid: Txid) -> Result<(), Error> {
+    // A watcher that can't be DM'ed should not keep the other watchers from their DM, so the callers log the error.
+    async fn dm(&self, user_id: &UserId, content: String) -> Result<(), Error> {
+        user_id
+            .create_dm_channel(&self.http)
+            .await?
+            .say(&self.http, content)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Compares every watched identity with the state its watchers last saw, and DMs the watchers what changed.
+    /// Every identity is looked up once per block, no matter how many users watch it.
+    async fn check_identity_watches(&self) -> Result<(), Error> {
+        let watches = get_identity_watches(&self.pool).await?;
+        if watches.is_empty() {
+            return Ok(());
+        }
+
         let client = Client::vrsc(
             self.config.application.testnet,
             Auth::UserPass(
@@ -156,38 +175,242 @@ impl TransactionProcessor {
             ),
         )?;
 
-        trace!("getting raw_transaction {txid}");
-        let raw_tx = client.get_raw_transaction_verbose(&txid)?;
-
-        for vout in raw_tx.vout.iter() {
-            if let Some(addresses) = &vout.script_pubkey.addresses {
-                for address in addresses {
-                    if let Some(user_id) = get_user_from_address(&self.pool, address).await? {
-                        trace!("there is a user for this address: {user_id}",);
-                        let mut write = self.queue_small_txns.write().await;
-                        let mut long_write = self.queue_large_txns.write().await;
-
-                        // if the value of the incoming transaction is greater than
-                        if vout
-                            .value
-                            .gt(&self.config.application.min_deposit_threshold)
-                        {
-                            trace!("{txid} put in long queue");
-                            long_write.push_back((txid.clone(), vout.value))
-                        } else {
-                            trace!("{txid} put in short queue");
-                            write.push_back((txid.clone(), vout.value))
-                        }
-                    }
+        let mut snapshots: HashMap<Address, Option<IdentitySnapshot>> = HashMap::new();
+
+        for (user_id, identity_address, identity, state) in watches {
+            let Some(snapshot) = snapshots
+                .entry(identity_address.clone())
+                .or_insert_with(|| {
+                    get_identity(&client, &identity_address.to_string())
+                        .map(|result| IdentitySnapshot::from(&result))
+                })
+                .clone()
+            else {
+                warn!("watched identity {identity_address} could not be found");
+                continue;
+            };
+
+            let old_snapshot = match serde_json::from_str::<IdentitySnapshot>(&state) {
+                Ok(old_snapshot) => old_snapshot,
+                Err(e) => {
+                    error!("the watch of {user_id} on {identity} has an invalid state: {e:?}");
+                    continue;
                 }
-            } else {
-                trace!("no addresses found in scriptpubkey");
+            };
+            let changes = snapshot.changes_since(&old_snapshot);
+            if changes.is_empty() {
+                continue;
+            }
+            debug!("{identity} changed for {user_id}: {changes:?}");
+
+            // the new state is stored first: a user that can't be DM'ed is not notified again for the same change.
+            update_identity_watch_state(
+                &self.pool,
+                &user_id,
+                &identity_address,
+                &serde_json::to_string(&snapshot)?,
+            )
+            .await?;
+
+            let content = format!(
+                "The VerusID you watch, `{identity}`, changed:\n{}",
+                changes
+                    .iter()
+                    .map(|change| format!("- {change}"))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            );
+            if let Err(e) = self.dm(&user_id, content).await {
+                warn!("could not notify {user_id} of a change of {identity}: {e:?}");
             }
         }
 
         Ok(())
     }
 
+    /// Compares the open offers of every watched identity with the offers its watchers know about, and DMs the
+    /// watchers the new ones. The offers of every identity are looked up once per block.
+    async fn check_offer_watches(&self) -> Result<(), Error> {
+        let watches = get_offer_watches(&self.pool).await?;
+        if watches.is_empty() {
+            return Ok(());
+        }
+
+        let client = Client::vrsc(
+            self.config.application.testnet,
+            Auth::UserPass(
+                format!("127.0.0.1:{}", self.config.application.rpc_port),
+                self.config.application.rpc_user.clone(),
+                self.config.application.rpc_password.clone(),
+            ),
+        )?;
+
+        let mut open_offers: HashMap<Address, Vec<Offer>> = HashMap::new();
+
+        for (user_id, identity_address, identity, known) in watches {
+            if !open_offers.contains_key(&identity_address) {
+                let offers = get_offers(&client, &identity_address.to_string(), false)?;
+                open_offers.insert(identity_address.clone(), offers);
+            }
+            let offers = &open_offers[&identity_address];
+
+            let known = match serde_json::from_str::<Vec<String>>(&known) {
+                Ok(known) => known,
+                Err(e) => {
+                    error!("the offer watch of {user_id} on {identity} has invalid offers: {e:?}");
+                    continue;
+                }
+            };
+            let new_offers = offers
+                .iter()
+                .filter(|offer| !known.contains(&offer.txid))
+                .collect::<Vec<_>>();
+            // offers that were taken or expired are forgotten as well, so the known offers don't grow forever.
+            if new_offers.is_empty() && known.len() == offers.len() {
+                continue;
+            }
+            debug!(
+                "{} new offers for {identity} for {user_id}",
+                new_offers.len()
+            );
+
+            // the open offers are stored first: a user that can't be DM'ed is not notified again for the same offer.
+            update_offer_watch_offers(
+                &self.pool,
+                &user_id,
+                &identity_address,
+                &serde_json::to_string(
+                    &offers.iter().map(|offer| &offer.txid).collect::<Vec<_>>(),
+                )?,
+            )
+            .await?;
+
+            if new_offers.is_empty() {
+                continue;
+            }
+
+            let content = format!(
+                "New offers for `{identity}`:\n{}",
+                new_offers
+                    .iter()
+                    .map(|offer| format!(
+                        "- offered: {}, asked: {}, expires at block {} (`{}`)",
+                        offer_side(&offer.offer, &self.currency_names),
+                        offer_side(&offer.accept, &self.currency_names),
+                        offer.blockexpiry,
+                        offer.txid
+                    ))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            );
+            if let Err(e) = self.dm(&user_id, content).await {
+                warn!("could not notify {user_id} of new offers for {identity}: {e:?}");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// DMs the users that watch an address that receives or spends funds in this transaction.
+    /// Only addresses the daemon indexes have the address of their inputs in the transaction, so outgoing transactions
+    /// are only noticed for those.
+    async fn notify_address_watchers(&self, client: &Client, txid: Txid) -> Result<(), Error> {
+        let transaction = get_transaction(client, &txid)?;
+        let addresses = transaction.addresses();
+        if addresses.is_empty() {
+            return Ok(());
+        }
+
+        for (user_id, address) in get_address_watchers(&self.pool, &addresses).await? {
+            // the daemon announces a transaction twice: once in the mempool and once in a block.
+            if !insert_address_watch_notification(&self.pool, &user_id, &address, &txid).await? {
+                trace!("{user_id} was already notified of {txid} for {address}");
+                continue;
+            }
+
+            let (received, sent) = transaction.native_movements(&address.to_string());
+            let mut lines = vec![format!(
+                "The address you watch, `{address}`, is in a new transaction:"
+            )];
+            if received > 0 {
+                lines.push(format!(
+                    "- received {}",
+                    self.native_currency.display(&Amount::from_sat(received))
+                ));
+            }
+            if sent > 0 {
+                lines.push(format!(
+                    "- spent {}",
+                    self.native_currency.display(&Amount::from_sat(sent))
+                ));
+            }
+            lines.push(format!("Transaction: `{txid}`"));
+
+            if let Err(e) = self.dm(&user_id, lines.join("\n")).await {
+                warn!("could not notify {user_id} of {txid} for {address}: {e:?}");
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn check_tx(&self, txid: Txid) -> Result<(), Error> {
+        let client = Client::vrsc(
+            self.config.application.testnet,
+            Auth::UserPass(
+                format!("127.0.0.1:{}", self.config.application.rpc_port),
+                self.config.application.rpc_user.clone(),
+                self.config.application.rpc_password.clone(),
+            ),
+        )?;
+
+        trace!("getting raw_transaction {txid}");
+        self.queue_deposits(&client, txid).await?;
+
+        Ok(())
+    }
+
+    // Puts a transaction in one of the queues if it contains deposits.
+    // Deposits of other currencies than the native currency always go in the long queue, as the deposit threshold is
+    // denominated in the native currency.
+    async fn queue_deposits(&self, client: &Client, txid: Txid) -> Result<(), Error> {
+        let deposits = get_deposits(
+            client,
+            &self.pool,
+            &self.native_currency,
+            &self.currency_names,
+            &txid,
+        )
+        .await?;
+
+        if deposits.is_empty() {
+            trace!("no deposits in {txid}");
+
+            return Ok(());
+        }
+
+        let native_amount = deposits
+            .iter()
+            .filter(|deposit| deposit.currency == self.native_currency)
+            .fold(Amount::ZERO, |sum, deposit| sum + deposit.amount);
+
+        if native_amount.gt(&self.config.application.min_deposit_threshold)
+            || deposits
+                .iter()
+                .any(|deposit| deposit.currency != self.native_currency)
+        {
+            trace!("{txid} put in long queue");
+            let mut long_write = self.queue_large_txns.write().await;
+            long_write.push_back((txid, native_amount))
+        } else {
+            trace!("{txid} put in short queue");
+            let mut write = self.queue_small_txns.write().await;
+            write.push_back((txid, native_amount))
+        }
+
+        Ok(())
+    }
+
     #[instrument(skip(self))]
     pub async fn process_short_queue(
         // queue: Arc<RwLock<VecDeque<(Txid, Amount)>>>,
@@ -233,7 +456,16 @@ impl TransactionProcessor {
                         break;
                     } else {
                         trace!("tx has at least {} confs: {}", min_confs, front.0);
-                        if let Err(e) = process_txid(Arc::clone(&http), &pool, &raw_tx).await {
+                        if let Err(e) = process_txid(
+                            Arc::clone(&http),
+                            &pool,
+                            &client,
+                            &self.native_currency,
+                            &self.currency_names,
+                            &front.0,
+                        )
+                        .await
+                        {
                             error!(
                                 "something went wrong while handling a new wallet tx: {:?}\n{:?}",
                                 e, &front
@@ -294,7 +526,16 @@ impl TransactionProcessor {
                         break;
                     } else {
                         trace!("tx has at least {} confs: {}", min_confs, front.0);
-                        if let Err(e) = process_txid(Arc::clone(&http), &pool, &raw_tx).await {
+                        if let Err(e) = process_txid(
+                            Arc::clone(&http),
+                            &pool,
+                            &client,
+                            &self.native_currency,
+                            &self.currency_names,
+                            &front.0,
+                        )
+                        .await
+                        {
                             error!(
                                 "something went wrong while handling a new wallet tx: {:?}\n{:?}",
                                 e, &front
@@ -318,39 +559,216 @@ impl TransactionProcessor {
     }
 }
 
+// The parts of a verbose `getrawtransaction` that are needed to find deposits and watched addresses.
+// `GetRawTransactionResultVerbose` from vrsc-rpc does not have the currency values of outputs that hold other currencies
+// than the native currency, so the transaction is deserialized into these structs instead.
+#[derive(Debug, Deserialize)]
+struct DepositTransaction {
+    // inputs only have an address and a value when the daemon indexes addresses.
+    #[serde(default)]
+    vin: Vec<DepositVin>,
+    vout: Vec<DepositVout>,
+}
+
+impl DepositTransaction {
+    // All addresses that are spent from or paid to in this transaction.
+    fn addresses(&self) -> Vec<String> {
+        let mut addresses = self
+            .vin
+            .iter()
+            .filter_map(|vin| vin.address.clone())
+            .chain(
+                self.vout
+                    .iter()
+                    .flat_map(|vout| vout.script_pubkey.addresses.iter().flatten().cloned()),
+            )
+            .collect::<Vec<_>>();
+        addresses.sort();
+        addresses.dedup();
+
+        addresses
+    }
+
+    // The amount of the native currency an address receives and spends in this transaction, in sats.
+    fn native_movements(&self, address: &str) -> (u64, u64) {
+        let received = self
+            .vout
+            .iter()
+            .filter(|vout| {
+                vout.script_pubkey
+                    .addresses
+                    .iter()
+                    .flatten()
+                    .any(|a| a == address)
+            })
+            .map(|vout| vout.value_sat)
+            .sum();
+        let sent = self
+            .vin
+            .iter()
+            .filter(|vin| vin.address.as_deref() == Some(address))
+            .filter_map(|vin| vin.value_sat)
+            .sum();
+
+        (received, sent)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct DepositVin {
+    address: Option<String>,
+    #[serde(rename = "valueSat")]
+    value_sat: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DepositVout {
+    n: u32,
+    #[serde(rename = "valueSat")]
+    value_sat: u64,
+    #[serde(rename = "scriptPubKey")]
+    script_pubkey: DepositScriptPubKey,
+}
+
+#[derive(Debug, Deserialize)]
+struct DepositScriptPubKey {
+    addresses: Option<Vec<String>>,
+    // currency name -> amount, as a decimal number with at most 8 decimals.
+    reserve_balance: Option<HashMap<String, serde_json::Number>>,
+}
+
+#[derive(Debug)]
+struct Deposit {
+    user_id: UserId,
+    vout: u32,
+    currency: Currency,
+    amount: Amount,
+}
+
+fn get_transaction(client: &Client, txid: &Txid) -> Result<DepositTransaction, Error> {
+    Ok(client.call(
+        "getrawtransaction",
+        &[serde_json::json!(txid.to_string()), serde_json::json!(1)],
+    )?)
+}
+
+// Finds all the outputs in a transaction that pay to an address that belongs to a discord user, for every currency
+// in that output.
+async fn get_deposits(
+    client: &Client,
+    pool: &PgPool,
+    native_currency: &Currency,
+    currency_names: &CurrencyNames,
+    txid: &Txid,
+) -> Result<Vec<Deposit>, Error> {
+    let transaction = get_transaction(client, txid)?;
+
+    let mut deposits = vec![];
+
+    for vout in transaction.vout {
+        let Some(addresses) = &vout.script_pubkey.addresses else {
+            debug!("no addresses found in scriptpubkey");
+            continue;
+        };
+
+        for address in addresses {
+            let Ok(address) = Address::from_str(address) else {
+                continue;
+            };
+
+            let Some(user_id) = get_user_from_address(pool, &address).await? else {
+                continue;
+            };
+
+            trace!(?user_id, "there is a user for this address");
+
+            if vout.value_sat > 0 {
+                deposits.push(Deposit {
+                    user_id,
+                    vout: vout.n,
+                    currency: native_currency.clone(),
+                    amount: Amount::from_sat(vout.value_sat),
+                });
+            }
+
+            // an output that can't be read is skipped, so it doesn't hold up the other deposits in the transaction.
+            for (currency_name, amount) in vout.script_pubkey.reserve_balance.iter().flatten() {
+                let Some(currency) = lookup_currency(client, currency_names, currency_name) else {
+                    error!(
+                        "unknown currency {currency_name} in {txid} vout {}, deposit skipped",
+                        vout.n
+                    );
+                    continue;
+                };
+
+                let Some(amount) = reserve_amount(amount) else {
+                    error!(
+                        "invalid amount {amount} of {currency_name} in {txid} vout {}, deposit skipped",
+                        vout.n
+                    );
+                    continue;
+                };
+
+                deposits.push(Deposit {
+                    user_id,
+                    vout: vout.n,
+                    currency,
+                    amount,
+                });
+            }
+        }
+    }
+
+    Ok(deposits)
+}
+
+// The daemon only reports reserve amounts in whole coins. The number is parsed as the decimal it was sent as,
+// a float would round some amounts down by a satoshi.
+pub(crate) fn reserve_amount(amount: &serde_json::Number) -> Option<Amount> {
+    Amount::from_str_in(&amount.to_string(), Denomination::Verus).ok()
+}
+
 // checks if a transaction id contains an output address that belongs to a discord user
-// if it exists, the balance of that user is increased
+// if it exists, the balance of that user is increased for every currency in that output
 // the transactions is stored in the database such that it doesn't get processed again
 // a dm is sent to the user afterwards
 
 pub async fn process_txid(
     http: Arc<Http>,
     pool: &PgPool,
-    raw_tx: &GetRawTransactionResultVerbose,
-    // _config: ?Settings,
+    client: &Client,
+    native_currency: &Currency,
+    currency_names: &CurrencyNames,
+    txid: &Txid,
 ) -> Result<(), Error> {
-    if !transaction_processed(&pool, &raw_tx.txid).await? {
-        for vout in raw_tx.vout.iter() {
-            if let Some(addresses) = &vout.script_pubkey.addresses {
-                for address in addresses {
-                    if let Some(user_id) = get_user_from_address(&pool, address).await? {
-                        let uuid = Uuid::new_v4();
-                        if let Err(e) = increase_balance(&pool, &user_id, vout.value_sat).await {
-                            error!("something went wrong while increasing a user's balance\nuser: {user_id} txid: {} vout: {} \nerror: {:?}", &raw_tx.txid, vout.n, e)
-                        } else {
-                            if let Err(e) =
-                                store_deposit_transaction(&pool, &uuid, &user_id, &raw_tx.txid)
-                                    .await
-                            {
-                                error!("something went wrong while storing a transaction to the database: {:?}", e)
-                            } else {
-                                send_deposit_dm(http.clone(), user_id, vout.value).await?;
-                            }
-                        }
-                    }
-                }
+    if !transaction_processed(&pool, txid).await? {
+        for deposit in get_deposits(client, pool, native_currency, currency_names, txid).await? {
+            let user_id = deposit.user_id;
+            let uuid = Uuid::new_v4();
+            if let Err(e) = process_deposit(
+                &pool,
+                &uuid,
+                &user_id,
+                &deposit.currency.id,
+                deposit.amount,
+                txid,
+            )
+            .await
+            {
+                error!("something went wrong while increasing a user's balance\nuser: {user_id} txid: {} vout: {} \nerror: {:?}", txid, deposit.vout, e)
             } else {
-                debug!("no addresses found in scriptpubkey");
+                if let Err(e) =
+                    store_deposit_transaction(&pool, &uuid, &user_id, &deposit.currency.id, txid)
+                        .await
+                {
+                    error!(
+                        "something went wrong while storing a transaction to the database: {:?}",
+                        e
+                    )
+                } else {
+                    send_deposit_dm(http.clone(), user_id, &deposit.currency, deposit.amount)
+                        .await?;
+                }
             }
         }
     } else {
@@ -360,12 +778,56 @@ pub async fn process_txid(
     Ok(())
 }
 
-async fn send_deposit_dm(http: Arc<Http>, user_id: UserId, amount: Amount) -> Result<(), Error> {
+async fn send_deposit_dm(
+    http: Arc<Http>,
+    user_id: UserId,
+    currency: &Currency,
+    amount: Amount,
+) -> Result<(), Error> {
     let user = http.get_user(user_id.0).await?;
     user.direct_message(http, |message| {
-        message.content(format!("Your deposit of {} has been processed.", amount))
+        message.content(format!(
+            "Your deposit of {} has been processed.",
+            currency.display(&amount)
+        ))
     })
     .await?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn native_movements_of_watched_address() {
+        let transaction: DepositTransaction = serde_json::from_value(serde_json::json!({
+            "vin": [
+                { "address": "RWatched", "valueSat": 500 },
+                { "address": "ROther", "valueSat": 100 },
+                { "coinbase": "03ab" }
+            ],
+            "vout": [
+                { "n": 0, "valueSat": 300, "scriptPubKey": { "addresses": ["ROther"] } },
+                { "n": 1, "valueSat": 200, "scriptPubKey": { "addresses": ["RWatched"] } },
+                { "n": 2, "valueSat": 0, "scriptPubKey": {} }
+            ]
+        }))
+        .unwrap();
+
+        assert_eq!(transaction.addresses(), vec!["ROther", "RWatched"]);
+        assert_eq!(transaction.native_movements("RWatched"), (200, 500));
+        assert_eq!(transaction.native_movements("RNone"), (0, 0));
+    }
+
+    #[test]
+    fn reserve_amounts_are_exact() {
+        let amount = |json: &str| reserve_amount(&serde_json::from_str(json).unwrap());
+
+        // 0.29 * 100_000_000 as a float is 28999999.999999996.
+        assert_eq!(amount("0.29"), Some(Amount::from_sat(29_000_000)));
+        assert_eq!(amount("1.00000001"), Some(Amount::from_sat(100_000_001)));
+        assert_eq!(amount("-1"), None);
+    }
+}
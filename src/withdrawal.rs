@@ -0,0 +1,396 @@
+use std::{fmt::Display, sync::Arc, time::Duration};
+
+use poise::serenity_prelude::{Http, UserId};
+use sqlx::PgPool;
+use tracing::{debug, error, info, trace, warn};
+use uuid::Uuid;
+use vrsc::{Address, Amount};
+use vrsc_rpc::{
+    bitcoin::Txid,
+    client::{Client, RpcApi, SendCurrencyOutput},
+};
+
+use crate::{
+    util::{
+        currency::{cached_currency, Currency, CurrencyNames},
+        database,
+        ledger::LedgerKind,
+    },
+    Error,
+};
+
+/// The state of a withdrawal in the outbox.
+///
+/// - queued: the amount and fee are reserved, sendcurrency has not been called yet.
+/// - broadcast: sendcurrency has been called. The opid is stored once the daemon returned it, the txid once the operation finished.
+/// - confirmed: the transaction has at least 1 confirmation, the reservation is settled.
+/// - failed: the operation failed, the reservation has been refunded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WithdrawalState {
+    Queued,
+    Broadcast,
+    Confirmed,
+    Failed,
+}
+
+impl Display for WithdrawalState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Queued => write!(f, "queued"),
+            Self::Broadcast => write!(f, "broadcast"),
+            Self::Confirmed => write!(f, "confirmed"),
+            Self::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+impl TryFrom<String> for WithdrawalState {
+    type Error = Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_ref() {
+            "queued" => Ok(WithdrawalState::Queued),
+            "broadcast" => Ok(WithdrawalState::Broadcast),
+            "confirmed" => Ok(WithdrawalState::Confirmed),
+            "failed" => Ok(WithdrawalState::Failed),
+            _ => Err(format!("unknown withdrawal state: {value}").into()),
+        }
+    }
+}
+
+/// Whether users withdraw to their own wallet, or pay someone else on-chain with `/send`.
+/// Both go through the same outbox, but are recorded separately in the ledger.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WithdrawalKind {
+    Withdrawal,
+    Send,
+}
+
+impl WithdrawalKind {
+    /// The ledger kind of the amount. The fee is always a `Fee`.
+    pub fn ledger_kind(&self) -> LedgerKind {
+        match self {
+            Self::Withdrawal => LedgerKind::Withdrawal,
+            Self::Send => LedgerKind::Send,
+        }
+    }
+}
+
+impl Display for WithdrawalKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Withdrawal => write!(f, "withdrawal"),
+            Self::Send => write!(f, "send"),
+        }
+    }
+}
+
+impl TryFrom<String> for WithdrawalKind {
+    type Error = Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_ref() {
+            "withdrawal" => Ok(WithdrawalKind::Withdrawal),
+            "send" => Ok(WithdrawalKind::Send),
+            _ => Err(format!("unknown withdrawal kind: {value}").into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Withdrawal {
+    pub id: Uuid,
+    pub user_id: UserId,
+    pub currency_id: Address,
+    pub amount: Amount,
+    pub fee_currency_id: Address,
+    pub fee: Amount,
+    pub destination: String,
+    pub kind: WithdrawalKind,
+    pub status: WithdrawalState,
+    pub opid: Option<String>,
+    pub txid: Option<Txid>,
+}
+
+/// Everything the withdrawal worker needs to send withdrawals.
+#[derive(Debug)]
+pub struct WithdrawalProcessor {
+    pub http: Arc<Http>,
+    pub pool: PgPool,
+    pub client: Client,
+    pub native_currency: Currency,
+    pub currency_names: CurrencyNames,
+    pub testnet: bool,
+}
+
+impl WithdrawalProcessor {
+    /// Advances every withdrawal that is not finished yet. Called periodically and on startup, so withdrawals that were
+    /// interrupted by a restart are resumed.
+    ///
+    /// A failing withdrawal does not stop the others from being processed.
+    pub async fn process_withdrawals(&self) -> Result<(), Error> {
+        let withdrawals = database::get_unfinished_withdrawals(&self.pool).await?;
+        debug!("number of unfinished withdrawals: {}", withdrawals.len());
+
+        for withdrawal in withdrawals {
+            let withdrawal = match withdrawal {
+                Ok(withdrawal) => withdrawal,
+                Err(e) => {
+                    error!("could not read withdrawal: {e:?}");
+                    continue;
+                }
+            };
+
+            match self.advance(&withdrawal).await {
+                Ok(WithdrawalState::Failed) => {
+                    if let Err(e) = self.send_refund_dm(&withdrawal).await {
+                        warn!(
+                            "could not notify {} of a failed withdrawal: {e:?}",
+                            withdrawal.user_id
+                        );
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => error!("could not process withdrawal {}: {e:?}", withdrawal.id),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Moves a withdrawal to its next state, if it can. Returns the state the withdrawal is in afterwards.
+    ///
+    /// Only the withdrawal worker calls this, so a withdrawal is never advanced twice at the same time.
+    async fn advance(&self, withdrawal: &Withdrawal) -> Result<WithdrawalState, Error> {
+        match (withdrawal.status, &withdrawal.opid, &withdrawal.txid) {
+            (WithdrawalState::Queued, _, _) => self.broadcast(withdrawal).await,
+            (WithdrawalState::Broadcast, None, _) => {
+                // sendcurrency was called, but the bot stopped before the opid was stored.
+                // Only an admin can tell if the withdrawal was sent, see `resolvewithdrawal`.
+                warn!(
+                    "withdrawal {} was broadcast without an opid, it needs to be resolved manually",
+                    withdrawal.id
+                );
+
+                Ok(WithdrawalState::Broadcast)
+            }
+            (WithdrawalState::Broadcast, Some(opid), None) => {
+                self.check_operation(withdrawal, opid).await
+            }
+            (WithdrawalState::Broadcast, Some(_), Some(txid)) => {
+                self.check_confirmations(withdrawal, txid).await
+            }
+            (state, _, _) => Ok(state),
+        }
+    }
+
+    /// Waits until the withdrawal worker found the txid of a withdrawal, the withdrawal failed or the timeout passed.
+    /// Returns the withdrawal as it is stored afterwards.
+    pub async fn wait_for_txid(&self, id: &Uuid, timeout: Duration) -> Result<Withdrawal, Error> {
+        let start = std::time::Instant::now();
+
+        loop {
+            let Some(withdrawal) = database::get_withdrawal(&self.pool, id).await? else {
+                return Err(format!("withdrawal {id} does not exist").into());
+            };
+
+            if withdrawal.txid.is_some()
+                || [WithdrawalState::Confirmed, WithdrawalState::Failed]
+                    .contains(&withdrawal.status)
+                || start.elapsed() > timeout
+            {
+                return Ok(withdrawal);
+            }
+
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+    }
+
+    async fn broadcast(&self, withdrawal: &Withdrawal) -> Result<WithdrawalState, Error> {
+        if !database::claim_withdrawal(&self.pool, &withdrawal.id).await? {
+            trace!("withdrawal {} was already claimed", withdrawal.id);
+
+            return Ok(WithdrawalState::Broadcast);
+        }
+
+        let sendcurrency_currency =
+            sendcurrency_currency(self.testnet, &self.native_currency, &withdrawal.currency_id);
+        let sco = SendCurrencyOutput::new(
+            sendcurrency_currency.as_deref(),
+            &withdrawal.amount,
+            &withdrawal.destination,
+        );
+
+        match self.client.send_currency("*", vec![sco], None, None) {
+            Ok(opid) => {
+                debug!("sendcurrency opid: {:?}", &opid);
+                database::set_withdrawal_opid(&self.pool, &withdrawal.id, &opid).await?;
+
+                Ok(WithdrawalState::Broadcast)
+            }
+            Err(e) => {
+                // the daemon refused the sendcurrency, nothing was sent.
+                error!(
+                    "sendcurrency for withdrawal {} failed: {e:?}",
+                    withdrawal.id
+                );
+                database::fail_withdrawal(&self.pool, withdrawal, &e.to_string()).await?;
+
+                Ok(WithdrawalState::Failed)
+            }
+        }
+    }
+
+    // Sendcurrency works with op-ids because it can work with zk-transactions. Therefore the txid of a transactions is not always known directly after sending.
+    // from https://buildmedia.readthedocs.org/media/pdf/zcash/english-docs/zcash.pdf
+    // status can be one of queued, executing, failed or success.
+    async fn check_operation(
+        &self,
+        withdrawal: &Withdrawal,
+        opid: &str,
+    ) -> Result<WithdrawalState, Error> {
+        trace!("getting operation status: {}", &opid);
+        let operation_status = self.client.z_get_operation_status(vec![opid])?;
+        trace!("got operation status: {:?}", &operation_status);
+
+        let Some(Some(opstatus)) = operation_status.first() else {
+            // the daemon forgets operations when it restarts.
+            warn!(
+                "there was NO operation_status for withdrawal {}",
+                withdrawal.id
+            );
+
+            return Ok(WithdrawalState::Broadcast);
+        };
+
+        if ["queued", "executing"].contains(&opstatus.status.as_ref()) {
+            trace!("opid still executing");
+
+            return Ok(WithdrawalState::Broadcast);
+        }
+
+        // the params are what sendcurrency was called with. If the daemon leaves them out, the withdrawal has the same values.
+        let (address, amount, currency) = match opstatus.params.first().and_then(Option::as_ref) {
+            Some(params) => (
+                params.address.clone(),
+                params.amount,
+                params
+                    .currency
+                    .clone()
+                    .unwrap_or_else(|| String::from("VRSC")),
+            ),
+            None => {
+                warn!("operation {opid} has no params");
+
+                (
+                    withdrawal.destination.clone(),
+                    withdrawal.amount.as_vrsc(),
+                    withdrawal.currency_id.to_string(),
+                )
+            }
+        };
+
+        // the operation and the txid are stored together, so a crash in between can't leave the withdrawal without its txid.
+        let mut tx = self.pool.begin().await?;
+        database::store_opid(
+            &mut tx,
+            opid,
+            &opstatus.status,
+            opstatus.creation_time as i64,
+            opstatus.result.as_ref().map(|txid| txid.txid),
+            &address,
+            amount,
+            &currency,
+        )
+        .await?;
+
+        if let Some(txid) = &opstatus.result {
+            trace!(
+                "operation was executed with status: {}, txid: {}",
+                opstatus.status,
+                txid.txid
+            );
+
+            if database::set_withdrawal_txid(&mut tx, &withdrawal.id, &txid.txid).await? {
+                // at this point the txid is known. Now blockchain shenanigans could be happening, so we should store everything in the transactions_db table
+                database::store_withdraw_transaction(
+                    &mut tx,
+                    &withdrawal.id,
+                    &withdrawal.user_id,
+                    &withdrawal.currency_id,
+                    Some(&txid.txid),
+                    opid,
+                    &withdrawal.fee,
+                )
+                .await?;
+            }
+            tx.commit().await?;
+
+            Ok(WithdrawalState::Broadcast)
+        } else {
+            tx.commit().await?;
+
+            error!("execution failed with status: {}", opstatus.status);
+            database::fail_withdrawal(&self.pool, withdrawal, &opstatus.status).await?;
+
+            Ok(WithdrawalState::Failed)
+        }
+    }
+
+    async fn check_confirmations(
+        &self,
+        withdrawal: &Withdrawal,
+        txid: &Txid,
+    ) -> Result<WithdrawalState, Error> {
+        let raw_tx = self.client.get_raw_transaction_verbose(txid)?;
+
+        match raw_tx.confirmations {
+            Some(confs) if confs > 0 => {
+                database::confirm_withdrawal(&self.pool, withdrawal).await?;
+                info!("withdrawal {} confirmed in {txid}", withdrawal.id);
+
+                Ok(WithdrawalState::Confirmed)
+            }
+            _ => {
+                trace!("{txid} has no confirmations yet");
+
+                Ok(WithdrawalState::Broadcast)
+            }
+        }
+    }
+
+    async fn send_refund_dm(&self, withdrawal: &Withdrawal) -> Result<(), Error> {
+        let currency = cached_currency(&self.currency_names, &withdrawal.currency_id);
+
+        let user = self.http.get_user(withdrawal.user_id.0).await?;
+        user.direct_message(&self.http, |message| {
+            message.content(format!(
+                "Your {} of {} to {} (ID: {}) failed. The amount and the fee have been returned to your balance.",
+                withdrawal.kind,
+                currency.display(&withdrawal.amount),
+                withdrawal.destination,
+                withdrawal.id
+            ))
+        })
+        .await?;
+
+        Ok(())
+    }
+}
+
+/// Returns the value for the currency parameter of sendcurrency.
+pub fn sendcurrency_currency(
+    testnet: bool,
+    native_currency: &Currency,
+    currency_id: &Address,
+) -> Option<String> {
+    if currency_id == &native_currency.id {
+        // until PBaaS releases on mainnet, we should not use a value for currency for "VRSC" withdrawals as there will be a daemon error
+        match testnet {
+            true => Some(String::from("vrsctest")),
+            false => None,
+        }
+    } else {
+        Some(currency_id.to_string())
+    }
+}